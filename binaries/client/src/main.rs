@@ -1,8 +1,19 @@
 extern crate tungsten;
 extern crate tungsten_glium2d;
+extern crate tungsten_audio;
 
-use tungsten::{Framework, EventDispatcher, UpdateEvent};
-use tungsten_glium2d::{Frontend2D, CloseRequestEvent, FrameRenderInfo, KeyboardInputEvent, Key, KeyState, RenderTarget, Rectangle, View2D, TextureId};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tungsten::{Framework, EventDispatcher, UpdateEvent, AssetManager, AssetSource, Archive, AssetLoadedEvent, AssetProgressEvent, Handle, LevelAssets};
+use tungsten_glium2d::{Texture, Frontend2D, CloseRequestEvent, FrameRenderInfo, KeyboardInputEvent, Key, KeyState, RenderTarget, Rectangle, View2D, TextureId, TileMap, TileMapId, BackgroundInfo, BackgroundFill, TextureOptions, FrontendErrorEvent, Input, InputMap, FocusEvent, MinimizeEvent, DebugDraw, InputStateEvent, TextInputEvent, WindowResizedEvent, Ui, Skin, Direction, GamepadButton, GamepadButtonEvent};
+#[cfg(feature = "gamepads")]
+use tungsten_glium2d::GilrsGamepads;
+use tungsten_audio::{Audio, AudioBackend, BusId, PlayOptions, Sound};
+#[cfg(feature = "audio")]
+use tungsten_audio::CpalBackend;
+#[cfg(not(feature = "audio"))]
+use tungsten_audio::NullBackend;
 
 enum BirdState {
     Alive,
@@ -11,26 +22,59 @@ enum BirdState {
 
 struct GameModel {
     should_close: bool,
+    focused: bool,
+    minimized: bool,
+    loading: bool,
     bird_height: f32,
     bird_velocity: f32,
     bird_state: BirdState,
     camera_distance: f32,
+    controls: InputMap,
+    debug: DebugDraw,
+    audio: Audio<Box<AudioBackend>>,
+    effects: BusId,
+    flap: Handle<Sound>,
+    ui: Ui,
 }
 
 impl GameModel {
-    fn new() -> Self {
+    fn new(
+        controls: InputMap, debug: DebugDraw, skin: Skin, mut audio: Audio<Box<AudioBackend>>,
+        flap: Handle<Sound>
+    ) -> Self {
+        let effects = audio.create_bus("effects");
+
         GameModel {
             should_close: false,
+            focused: true,
+            minimized: false,
+            loading: false,
             bird_height: 64.0,
             bird_velocity: 0.0,
             bird_state: BirdState::Alive,
             camera_distance: 0.0,
+            controls: controls,
+            debug: debug,
+            audio: audio,
+            effects: effects,
+            flap: flap,
+            ui: Ui::new(skin),
         }
     }
 
     fn update(&mut self, delta: f32) {
+        if self.is_paused() || self.loading {
+            return;
+        }
+
+        // Sounds that fail to play are stopped, the game goes on without them
+        if let Err(e) = self.audio.update(delta) {
+            eprintln!("Audio error: {}", e);
+        }
+
         // Advance the game state
         self.camera_distance += 32.0 * 6.0 * delta;
+        self.audio.set_listener_position([self.camera_distance, 0.0]);
 
         // Different paths for the state of the bird
         match self.bird_state {
@@ -43,6 +87,17 @@ impl GameModel {
                 if self.bird_height < -300.0 {
                     self.bird_state = BirdState::Dead(self.camera_distance);
                 }
+
+                // Show where the bird is heading, toggled with F3
+                let position = [self.camera_distance, self.bird_height];
+                let velocity = [32.0 * 6.0, self.bird_velocity];
+                let heading = [position[0] + velocity[0] * 0.25, position[1] + velocity[1] * 0.25];
+                self.debug.box_outline(position, [64.0, 64.0], [0.0, 1.0, 0.0, 1.0]);
+                self.debug.arrow(position, heading, [1.0, 0.0, 0.0, 1.0]);
+                self.debug.text(
+                    [position[0] - 32.0, position[1] + 40.0], 10.0,
+                    &format!("{:.0}", self.bird_height), [1.0, 1.0, 1.0, 1.0]
+                );
             },
             BirdState::Dead(_distance) => {
                 // It's dead, nothing happens
@@ -52,6 +107,11 @@ impl GameModel {
 
     fn launch_bird(&mut self) {
         self.bird_velocity = 32.0 * 18.0;
+
+        if let Some(flap) = self.flap.get() {
+            let position = [self.camera_distance, self.bird_height];
+            self.audio.play(&flap, PlayOptions::at(self.effects, position));
+        }
     }
 
     fn reset_game(&mut self) {
@@ -61,6 +121,32 @@ impl GameModel {
         self.camera_distance = 0.0;
     }
 
+    /// Builds this frame's UI, the menu is only there while the bird is dead.
+    fn build_ui(&mut self) {
+        let mut retry = false;
+
+        self.ui.begin();
+        if let BirdState::Dead(_) = self.bird_state {
+            self.ui.begin_panel("death", [0.0, -180.0], Direction::Horizontal);
+            retry = self.ui.button("Retry");
+            if self.ui.button("Quit") {
+                self.close();
+            }
+            self.ui.end_panel();
+        }
+        self.ui.end();
+
+        if retry {
+            self.reset_game();
+        }
+    }
+
+    /// Don't let the bird fall to its death while the player is doing something else. A window
+    /// can be minimized without losing focus, so both are checked.
+    fn is_paused(&self) -> bool {
+        !self.focused || self.minimized
+    }
+
     fn close(&mut self) {
         self.should_close = true;
     }
@@ -70,39 +156,198 @@ impl GameModel {
     }
 }
 
+/// Where the player's controls are kept, in their own config directory so they can be changed
+/// without touching the game's assets.
+fn user_controls_path() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|config| config.join("tungsten").join("controls.json"))
+}
+
+/// Loads the player's controls. The first time the game is started these don't exist yet, so the
+/// controls shipped with the assets are used and saved for the player to change.
+fn load_controls(source: &AssetSource) -> InputMap {
+    let user_path = user_controls_path();
+    if let Some(ref path) = user_path {
+        if path.exists() {
+            match InputMap::load(path) {
+                Ok(controls) => return controls,
+                // Don't overwrite the player's file, they may want to fix it
+                Err(e) => {
+                    eprintln!("Using default controls: {}", e);
+                    return default_controls();
+                },
+            }
+        }
+    }
+
+    let controls = source.read(Path::new("controls.json"))
+        .and_then(|data| String::from_utf8(data).map_err(|e| e.to_string()))
+        .and_then(|data| InputMap::from_json(&data))
+        .unwrap_or_else(|e| {
+            eprintln!("Using default controls: {}", e);
+            default_controls()
+        });
+
+    if let Some(path) = user_path {
+        let saved = fs::create_dir_all(path.parent().unwrap())
+            .map_err(|e| format!("Could not create {}: {}", path.parent().unwrap().display(), e))
+            .and_then(|_| controls.save(&path));
+        if let Err(e) = saved {
+            eprintln!("Could not save controls: {}", e);
+        }
+    }
+
+    controls
+}
+
+fn default_controls() -> InputMap {
+    let mut controls = InputMap::new();
+    controls.bind("launch", Input::Key(Key::W));
+    controls.bind("launch", Input::Gamepad(GamepadButton::South));
+    controls.bind("reset", Input::Key(Key::R));
+    controls.bind("quit", Input::Key(Key::Escape));
+    controls
+}
+
 fn close_request_handler(model: &mut GameModel, _event: &CloseRequestEvent) {
     model.close();
 }
 
+fn frontend_error_handler(model: &mut GameModel, event: &FrontendErrorEvent) {
+    eprintln!("Frontend error: {}", event.error);
+
+    // Nothing will be rendered anymore, so there's no point in keeping the game running
+    if event.error.is_fatal() {
+        model.close();
+    }
+}
+
+fn focus_handler(model: &mut GameModel, event: &FocusEvent) {
+    model.focused = event.focused;
+}
+
+fn minimize_handler(model: &mut GameModel, event: &MinimizeEvent) {
+    model.minimized = event.minimized;
+}
+
+fn ui_handler(model: &mut GameModel, event: &InputStateEvent) {
+    model.ui.handle_input(&event.state);
+}
+
+fn text_input_handler(model: &mut GameModel, event: &TextInputEvent) {
+    model.ui.text_input(event.character);
+}
+
+fn resize_handler(model: &mut GameModel, event: &WindowResizedEvent) {
+    model.ui.set_window_size(event.size);
+}
+
+fn asset_loaded_handler(_model: &mut GameModel, event: &AssetLoadedEvent) {
+    // Missing assets are drawn plain white, the game can still be played
+    if let Some(ref error) = event.error {
+        eprintln!("Could not load {} {}: {}", event.kind, event.path.display(), error);
+    }
+}
+
+fn asset_progress_handler(model: &mut GameModel, event: &AssetProgressEvent) {
+    // The game starts once everything has been loaded
+    model.loading = !event.progress.is_done();
+    if event.progress.is_done() {
+        println!("Loaded {} of {} assets", event.progress.loaded, event.progress.total);
+    }
+}
+
 fn update_handler(model: &mut GameModel, event: &UpdateEvent) {
+    model.build_ui();
     model.update(event.delta);
 }
 
 fn keyboard_handler(model: &mut GameModel, event: &KeyboardInputEvent) {
+    if let (KeyState::Pressed, Some(key)) = (event.state, event.key) {
+        input_pressed(model, Input::Key(key));
+    }
+}
+
+fn gamepad_handler(model: &mut GameModel, event: &GamepadButtonEvent) {
     if event.state == KeyState::Pressed {
-        match event.key {
-            Key::W => model.launch_bird(),
-            Key::R => model.reset_game(),
-            Key::Escape => model.close(),
+        input_pressed(model, Input::Gamepad(event.button));
+    }
+}
+
+fn input_pressed(model: &mut GameModel, input: Input) {
+    let actions: Vec<String> = model.controls.actions(input)
+        .iter().map(|a| a.to_string()).collect();
+
+    for action in actions {
+        match &action[..] {
+            "launch" => model.launch_bird(),
+            "reset" => model.reset_game(),
+            "quit" => model.close(),
             _ => ()
         }
     }
 }
 
+#[cfg(feature = "audio")]
+fn open_audio() -> Box<AudioBackend> {
+    match CpalBackend::new() {
+        Ok(backend) => Box::new(backend),
+        Err(e) => {
+            eprintln!("Playing without sound: {}", e);
+            Box::new(NullBackend::new(44100))
+        }
+    }
+}
+
+#[cfg(not(feature = "audio"))]
+fn open_audio() -> Box<AudioBackend> {
+    Box::new(NullBackend::new(44100))
+}
+
+#[cfg(feature = "gamepads")]
+fn add_gamepads(frontend: &mut Frontend2D<GameModel>) {
+    match GilrsGamepads::new() {
+        Ok(gamepads) => frontend.set_gamepad_backend(gamepads),
+        Err(e) => eprintln!("Playing without gamepads: {}", e),
+    }
+}
+
+#[cfg(not(feature = "gamepads"))]
+fn add_gamepads(_frontend: &mut Frontend2D<GameModel>) {
+}
+
 struct View {
-    bird: TextureId,
-    ground: TextureId,
-    youdied: TextureId,
+    _level: Option<LevelAssets>,
+    white: TextureId,
+    star: Handle<Texture>,
+    bird: Handle<Texture>,
+    ground: TileMapId,
+    youdied: Handle<Texture>,
 }
 
 impl View {
-    fn new(frontend: &mut Frontend2D<GameModel>) -> Self {
-        // Load in textures
-        let bird = frontend.load_texture("./assets/bird.png");
-        let ground = frontend.load_texture("./assets/grass.png");
-        let youdied = frontend.load_texture("./assets/youdied.png");
+    fn new(frontend: &mut Frontend2D<GameModel>, assets: &AssetManager, level: Option<LevelAssets>) -> Self {
+        // These give back the level's textures, which may still be loading
+        let star = assets.load("texture", "star.png");
+        let bird = assets.load("pixel_texture", "bird.png");
+        let youdied = assets.load("texture", "youdied.png");
+
+        // The tilemap needs its texture right away, so it's loaded directly
+        let ground = frontend.load_texture("grass.png", TextureOptions::pixel_art());
+
+        // Create the ground strip
+        let mut ground_map = TileMap::new(ground, 1, [64.0, 64.0], [110, 1]);
+        for i in 0..110 {
+            ground_map.set([i, 0], Some(0));
+        }
+        let ground = frontend.create_tilemap(ground_map);
 
         View {
+            _level: level,
+            white: frontend.white_texture(),
+            star: star,
             bird: bird,
             ground: ground,
             youdied: youdied,
@@ -111,65 +356,129 @@ impl View {
 
     fn render_world(&self, model: &GameModel, info: &mut FrameRenderInfo) {
         let camera = info.game_camera([model.camera_distance, 0.0]);
-        let batch = camera.batch();
+
+        // Draw the starfield, far away stars first
+        camera.background(self.stars(0.1, 1, [4.0, 8.0]));
+        camera.background(self.stars(0.3, 2, [8.0, 16.0]));
 
         // Draw the terrain
-        for i in -10..100 {
-            let rect = Rectangle {
-                position: [i as f32 * 64.0, -720.0/2.0],
-                size: [64.0, 64.0],
-                texture: self.ground,
-            };
-            batch.rectangle(rect);
-        }
+        camera.tilemap(self.ground, [-10.0 * 64.0 - 32.0, -720.0/2.0 - 32.0]);
+        let batch = camera.batch();
 
         // Draw the bird
         let dist = {
             if let BirdState::Dead(dist) = model.bird_state { dist }
             else { model.camera_distance }
         };
-        let rect = Rectangle {
-            position: [dist, model.bird_height],
-            size: [64.0, 64.0],
-            texture: self.bird,
-        };
+        let rect = Rectangle::new([dist, model.bird_height], [64.0, 64.0], self.texture(&self.bird));
         batch.rectangle(rect);
     }
 
+    fn stars(&self, parallax: f32, seed: u32, size: [f32; 2]) -> BackgroundInfo {
+        BackgroundInfo::new(parallax, BackgroundFill::Scatter {
+            textures: vec!(self.texture(&self.star)),
+            cell_size: 64.0,
+            density: 0.2,
+            size: size,
+            seed: seed,
+        })
+    }
+
     fn render_ui(&self, model: &GameModel, info: &mut FrameRenderInfo) {
         let camera = info.game_camera([0.0, 0.0]);
-        let batch = camera.batch();
 
         if let BirdState::Dead(_) = model.bird_state {
-            let rect = Rectangle {
-                position: [0.0, 0.0],
-                size: [256.0, 256.0],
-                texture: self.youdied,
-            };
-            batch.rectangle(rect);
+            let rect = Rectangle::new([0.0, 0.0], [256.0, 256.0], self.texture(&self.youdied));
+            camera.batch().rectangle(rect);
         }
+
+        model.ui.render(camera);
+    }
+
+    /// The texture of a handle, or white if it's still loading or failed.
+    fn texture(&self, handle: &Handle<Texture>) -> TextureId {
+        handle.get().map(|t| t.id).unwrap_or(self.white)
     }
 }
 
 impl View2D<GameModel> for View {
     fn render(&mut self, model: &GameModel, info: &mut FrameRenderInfo) {
+        // Nothing to show until the level's textures are there
+        if model.loading {
+            return;
+        }
+
         self.render_world(model, info);
         self.render_ui(model, info);
     }
 }
 
 fn main() {
-    let model = GameModel::new();
-
     let mut event_dispatcher = EventDispatcher::new();
     event_dispatcher.add_handler(close_request_handler);
+    event_dispatcher.add_handler(frontend_error_handler);
+    event_dispatcher.add_handler(focus_handler);
+    event_dispatcher.add_handler(minimize_handler);
+    event_dispatcher.add_handler(ui_handler);
+    event_dispatcher.add_handler(text_input_handler);
+    event_dispatcher.add_handler(resize_handler);
+    event_dispatcher.add_handler(asset_loaded_handler);
+    event_dispatcher.add_handler(asset_progress_handler);
     event_dispatcher.add_handler(update_handler);
     event_dispatcher.add_handler(keyboard_handler);
+    event_dispatcher.add_handler(gamepad_handler);
+
+    // Running headless renders on the CPU, for machines without OpenGL
+    let mut frontend = if env::args().any(|a| a == "--headless") {
+        Frontend2D::headless(1280, 720)
+    } else {
+        Frontend2D::new()
+    };
+    add_gamepads(&mut frontend);
+
+    // The UI reads the input from the model, since handlers can't reach the frontend
+    frontend.set_input_state_events(true);
+
+    // The assets directory is found near the executable, unless it's given with --assets
+    let args: Vec<String> = env::args().collect();
+    if let Some(index) = args.iter().position(|a| a == "--assets") {
+        match args.get(index + 1) {
+            Some(root) => frontend.set_asset_root(root.clone()),
+            None => eprintln!("Missing directory after --assets"),
+        }
+    }
+
+    // Load the textures on the loading thread. Packed assets next to the assets directory are
+    // used if there are any, loose files still override them.
+    let mut source = AssetSource::new(frontend.asset_root());
+    let archive_path = frontend.asset_root().with_extension("pack");
+    if archive_path.exists() {
+        match Archive::open(&archive_path) {
+            Ok(archive) => source.add_archive(archive),
+            Err(e) => eprintln!("Could not open {}: {}", archive_path.display(), e),
+        }
+    }
+    frontend.set_asset_source(source.clone());
+    let controls = load_controls(&source);
+    let assets = AssetManager::with_source(source);
+    assets.add_loader("texture", frontend.texture_loader(TextureOptions::default()));
+    assets.add_loader("pixel_texture", frontend.texture_loader(TextureOptions::pixel_art()));
+    assets.add_loader("sound", |_: &Path, data: Vec<u8>| {
+        Sound::from_wav(&data).map_err(|e| e.to_string())
+    });
+    let level = assets.load_manifest("levels.manifest")
+        .and_then(|manifest| assets.load_level(&manifest, "game"))
+        .map_err(|e| eprintln!("Could not load the level: {}", e))
+        .ok();
 
-    let mut frontend = Frontend2D::new();
-    let view = View::new(&mut frontend);
+    let view = View::new(&mut frontend, &assets, level);
     frontend.set_view(view);
+    let skin = Skin::plain(frontend.white_texture());
+    let flap = assets.load("sound", "flap.wav");
+    let mut model = GameModel::new(controls, frontend.debug_draw(), skin, Audio::new(open_audio()), flap);
+    model.loading = !assets.is_done();
 
-    let framework = Framework::new(model, frontend, event_dispatcher);
+    let mut framework = Framework::new(model, frontend, event_dispatcher);
+    framework.add_event_source(assets);
     framework.run(|model| model.keep_running());
 }
@@ -0,0 +1,75 @@
+extern crate tungsten;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process;
+use tungsten::{ArchiveWriter, Compression};
+
+const USAGE: &'static str = "Usage: packer <asset directory> <archive> [--store]
+
+Packs every file in the asset directory into a single archive. Files are compressed unless
+--store is given, or compressing doesn't make them smaller.";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let store = args.iter().any(|a| a == "--store");
+    let paths: Vec<&String> = args.iter().filter(|a| !a.starts_with("--")).collect();
+    if paths.len() != 2 {
+        eprintln!("{}", USAGE);
+        process::exit(1);
+    }
+
+    let compression = if store { Compression::Stored } else { Compression::Deflate };
+    if let Err(e) = pack(Path::new(paths[0]), Path::new(paths[1]), compression) {
+        eprintln!("Could not pack assets: {}", e);
+        process::exit(1);
+    }
+}
+
+fn pack(directory: &Path, archive: &Path, compression: Compression) -> Result<(), String> {
+    let mut files = Vec::new();
+    try!(find_files(directory, &mut files).map_err(|e| format!("{}: {}", directory.display(), e)));
+
+    // Sorted so packing the same files always gives the same archive
+    files.sort();
+
+    let output = try!(File::create(archive).map_err(|e| format!("{}: {}", archive.display(), e)));
+    let mut writer = try!(ArchiveWriter::new(output).map_err(|e| e.to_string()));
+    let mut total = [0, 0];
+    for file in files {
+        // Don't pack the archive into itself if it's in the directory
+        if fs::canonicalize(&file).ok() == fs::canonicalize(archive).ok() {
+            continue;
+        }
+
+        let mut data = Vec::new();
+        try!(File::open(&file)
+            .and_then(|mut f| f.read_to_end(&mut data))
+            .map_err(|e| format!("{}: {}", file.display(), e)));
+
+        let relative = file.strip_prefix(directory).unwrap();
+        try!(writer.add(relative, &data, compression).map_err(|e| e.to_string()));
+        println!("Packed {} ({} bytes)", relative.display(), data.len());
+        total[0] += 1;
+        total[1] += data.len();
+    }
+
+    let output = try!(writer.finish().map_err(|e| e.to_string()));
+    let size = try!(output.metadata().map_err(|e| e.to_string())).len();
+    println!("Packed {} files, {} bytes into {} bytes", total[0], total[1], size);
+    Ok(())
+}
+
+fn find_files(directory: &Path, files: &mut Vec<PathBuf>) -> ::std::io::Result<()> {
+    for entry in try!(fs::read_dir(directory)) {
+        let path = try!(entry).path();
+        if path.is_dir() {
+            try!(find_files(&path, files));
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,446 @@
+use std::path::Path;
+use backend::AudioBackend;
+use error::AudioError;
+use sound::Sound;
+use stream::{self, SoundStream};
+
+/// How many frames are decoded from a stream at once.
+const STREAM_CHUNK_FRAMES: usize = 4096;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BusId {
+    id: usize
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VoiceId {
+    id: u64
+}
+
+/// How a sound should be played.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PlayOptions {
+    pub bus: BusId,
+    pub volume: f32,
+    /// Where the sound is in the world, sounds without a position play at the listener.
+    pub position: Option<[f32; 2]>,
+    pub looping: bool,
+}
+
+impl PlayOptions {
+    pub fn new(bus: BusId) -> Self {
+        PlayOptions {
+            bus: bus,
+            volume: 1.0,
+            position: None,
+            looping: false,
+        }
+    }
+
+    /// Options for a sound at a position in the world, panned and faded relative to the listener.
+    pub fn at(bus: BusId, position: [f32; 2]) -> Self {
+        let mut options = Self::new(bus);
+        options.position = Some(position);
+        options
+    }
+}
+
+/// Where sounds are heard from, usually kept at the position of the game camera.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Listener {
+    pub position: [f32; 2],
+    /// How far to the side a sound has to be to play only on one speaker.
+    pub pan_distance: f32,
+    /// How far away a sound has to be to not be heard anymore, it fades out linearly until then.
+    pub max_distance: f32,
+}
+
+impl Default for Listener {
+    fn default() -> Self {
+        Listener {
+            position: [0.0, 0.0],
+            pan_distance: 640.0,
+            max_distance: 2000.0,
+        }
+    }
+}
+
+struct Bus {
+    name: String,
+    volume: f32,
+}
+
+struct StreamSource {
+    stream: Box<SoundStream>,
+    buffer: Vec<f32>,
+    ended: bool,
+}
+
+impl StreamSource {
+    fn frames(&self) -> usize {
+        self.buffer.len() / self.stream.channels() as usize
+    }
+
+    fn frame(&self, frame: usize) -> [f32; 2] {
+        if self.stream.channels() == 1 {
+            [self.buffer[frame], self.buffer[frame]]
+        } else {
+            [self.buffer[frame * 2], self.buffer[frame * 2 + 1]]
+        }
+    }
+
+    /// Decodes more of the stream, starting over at the end if looping.
+    fn fill(&mut self, looping: bool) -> Result<(), AudioError> {
+        let read = try!(self.stream.read(&mut self.buffer, STREAM_CHUNK_FRAMES));
+        if read != 0 {
+            return Ok(());
+        }
+
+        if looping {
+            try!(self.stream.rewind());
+
+            // An empty stream would loop forever without ever giving us anything
+            if try!(self.stream.read(&mut self.buffer, STREAM_CHUNK_FRAMES)) != 0 {
+                return Ok(());
+            }
+        }
+        self.ended = true;
+        Ok(())
+    }
+
+    /// Throws away frames that have been played.
+    fn discard(&mut self, frames: usize) {
+        let samples = frames * self.stream.channels() as usize;
+        self.buffer.drain(..samples);
+    }
+}
+
+enum Source {
+    Sound(Sound),
+    Stream(StreamSource),
+}
+
+struct Voice {
+    id: VoiceId,
+    source: Source,
+    options: PlayOptions,
+    /// The position in the source in frames, between frames when resampling.
+    cursor: f64,
+}
+
+impl Voice {
+    /// Returns the next frame of the voice as left and right, or None once it's done.
+    fn next_frame(&mut self, output_rate: u32) -> Result<Option<[f32; 2]>, AudioError> {
+        let looping = self.options.looping;
+
+        let (left, right, sample_rate) = match self.source {
+            Source::Sound(ref sound) => {
+                let frames = sound.frames();
+                if self.cursor >= frames as f64 {
+                    if looping && frames != 0 {
+                        self.cursor %= frames as f64;
+                    } else {
+                        return Ok(None);
+                    }
+                }
+
+                let index = self.cursor as usize;
+                let next = if index + 1 < frames { index + 1 } else if looping { 0 } else { index };
+                (sound.frame(index), sound.frame(next), sound.sample_rate())
+            },
+            Source::Stream(ref mut source) => {
+                // Make sure we have the frame after this one too, to interpolate with
+                while !source.ended && source.frames() <= self.cursor as usize + 1 {
+                    try!(source.fill(looping));
+                }
+                if self.cursor as usize >= source.frames() {
+                    return Ok(None);
+                }
+
+                // Drop what we've played now and then, so the buffer doesn't keep growing
+                if self.cursor as usize >= STREAM_CHUNK_FRAMES {
+                    source.discard(STREAM_CHUNK_FRAMES);
+                    self.cursor -= STREAM_CHUNK_FRAMES as f64;
+                }
+
+                let index = self.cursor as usize;
+                let next = (index + 1).min(source.frames() - 1);
+                (source.frame(index), source.frame(next), source.stream.sample_rate())
+            },
+        };
+
+        // Linearly interpolate between frames, sounds don't have to match the output rate
+        let factor = (self.cursor - self.cursor.floor()) as f32;
+        self.cursor += sample_rate as f64 / output_rate as f64;
+        Ok(Some([
+            left[0] + (right[0] - left[0]) * factor,
+            left[1] + (right[1] - left[1]) * factor,
+        ]))
+    }
+}
+
+/// Plays sounds and music, mixing them into a backend.
+///
+/// Keep this in the model and call `update` every update tick with the time that passed, that's
+/// when audio is mixed and given to the backend.
+pub struct Audio<B> {
+    backend: B,
+    buses: Vec<Bus>,
+    master_volume: f32,
+    listener: Listener,
+    voices: Vec<Voice>,
+    next_voice: u64,
+    /// Frames that should have been mixed but were less than a whole frame.
+    pending_frames: f64,
+}
+
+impl<B: AudioBackend> Audio<B> {
+    pub fn new(backend: B) -> Self {
+        Audio {
+            backend: backend,
+            buses: Vec::new(),
+            master_volume: 1.0,
+            listener: Listener::default(),
+            voices: Vec::new(),
+            next_voice: 0,
+            pending_frames: 0.0,
+        }
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    /// Creates a bus to group sounds under, so their volume can be changed together.
+    pub fn create_bus(&mut self, name: &str) -> BusId {
+        self.buses.push(Bus {
+            name: name.to_string(),
+            volume: 1.0,
+        });
+        BusId {
+            id: self.buses.len() - 1
+        }
+    }
+
+    /// Finds a bus by the name it was created with.
+    pub fn bus(&self, name: &str) -> Option<BusId> {
+        self.buses.iter().position(|b| b.name == name).map(|id| BusId { id: id })
+    }
+
+    pub fn bus_volume(&self, bus: BusId) -> f32 {
+        self.buses[bus.id].volume
+    }
+
+    pub fn set_bus_volume(&mut self, bus: BusId, volume: f32) {
+        self.buses[bus.id].volume = volume.max(0.0);
+    }
+
+    pub fn master_volume(&self) -> f32 {
+        self.master_volume
+    }
+
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.master_volume = volume.max(0.0);
+    }
+
+    pub fn listener(&self) -> Listener {
+        self.listener
+    }
+
+    pub fn set_listener(&mut self, listener: Listener) {
+        self.listener = listener;
+    }
+
+    /// Moves the listener, call this with the camera position every update.
+    pub fn set_listener_position(&mut self, position: [f32; 2]) {
+        self.listener.position = position;
+    }
+
+    /// Plays a sound that's in memory, such as an effect.
+    pub fn play(&mut self, sound: &Sound, options: PlayOptions) -> VoiceId {
+        self.add_voice(Source::Sound(sound.clone()), options)
+    }
+
+    /// Streams music from a file while it plays, instead of loading it all at once.
+    pub fn play_music<P: AsRef<Path>>(&mut self, path: P, options: PlayOptions) -> Result<VoiceId, AudioError> {
+        let stream = try!(stream::open_stream(path));
+        Ok(self.play_stream(stream, options))
+    }
+
+    pub fn play_stream(&mut self, stream: Box<SoundStream>, options: PlayOptions) -> VoiceId {
+        let source = StreamSource {
+            stream: stream,
+            buffer: Vec::new(),
+            ended: false,
+        };
+        self.add_voice(Source::Stream(source), options)
+    }
+
+    fn add_voice(&mut self, source: Source, options: PlayOptions) -> VoiceId {
+        assert!(options.bus.id < self.buses.len());
+
+        let id = VoiceId {
+            id: self.next_voice
+        };
+        self.next_voice += 1;
+
+        self.voices.push(Voice {
+            id: id,
+            source: source,
+            options: options,
+            cursor: 0.0,
+        });
+        id
+    }
+
+    /// Returns true if the voice hasn't finished or been stopped yet.
+    pub fn is_playing(&self, voice: VoiceId) -> bool {
+        self.voices.iter().any(|v| v.id == voice)
+    }
+
+    pub fn stop(&mut self, voice: VoiceId) {
+        self.voices.retain(|v| v.id != voice);
+    }
+
+    /// Stops everything playing on a bus.
+    pub fn stop_bus(&mut self, bus: BusId) {
+        self.voices.retain(|v| v.options.bus != bus);
+    }
+
+    pub fn set_volume(&mut self, voice: VoiceId, volume: f32) {
+        if let Some(voice) = self.voices.iter_mut().find(|v| v.id == voice) {
+            voice.options.volume = volume;
+        }
+    }
+
+    /// Moves a playing sound, for sounds that follow something in the world.
+    pub fn set_position(&mut self, voice: VoiceId, position: Option<[f32; 2]>) {
+        if let Some(voice) = self.voices.iter_mut().find(|v| v.id == voice) {
+            voice.options.position = position;
+        }
+    }
+
+    /// Mixes the audio for the time that passed and gives it to the backend. If a stream fails
+    /// to decode it's stopped, and the error is returned after everything else was still mixed.
+    pub fn update(&mut self, delta: f32) -> Result<(), AudioError> {
+        self.pending_frames += delta as f64 * self.backend.sample_rate() as f64;
+        let frames = self.pending_frames as usize;
+        self.pending_frames -= frames as f64;
+
+        let (samples, error) = self.mix(frames);
+        try!(self.backend.write(&samples));
+        match error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    fn mix(&mut self, frames: usize) -> (Vec<f32>, Option<AudioError>) {
+        let output_rate = self.backend.sample_rate();
+        let mut samples = vec!(0.0; frames * 2);
+        let mut error = None;
+
+        let mut finished = Vec::new();
+        for voice in &mut self.voices {
+            let volume = voice.options.volume * self.buses[voice.options.bus.id].volume * self.master_volume;
+            let gains = spatial_gains(&self.listener, voice.options.position);
+
+            for frame in 0..frames {
+                match voice.next_frame(output_rate) {
+                    Ok(Some(value)) => {
+                        samples[frame * 2] += value[0] * volume * gains[0];
+                        samples[frame * 2 + 1] += value[1] * volume * gains[1];
+                    },
+                    Ok(None) => {
+                        finished.push(voice.id);
+                        break;
+                    },
+                    Err(e) => {
+                        error = Some(e);
+                        finished.push(voice.id);
+                        break;
+                    },
+                }
+            }
+        }
+        self.voices.retain(|v| !finished.contains(&v.id));
+
+        // Loud mixes would wrap around on some backends, so clip them
+        for sample in &mut samples {
+            *sample = sample.max(-1.0).min(1.0);
+        }
+        (samples, error)
+    }
+}
+
+/// Returns the volume of the left and right speaker for a sound at the position. Panning keeps
+/// the closer speaker at full volume and lowers the other one.
+fn spatial_gains(listener: &Listener, position: Option<[f32; 2]>) -> [f32; 2] {
+    let position = if let Some(position) = position {
+        position
+    } else {
+        return [1.0, 1.0];
+    };
+
+    let offset = [position[0] - listener.position[0], position[1] - listener.position[1]];
+    let distance = (offset[0] * offset[0] + offset[1] * offset[1]).sqrt();
+    let attenuation = (1.0 - distance / listener.max_distance).max(0.0);
+    let pan = (offset[0] / listener.pan_distance).max(-1.0).min(1.0);
+
+    [(1.0 - pan).min(1.0) * attenuation, (1.0 + pan).min(1.0) * attenuation]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use backend::NullBackend;
+    use sound::Sound;
+    use wav::{WavWriter, WavReader};
+    use super::{Audio, PlayOptions, Listener};
+
+    #[test]
+    fn mixes_buses_and_pans_to_the_listener() {
+        let mut audio = Audio::new(NullBackend::new(4));
+        let effects = audio.create_bus("effects");
+        audio.set_bus_volume(effects, 0.5);
+        audio.set_listener(Listener {
+            position: [0.0, 0.0],
+            pan_distance: 10.0,
+            max_distance: 100.0,
+        });
+
+        // A sound fully to the right only plays on the right speaker
+        let sound = Sound::from_samples(1, 4, vec!(1.0, 1.0));
+        let voice = audio.play(&sound, PlayOptions::at(effects, [10.0, 0.0]));
+        let (samples, _) = audio.mix(3);
+        assert_eq!(samples, vec!(0.0, 0.45, 0.0, 0.45, 0.0, 0.0));
+        assert!(!audio.is_playing(voice));
+
+        audio.update(1.0).unwrap();
+        assert_eq!(audio.backend().frames_written(), 4);
+    }
+
+    #[test]
+    fn streams_loop_from_the_start() {
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), 1, 4).unwrap();
+        writer.write(&[0.5, -0.5]).unwrap();
+        let mut data = writer.finish().unwrap();
+        data.set_position(0);
+
+        let mut audio = Audio::new(NullBackend::new(4));
+        let music = audio.create_bus("music");
+        let mut options = PlayOptions::new(music);
+        options.looping = true;
+        audio.play_stream(Box::new(WavReader::new(data).unwrap()), options);
+
+        let (samples, error) = audio.mix(3);
+        assert!(error.is_none());
+        // Rounded, as 16 bits can't store exactly 0.5
+        let left: Vec<_> = samples.chunks(2).map(|f| (f[0] * 2.0).round() / 2.0).collect();
+        assert_eq!(left, vec!(0.5, -0.5, 0.5));
+    }
+}
@@ -0,0 +1,99 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use error::AudioError;
+use wav::WavWriter;
+
+/// Where mixed audio goes. Samples are always interleaved stereo.
+pub trait AudioBackend {
+    fn sample_rate(&self) -> u32;
+    fn write(&mut self, samples: &[f32]) -> Result<(), AudioError>;
+}
+
+impl<B: AudioBackend + ?Sized> AudioBackend for Box<B> {
+    fn sample_rate(&self) -> u32 {
+        (**self).sample_rate()
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<(), AudioError> {
+        (**self).write(samples)
+    }
+}
+
+/// A backend that throws away everything, for running without any audio output like in tests.
+pub struct NullBackend {
+    sample_rate: u32,
+    frames_written: u64,
+}
+
+impl NullBackend {
+    pub fn new(sample_rate: u32) -> Self {
+        NullBackend {
+            sample_rate: sample_rate,
+            frames_written: 0,
+        }
+    }
+
+    /// Returns how many frames have been mixed, so tests can check time is passing.
+    pub fn frames_written(&self) -> u64 {
+        self.frames_written
+    }
+}
+
+impl AudioBackend for NullBackend {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<(), AudioError> {
+        self.frames_written += samples.len() as u64 / 2;
+        Ok(())
+    }
+}
+
+/// A backend that writes everything to a WAV file, to listen to what would have played without
+/// needing an audio device.
+pub struct WavWriterBackend {
+    sample_rate: u32,
+    writer: Option<WavWriter<BufWriter<File>>>,
+}
+
+impl WavWriterBackend {
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> Result<Self, AudioError> {
+        let file = try!(File::create(path));
+        let writer = try!(WavWriter::new(BufWriter::new(file), 2, sample_rate));
+
+        Ok(WavWriterBackend {
+            sample_rate: sample_rate,
+            writer: Some(writer),
+        })
+    }
+
+    /// Finishes the file, after this nothing more is written. This also happens when the backend
+    /// is dropped, but any error is lost then.
+    pub fn finish(&mut self) -> Result<(), AudioError> {
+        if let Some(writer) = self.writer.take() {
+            try!(writer.finish());
+        }
+        Ok(())
+    }
+}
+
+impl AudioBackend for WavWriterBackend {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<(), AudioError> {
+        if let Some(ref mut writer) = self.writer {
+            try!(writer.write(samples));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for WavWriterBackend {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
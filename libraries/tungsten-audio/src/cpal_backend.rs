@@ -0,0 +1,105 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use cpal::{self, EventLoop, StreamId, StreamData, UnknownTypeOutputBuffer, Sample};
+use backend::AudioBackend;
+use error::AudioError;
+
+/// The most audio waiting to be played in seconds. If the game mixes faster than the device plays
+/// the oldest audio is dropped, so sounds don't end up lagging behind the game.
+const MAX_QUEUED_SECONDS: f32 = 0.2;
+
+/// A backend that plays on the default audio output device, using cpal. Only available with the
+/// `device` feature.
+pub struct CpalBackend {
+    sample_rate: u32,
+    queue: Arc<Mutex<VecDeque<f32>>>,
+    event_loop: Arc<EventLoop>,
+    stream: StreamId,
+}
+
+impl CpalBackend {
+    pub fn new() -> Result<Self, AudioError> {
+        let device = try!(cpal::default_output_device()
+            .ok_or_else(|| AudioError::Device("There is no audio output device".into())));
+        let format = try!(device.default_output_format().map_err(device_error));
+        let event_loop = Arc::new(EventLoop::new());
+        let stream = try!(event_loop.build_output_stream(&device, &format).map_err(device_error));
+        event_loop.play_stream(stream.clone());
+
+        // The event loop takes over the thread it runs on, the device pulls from the queue there
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+        let device_queue = queue.clone();
+        let device_loop = event_loop.clone();
+        let channels = format.channels as usize;
+        thread::spawn(move || {
+            device_loop.run(move |_, data| {
+                let mut queue = device_queue.lock().unwrap();
+                match data {
+                    StreamData::Output { buffer: UnknownTypeOutputBuffer::U16(mut buffer) } =>
+                        fill(&mut buffer, channels, &mut queue),
+                    StreamData::Output { buffer: UnknownTypeOutputBuffer::I16(mut buffer) } =>
+                        fill(&mut buffer, channels, &mut queue),
+                    StreamData::Output { buffer: UnknownTypeOutputBuffer::F32(mut buffer) } =>
+                        fill(&mut buffer, channels, &mut queue),
+                    StreamData::Input { .. } => (),
+                }
+            });
+        });
+
+        Ok(CpalBackend {
+            sample_rate: format.sample_rate.0,
+            queue: queue,
+            event_loop: event_loop,
+            stream: stream,
+        })
+    }
+}
+
+impl AudioBackend for CpalBackend {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<(), AudioError> {
+        let mut queue = self.queue.lock().unwrap();
+        queue.extend(samples.iter().cloned());
+
+        // Drop whole frames so left and right stay in order
+        let max_samples = (self.sample_rate as f32 * MAX_QUEUED_SECONDS) as usize * 2;
+        if queue.len() > max_samples {
+            let excess = (queue.len() - max_samples + 1) / 2 * 2;
+            queue.drain(..excess);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for CpalBackend {
+    fn drop(&mut self) {
+        self.event_loop.destroy_stream(self.stream.clone());
+    }
+}
+
+/// Fills the device's buffer from the queued stereo audio, with silence if there isn't enough.
+/// Mono devices get both sides mixed together, extra channels are left silent.
+fn fill<S: Sample>(buffer: &mut [S], channels: usize, queue: &mut VecDeque<f32>) {
+    for frame in buffer.chunks_mut(channels) {
+        let left = queue.pop_front().unwrap_or(0.0);
+        let right = queue.pop_front().unwrap_or(0.0);
+
+        for (channel, sample) in frame.iter_mut().enumerate() {
+            let value = match (channels, channel) {
+                (1, _) => (left + right) * 0.5,
+                (_, 0) => left,
+                (_, 1) => right,
+                _ => 0.0,
+            };
+            *sample = Sample::from(&value);
+        }
+    }
+}
+
+fn device_error<E: ::std::fmt::Display>(error: E) -> AudioError {
+    AudioError::Device(format!("{}", error))
+}
@@ -0,0 +1,31 @@
+use std::fmt;
+use std::io;
+
+#[derive(Clone, Debug)]
+pub enum AudioError {
+    /// A file could not be read or written.
+    Io(String),
+    /// The data isn't valid for its format.
+    Format(String),
+    /// The format isn't supported, such as OGG without the `ogg` feature.
+    Unsupported(String),
+    /// The audio device could not be opened.
+    Device(String),
+}
+
+impl From<io::Error> for AudioError {
+    fn from(error: io::Error) -> Self {
+        AudioError::Io(format!("{}", error))
+    }
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AudioError::Io(ref e) => write!(f, "Could not access audio file: {}", e),
+            AudioError::Format(ref e) => write!(f, "Invalid audio data: {}", e),
+            AudioError::Unsupported(ref e) => write!(f, "Unsupported audio format: {}", e),
+            AudioError::Device(ref e) => write!(f, "Could not use audio device: {}", e),
+        }
+    }
+}
@@ -0,0 +1,24 @@
+#[cfg(feature = "device")]
+extern crate cpal;
+#[cfg(feature = "ogg")]
+extern crate lewton;
+
+mod audio;
+mod backend;
+#[cfg(feature = "device")]
+mod cpal_backend;
+mod error;
+#[cfg(feature = "ogg")]
+mod ogg;
+mod sound;
+mod stream;
+mod wav;
+
+pub use audio::{Audio, BusId, VoiceId, PlayOptions, Listener};
+pub use backend::{AudioBackend, NullBackend, WavWriterBackend};
+#[cfg(feature = "device")]
+pub use cpal_backend::CpalBackend;
+pub use error::AudioError;
+pub use sound::Sound;
+pub use stream::{SoundStream, open_stream};
+pub use wav::{WavReader, WavWriter};
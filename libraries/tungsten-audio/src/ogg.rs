@@ -0,0 +1,86 @@
+use std::io::{Read, Seek};
+use lewton::inside_ogg::OggStreamReader;
+use error::AudioError;
+use sound::Sound;
+use stream::SoundStream;
+
+/// Reads samples from OGG Vorbis data a packet at a time.
+pub struct OggReader<R: Read + Seek> {
+    reader: OggStreamReader<R>,
+    /// Decoded samples that didn't fit in the last read.
+    pending: Vec<f32>,
+}
+
+impl<R: Read + Seek> OggReader<R> {
+    pub fn new(reader: R) -> Result<Self, AudioError> {
+        let reader = try!(OggStreamReader::new(reader).map_err(vorbis_error));
+        let channels = reader.ident_hdr.audio_channels;
+        if channels == 0 || channels > 2 {
+            let error = format!("{} channels, only mono and stereo are supported", channels);
+            return Err(AudioError::Unsupported(error));
+        }
+
+        Ok(OggReader {
+            reader: reader,
+            pending: Vec::new(),
+        })
+    }
+}
+
+impl<R: Read + Seek> SoundStream for OggReader<R> {
+    fn channels(&self) -> u16 {
+        self.reader.ident_hdr.audio_channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.reader.ident_hdr.audio_sample_rate
+    }
+
+    fn read(&mut self, samples: &mut Vec<f32>, frames: usize) -> Result<usize, AudioError> {
+        let channels = self.channels() as usize;
+
+        // Packets don't line up with what we're asked for, so keep decoding until we have enough
+        while self.pending.len() < frames * channels {
+            match try!(self.reader.read_dec_packet_itl().map_err(vorbis_error)) {
+                Some(packet) => self.pending.extend(packet.iter().map(|&s| s as f32 / 32768.0)),
+                None => break,
+            }
+        }
+
+        let count = self.pending.len().min(frames * channels);
+        samples.extend(self.pending.drain(..count));
+        Ok(count / channels)
+    }
+
+    fn rewind(&mut self) -> Result<(), AudioError> {
+        self.pending.clear();
+        self.reader.seek_absgp_pg(0).map_err(vorbis_error)
+    }
+}
+
+/// Decodes a whole OGG Vorbis file into a sound.
+pub fn decode<R: Read + Seek>(reader: R) -> Result<Sound, AudioError> {
+    let mut reader = try!(OggReader::new(reader));
+    let mut samples = Vec::new();
+    while try!(reader.read(&mut samples, 4096)) != 0 {}
+    Ok(Sound::from_samples(reader.channels(), reader.sample_rate(), samples))
+}
+
+fn vorbis_error<E: ::std::fmt::Debug>(error: E) -> AudioError {
+    AudioError::Format(format!("{:?}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use error::AudioError;
+    use super::decode;
+
+    #[test]
+    fn rejects_data_that_is_not_vorbis() {
+        match decode(Cursor::new(b"RIFF but not an OGG file".to_vec())) {
+            Err(AudioError::Format(_)) => (),
+            _ => panic!("Decoded something that isn't OGG Vorbis"),
+        }
+    }
+}
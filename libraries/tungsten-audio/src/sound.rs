@@ -0,0 +1,96 @@
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+use std::path::Path;
+use std::sync::Arc;
+use error::AudioError;
+use wav;
+
+struct SoundData {
+    channels: u16,
+    sample_rate: u32,
+    samples: Vec<f32>,
+}
+
+/// A sound decoded fully into memory, for effects that are played often. Clones share the same
+/// samples, so they're cheap.
+#[derive(Clone)]
+pub struct Sound {
+    data: Arc<SoundData>,
+}
+
+impl Sound {
+    /// Creates a sound from interleaved samples between -1.0 and 1.0, with one or two channels.
+    pub fn from_samples(channels: u16, sample_rate: u32, samples: Vec<f32>) -> Self {
+        assert!(channels == 1 || channels == 2);
+        assert!(sample_rate > 0);
+
+        Sound {
+            data: Arc::new(SoundData {
+                channels: channels,
+                sample_rate: sample_rate,
+                samples: samples,
+            }),
+        }
+    }
+
+    /// Loads a WAV file, or an OGG file if the `ogg` feature is enabled, based on its extension.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, AudioError> {
+        let path = path.as_ref();
+        let reader = BufReader::new(try!(File::open(path)));
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("wav") => wav::decode(reader),
+            Some("ogg") => decode_ogg(reader),
+            _ => Err(AudioError::Unsupported(format!("{}", path.display()))),
+        }
+    }
+
+    pub fn from_wav(data: &[u8]) -> Result<Self, AudioError> {
+        wav::decode(Cursor::new(data))
+    }
+
+    pub fn from_ogg(data: &[u8]) -> Result<Self, AudioError> {
+        decode_ogg(Cursor::new(data))
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.data.channels
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.data.sample_rate
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        &self.data.samples
+    }
+
+    pub fn frames(&self) -> usize {
+        self.data.samples.len() / self.data.channels as usize
+    }
+
+    /// Returns how long the sound plays for in seconds.
+    pub fn duration(&self) -> f32 {
+        self.frames() as f32 / self.data.sample_rate as f32
+    }
+
+    /// Returns the left and right sample of a frame, mono sounds have the same sample on both.
+    pub fn frame(&self, frame: usize) -> [f32; 2] {
+        let samples = &self.data.samples;
+        if self.data.channels == 1 {
+            [samples[frame], samples[frame]]
+        } else {
+            [samples[frame * 2], samples[frame * 2 + 1]]
+        }
+    }
+}
+
+#[cfg(feature = "ogg")]
+fn decode_ogg<R: ::std::io::Read + ::std::io::Seek>(reader: R) -> Result<Sound, AudioError> {
+    ::ogg::decode(reader)
+}
+
+#[cfg(not(feature = "ogg"))]
+fn decode_ogg<R>(_reader: R) -> Result<Sound, AudioError> {
+    Err(AudioError::Unsupported("OGG needs the ogg feature".into()))
+}
@@ -0,0 +1,40 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use error::AudioError;
+use wav::WavReader;
+
+/// A source of samples that's decoded while it plays, for music that's too long to keep in memory.
+pub trait SoundStream {
+    fn channels(&self) -> u16;
+    fn sample_rate(&self) -> u32;
+
+    /// Adds up to `frames` frames of interleaved samples, returning how many were added. Returns
+    /// 0 at the end of the stream.
+    fn read(&mut self, samples: &mut Vec<f32>, frames: usize) -> Result<usize, AudioError>;
+
+    /// Goes back to the start of the stream, for looping.
+    fn rewind(&mut self) -> Result<(), AudioError>;
+}
+
+/// Opens a WAV file, or an OGG file if the `ogg` feature is enabled, as a stream.
+pub fn open_stream<P: AsRef<Path>>(path: P) -> Result<Box<SoundStream>, AudioError> {
+    let path = path.as_ref();
+    let reader = BufReader::new(try!(File::open(path)));
+
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("wav") => Ok(Box::new(try!(WavReader::new(reader)))),
+        Some("ogg") => open_ogg(reader),
+        _ => Err(AudioError::Unsupported(format!("{}", path.display()))),
+    }
+}
+
+#[cfg(feature = "ogg")]
+fn open_ogg(reader: BufReader<File>) -> Result<Box<SoundStream>, AudioError> {
+    Ok(Box::new(try!(::ogg::OggReader::new(reader))))
+}
+
+#[cfg(not(feature = "ogg"))]
+fn open_ogg(_reader: BufReader<File>) -> Result<Box<SoundStream>, AudioError> {
+    Err(AudioError::Unsupported("OGG needs the ogg feature".into()))
+}
@@ -0,0 +1,251 @@
+use std::io::{Read, Write, Seek, SeekFrom};
+use error::AudioError;
+use sound::Sound;
+use stream::SoundStream;
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct WavFormat {
+    channels: u16,
+    sample_rate: u32,
+    bits: u16,
+    float: bool,
+}
+
+impl WavFormat {
+    fn bytes_per_frame(&self) -> usize {
+        self.channels as usize * (self.bits as usize / 8)
+    }
+}
+
+/// Reads samples from WAV data a bit at a time, so long music doesn't have to be in memory at once.
+pub struct WavReader<R> {
+    reader: R,
+    format: WavFormat,
+    data_start: u64,
+    data_length: u32,
+    remaining: u32,
+}
+
+impl<R: Read + Seek> WavReader<R> {
+    pub fn new(mut reader: R) -> Result<Self, AudioError> {
+        let mut header = [0; 12];
+        try!(reader.read_exact(&mut header));
+        if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+            return Err(AudioError::Format("Not a RIFF WAVE file".into()));
+        }
+
+        // Go through the chunks until we find the data, the format has to come before it
+        let mut format = None;
+        loop {
+            let mut chunk = [0; 8];
+            try!(reader.read_exact(&mut chunk));
+            let length = read_u32(&chunk[4..8]);
+
+            match &chunk[0..4] {
+                b"fmt " => {
+                    if length < 16 {
+                        return Err(AudioError::Format("Format chunk is too short".into()));
+                    }
+                    let mut data = vec!(0; (length + length % 2) as usize);
+                    try!(reader.read_exact(&mut data));
+                    format = Some(try!(parse_format(&data)));
+                },
+                b"data" => {
+                    let format = try!(format.ok_or_else(|| AudioError::Format("Data before format".into())));
+                    let data_start = try!(reader.seek(SeekFrom::Current(0)));
+                    return Ok(WavReader {
+                        reader: reader,
+                        format: format,
+                        data_start: data_start,
+                        data_length: length,
+                        remaining: length,
+                    });
+                },
+                _ => {
+                    // Chunks we don't care about, skip them including their padding byte
+                    try!(reader.seek(SeekFrom::Current((length + length % 2) as i64)));
+                },
+            }
+        }
+    }
+}
+
+impl<R: Read + Seek> SoundStream for WavReader<R> {
+    fn channels(&self) -> u16 {
+        self.format.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.format.sample_rate
+    }
+
+    fn read(&mut self, samples: &mut Vec<f32>, frames: usize) -> Result<usize, AudioError> {
+        let frame_bytes = self.format.bytes_per_frame();
+        let frames = frames.min(self.remaining as usize / frame_bytes);
+        if frames == 0 {
+            return Ok(0);
+        }
+
+        let mut data = vec!(0; frames * frame_bytes);
+        try!(self.reader.read_exact(&mut data));
+        self.remaining -= data.len() as u32;
+
+        let sample_bytes = self.format.bits as usize / 8;
+        for sample in data.chunks(sample_bytes) {
+            samples.push(convert_sample(sample, self.format));
+        }
+        Ok(frames)
+    }
+
+    fn rewind(&mut self) -> Result<(), AudioError> {
+        try!(self.reader.seek(SeekFrom::Start(self.data_start)));
+        self.remaining = self.data_length;
+        Ok(())
+    }
+}
+
+fn parse_format(data: &[u8]) -> Result<WavFormat, AudioError> {
+    let format = WavFormat {
+        channels: read_u16(&data[2..4]),
+        sample_rate: read_u32(&data[4..8]),
+        bits: read_u16(&data[14..16]),
+        float: read_u16(&data[0..2]) == FORMAT_FLOAT,
+    };
+
+    match (read_u16(&data[0..2]), format.bits) {
+        (FORMAT_PCM, 8) | (FORMAT_PCM, 16) | (FORMAT_PCM, 24) | (FORMAT_PCM, 32) |
+        (FORMAT_FLOAT, 32) => (),
+        (tag, bits) => {
+            let error = format!("WAV format {} with {} bits", tag, bits);
+            return Err(AudioError::Unsupported(error));
+        },
+    }
+    if format.channels == 0 || format.channels > 2 {
+        let error = format!("{} channels, only mono and stereo are supported", format.channels);
+        return Err(AudioError::Unsupported(error));
+    }
+
+    Ok(format)
+}
+
+fn convert_sample(data: &[u8], format: WavFormat) -> f32 {
+    match (format.bits, format.float) {
+        // 8 bit samples are the only unsigned ones
+        (8, _) => (data[0] as f32 - 128.0) / 128.0,
+        (16, _) => read_u16(data) as i16 as f32 / 32768.0,
+        (24, _) => {
+            let value = (data[0] as i32) << 8 | (data[1] as i32) << 16 | (data[2] as i32) << 24;
+            (value >> 8) as f32 / 8388608.0
+        },
+        (32, true) => f32::from_bits(read_u32(data)),
+        (32, false) => read_u32(data) as i32 as f32 / 2147483648.0,
+        _ => unreachable!(),
+    }
+}
+
+/// Decodes a whole WAV file into a sound.
+pub fn decode<R: Read + Seek>(reader: R) -> Result<Sound, AudioError> {
+    let mut reader = try!(WavReader::new(reader));
+    let mut samples = Vec::new();
+    while try!(reader.read(&mut samples, 4096)) != 0 {}
+    Ok(Sound::from_samples(reader.channels(), reader.sample_rate(), samples))
+}
+
+/// Writes 16 bit WAV data, the sizes in the header are filled in when finished.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    data_length: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W, channels: u16, sample_rate: u32) -> Result<Self, AudioError> {
+        let block_align = channels * 2;
+        let mut header = Vec::new();
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&u32_bytes(0));
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&u32_bytes(16));
+        header.extend_from_slice(&u16_bytes(FORMAT_PCM));
+        header.extend_from_slice(&u16_bytes(channels));
+        header.extend_from_slice(&u32_bytes(sample_rate));
+        header.extend_from_slice(&u32_bytes(sample_rate * block_align as u32));
+        header.extend_from_slice(&u16_bytes(block_align));
+        header.extend_from_slice(&u16_bytes(16));
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&u32_bytes(0));
+        try!(writer.write_all(&header));
+
+        Ok(WavWriter {
+            writer: writer,
+            data_length: 0,
+        })
+    }
+
+    /// Writes interleaved samples, clipping anything outside of -1.0 to 1.0.
+    pub fn write(&mut self, samples: &[f32]) -> Result<(), AudioError> {
+        let mut data = Vec::with_capacity(samples.len() * 2);
+        for &sample in samples {
+            let value = (sample.max(-1.0).min(1.0) * 32767.0) as i16;
+            data.extend_from_slice(&u16_bytes(value as u16));
+        }
+
+        try!(self.writer.write_all(&data));
+        self.data_length += data.len() as u32;
+        Ok(())
+    }
+
+    /// Fills in the sizes in the header and returns the writer.
+    pub fn finish(mut self) -> Result<W, AudioError> {
+        try!(self.writer.seek(SeekFrom::Start(4)));
+        try!(self.writer.write_all(&u32_bytes(36 + self.data_length)));
+        try!(self.writer.seek(SeekFrom::Start(40)));
+        try!(self.writer.write_all(&u32_bytes(self.data_length)));
+        try!(self.writer.seek(SeekFrom::End(0)));
+        try!(self.writer.flush());
+        Ok(self.writer)
+    }
+}
+
+fn read_u16(data: &[u8]) -> u16 {
+    data[0] as u16 | (data[1] as u16) << 8
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    data[0] as u32 | (data[1] as u32) << 8 | (data[2] as u32) << 16 | (data[3] as u32) << 24
+}
+
+fn u16_bytes(value: u16) -> [u8; 2] {
+    [value as u8, (value >> 8) as u8]
+}
+
+fn u32_bytes(value: u32) -> [u8; 4] {
+    [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::{WavWriter, decode};
+
+    #[test]
+    fn written_wav_decodes_to_the_same_samples() {
+        let samples = [0.0, 0.5, -0.5, 1.0, -1.0, 0.25];
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), 2, 22050).unwrap();
+        writer.write(&samples).unwrap();
+        let mut data = writer.finish().unwrap();
+        data.set_position(0);
+
+        let sound = decode(data).unwrap();
+        assert_eq!(sound.channels(), 2);
+        assert_eq!(sound.sample_rate(), 22050);
+        assert_eq!(sound.frames(), 3);
+
+        // 16 bits loses a little bit of precision
+        for (decoded, original) in sound.samples().iter().zip(samples.iter()) {
+            assert!((decoded - original).abs() < 0.0001);
+        }
+    }
+}
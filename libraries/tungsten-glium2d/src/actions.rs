@@ -0,0 +1,427 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use rustc_serialize::json;
+use glium::glutin::MouseButton;
+use gamepad::{GamepadButton, GamepadAxis};
+use input::InputState;
+use Key;
+
+/// A single physical input that can be bound to actions.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Input {
+    Key(Key),
+    Mouse(MouseButton),
+    /// A button on any connected gamepad.
+    Gamepad(GamepadButton),
+}
+
+impl Input {
+    /// The name of the input as used in config files, such as `Key:W`, `Mouse:Left` or
+    /// `Gamepad:South`.
+    pub fn name(&self) -> Option<String> {
+        match *self {
+            Input::Key(key) => key_name(key).map(|n| format!("Key:{}", n)),
+            Input::Mouse(MouseButton::Left) => Some("Mouse:Left".into()),
+            Input::Mouse(MouseButton::Right) => Some("Mouse:Right".into()),
+            Input::Mouse(MouseButton::Middle) => Some("Mouse:Middle".into()),
+            Input::Mouse(MouseButton::Other(button)) => Some(format!("Mouse:{}", button)),
+            Input::Gamepad(button) => Some(format!("Gamepad:{:?}", button)),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let mut parts = name.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some("Key"), Some(key)) => key_from_name(key).map(Input::Key),
+            (Some("Mouse"), Some("Left")) => Some(Input::Mouse(MouseButton::Left)),
+            (Some("Mouse"), Some("Right")) => Some(Input::Mouse(MouseButton::Right)),
+            (Some("Mouse"), Some("Middle")) => Some(Input::Mouse(MouseButton::Middle)),
+            (Some("Mouse"), Some(button)) => button.parse().ok().map(|b| Input::Mouse(MouseButton::Other(b))),
+            (Some("Gamepad"), Some(button)) => gamepad_button_from_name(button).map(Input::Gamepad),
+            _ => None,
+        }
+    }
+
+    pub fn is_down(&self, state: &InputState) -> bool {
+        match *self {
+            Input::Key(key) => state.is_key_down(key),
+            Input::Mouse(button) => state.is_button_down(button),
+            Input::Gamepad(button) => state.is_gamepad_button_down(button),
+        }
+    }
+
+    pub fn was_pressed(&self, state: &InputState) -> bool {
+        match *self {
+            Input::Key(key) => state.was_key_pressed(key),
+            Input::Mouse(button) => state.was_button_pressed(button),
+            Input::Gamepad(button) => state.was_gamepad_button_pressed(button),
+        }
+    }
+
+    pub fn was_released(&self, state: &InputState) -> bool {
+        match *self {
+            Input::Key(key) => state.was_key_released(key),
+            Input::Mouse(button) => state.was_button_released(button),
+            Input::Gamepad(button) => state.was_gamepad_button_released(button),
+        }
+    }
+}
+
+/// Two inputs that together make up an analog axis, going from -1.0 to 1.0.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AxisBinding {
+    pub negative: Input,
+    pub positive: Input,
+}
+
+impl AxisBinding {
+    pub fn value(&self, state: &InputState) -> f32 {
+        let mut value = 0.0;
+        if self.negative.is_down(state) {
+            value -= 1.0;
+        }
+        if self.positive.is_down(state) {
+            value += 1.0;
+        }
+        value
+    }
+}
+
+/// Maps physical inputs to named actions and axes, so game code doesn't have to know which keys
+/// the player picked.
+#[derive(Clone, Debug, Default)]
+pub struct InputMap {
+    actions: BTreeMap<String, Vec<Input>>,
+    axes: BTreeMap<String, Vec<AxisBinding>>,
+    analog: BTreeMap<String, Vec<GamepadAxis>>,
+}
+
+impl InputMap {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds an input that triggers an action, on top of any inputs already bound to it.
+    pub fn bind(&mut self, action: &str, input: Input) {
+        let inputs = self.actions.entry(action.to_string()).or_insert_with(Vec::new);
+        if !inputs.contains(&input) {
+            inputs.push(input);
+        }
+    }
+
+    /// Replaces all inputs bound to an action with a single input. Axes with the same name are
+    /// left alone.
+    pub fn rebind(&mut self, action: &str, input: Input) {
+        self.clear_action(action);
+        self.bind(action, input);
+    }
+
+    pub fn unbind(&mut self, action: &str, input: Input) {
+        if let Some(inputs) = self.actions.get_mut(action) {
+            inputs.retain(|i| *i != input);
+        }
+    }
+
+    /// Removes all inputs bound to an action.
+    pub fn clear_action(&mut self, action: &str) {
+        self.actions.remove(action);
+    }
+
+    /// Removes all inputs and gamepad axes bound to an axis.
+    pub fn clear_axis(&mut self, axis: &str) {
+        self.axes.remove(axis);
+        self.analog.remove(axis);
+    }
+
+    pub fn bind_axis(&mut self, axis: &str, negative: Input, positive: Input) {
+        let bindings = self.axes.entry(axis.to_string()).or_insert_with(Vec::new);
+        bindings.push(AxisBinding {
+            negative: negative,
+            positive: positive,
+        });
+    }
+
+    /// Binds a gamepad stick or trigger to an axis, on top of any inputs already bound to it.
+    pub fn bind_analog(&mut self, axis: &str, gamepad_axis: GamepadAxis) {
+        let bindings = self.analog.entry(axis.to_string()).or_insert_with(Vec::new);
+        if !bindings.contains(&gamepad_axis) {
+            bindings.push(gamepad_axis);
+        }
+    }
+
+    pub fn bindings(&self, action: &str) -> &[Input] {
+        self.actions.get(action).map(|i| &i[..]).unwrap_or(&[])
+    }
+
+    pub fn axis_bindings(&self, axis: &str) -> &[AxisBinding] {
+        self.axes.get(axis).map(|b| &b[..]).unwrap_or(&[])
+    }
+
+    pub fn analog_bindings(&self, axis: &str) -> &[GamepadAxis] {
+        self.analog.get(axis).map(|b| &b[..]).unwrap_or(&[])
+    }
+
+    /// Finds all actions an input is bound to, for turning raw input events into actions.
+    pub fn actions(&self, input: Input) -> Vec<&str> {
+        self.actions.iter()
+            .filter(|&(_, inputs)| inputs.contains(&input))
+            .map(|(action, _)| &action[..])
+            .collect()
+    }
+
+    /// Returns true if any input bound to the action is held down.
+    pub fn is_active(&self, action: &str, state: &InputState) -> bool {
+        self.bindings(action).iter().any(|i| i.is_down(state))
+    }
+
+    /// Returns true if any input bound to the action was pressed this frame.
+    pub fn was_triggered(&self, action: &str, state: &InputState) -> bool {
+        self.bindings(action).iter().any(|i| i.was_pressed(state))
+    }
+
+    /// Returns true if any input bound to the action was released this frame.
+    pub fn was_released(&self, action: &str, state: &InputState) -> bool {
+        self.bindings(action).iter().any(|i| i.was_released(state))
+    }
+
+    /// Returns the value of an axis from -1.0 to 1.0, combining all its bindings.
+    pub fn axis(&self, axis: &str, state: &InputState) -> f32 {
+        let digital = self.axis_bindings(axis).iter().fold(0.0, |sum, b| sum + b.value(state));
+        let analog = self.analog_bindings(axis).iter().fold(0.0, |sum, &a| sum + state.gamepad_axis(a));
+        (digital + analog).max(-1.0).min(1.0)
+    }
+
+    /// Turns the bindings into JSON for a config file. Fails if an input doesn't have a name,
+    /// rather than leaving it out of the file.
+    pub fn to_json(&self) -> Result<String, String> {
+        let mut config = BindingsConfig {
+            actions: BTreeMap::new(),
+            axes: BTreeMap::new(),
+            analog: None,
+        };
+
+        for (action, inputs) in &self.actions {
+            let names = try!(inputs.iter().map(input_name).collect());
+            config.actions.insert(action.clone(), names);
+        }
+        for (axis, bindings) in &self.axes {
+            let names = try!(bindings.iter().map(axis_config).collect());
+            config.axes.insert(axis.clone(), names);
+        }
+        if !self.analog.is_empty() {
+            let analog = self.analog.iter()
+                .map(|(axis, axes)| (axis.clone(), axes.iter().map(|a| format!("{:?}", a)).collect()))
+                .collect();
+            config.analog = Some(analog);
+        }
+
+        Ok(json::as_pretty_json(&config).to_string())
+    }
+
+    pub fn from_json(data: &str) -> Result<Self, String> {
+        let config: BindingsConfig = try!(json::decode(data).map_err(|e| format!("{}", e)));
+        let mut map = InputMap::new();
+
+        for (action, names) in config.actions {
+            // Actions without any bindings are kept, so they show up when remapping
+            map.actions.entry(action.clone()).or_insert_with(Vec::new);
+            for name in names {
+                let input = try!(parse_input(&name));
+                map.bind(&action, input);
+            }
+        }
+        for (axis, bindings) in config.axes {
+            map.axes.entry(axis.clone()).or_insert_with(Vec::new);
+            for binding in bindings {
+                let negative = try!(parse_input(&binding.negative));
+                let positive = try!(parse_input(&binding.positive));
+                map.bind_axis(&axis, negative, positive);
+            }
+        }
+        for (axis, names) in config.analog.unwrap_or_else(BTreeMap::new) {
+            map.analog.entry(axis.clone()).or_insert_with(Vec::new);
+            for name in names {
+                let gamepad_axis = try!(
+                    gamepad_axis_from_name(&name).ok_or_else(|| format!("Unknown gamepad axis \"{}\"", name))
+                );
+                map.bind_analog(&axis, gamepad_axis);
+            }
+        }
+
+        Ok(map)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let mut data = String::new();
+        try!(
+            File::open(path).and_then(|mut f| f.read_to_string(&mut data))
+                .map_err(|e| format!("Could not read {}: {}", path.display(), e))
+        );
+        Self::from_json(&data)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let data = try!(self.to_json());
+        File::create(path).and_then(|mut f| f.write_all(data.as_bytes()))
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    }
+}
+
+fn input_name(input: &Input) -> Result<String, String> {
+    input.name().ok_or_else(|| format!("{:?} doesn't have a name it can be saved with", input))
+}
+
+fn axis_config(binding: &AxisBinding) -> Result<AxisConfig, String> {
+    Ok(AxisConfig {
+        negative: try!(input_name(&binding.negative)),
+        positive: try!(input_name(&binding.positive)),
+    })
+}
+
+fn parse_input(name: &str) -> Result<Input, String> {
+    Input::from_name(name).ok_or_else(|| format!("Unknown input \"{}\"", name))
+}
+
+#[derive(RustcEncodable, RustcDecodable)]
+struct BindingsConfig {
+    actions: BTreeMap<String, Vec<String>>,
+    axes: BTreeMap<String, Vec<AxisConfig>>,
+    // Optional so configs written before gamepads were supported still load
+    analog: Option<BTreeMap<String, Vec<String>>>,
+}
+
+#[derive(RustcEncodable, RustcDecodable)]
+struct AxisConfig {
+    negative: String,
+    positive: String,
+}
+
+fn gamepad_button_from_name(name: &str) -> Option<GamepadButton> {
+    let buttons = [
+        GamepadButton::South, GamepadButton::East, GamepadButton::West, GamepadButton::North,
+        GamepadButton::LeftBumper, GamepadButton::RightBumper, GamepadButton::Select, GamepadButton::Start,
+        GamepadButton::LeftStick, GamepadButton::RightStick,
+        GamepadButton::DPadUp, GamepadButton::DPadDown, GamepadButton::DPadLeft, GamepadButton::DPadRight,
+    ];
+    buttons.iter().cloned().find(|b| format!("{:?}", b) == name)
+}
+
+fn gamepad_axis_from_name(name: &str) -> Option<GamepadAxis> {
+    let axes = [
+        GamepadAxis::LeftStickX, GamepadAxis::LeftStickY, GamepadAxis::RightStickX, GamepadAxis::RightStickY,
+        GamepadAxis::LeftTrigger, GamepadAxis::RightTrigger,
+    ];
+    axes.iter().cloned().find(|a| format!("{:?}", a) == name)
+}
+
+macro_rules! key_names {
+    ($($key:ident),*) => {
+        // Every key is listed, the fallback is for keys added by newer versions of glutin
+        #[allow(unreachable_patterns)]
+        fn key_name(key: Key) -> Option<&'static str> {
+            match key {
+                $(Key::$key => Some(stringify!($key)),)*
+                _ => None,
+            }
+        }
+
+        fn key_from_name(name: &str) -> Option<Key> {
+            match name {
+                $(stringify!($key) => Some(Key::$key),)*
+                _ => None,
+            }
+        }
+    }
+}
+
+// Keys that can be used in config files, named the same as the key codes
+key_names!(
+    Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Escape, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12, F13, F14, F15,
+    Snapshot, Scroll, Pause,
+    Insert, Home, Delete, End, PageDown, PageUp, Left, Up, Right, Down,
+    Back, Return, Space, Tab,
+    Numlock, Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8,
+    Numpad9, NumpadComma, NumpadEnter, NumpadEquals,
+    Add, Subtract, Multiply, Divide, Decimal,
+    Apostrophe, Backslash, Comma, Equals, Grave, LBracket, Minus, Period, RBracket, Semicolon, Slash,
+    LShift, RShift, LControl, RControl, LMenu, RMenu, LWin, RWin,
+    AbntC1, AbntC2, Apps, At, Ax, Calculator, Capital, Colon, Convert, Kana, Kanji, Mail,
+    MediaSelect, MediaStop, Mute, MyComputer, NavigateForward, NavigateBackward, NextTrack,
+    NoConvert, OEM102, PlayPause, Power, PrevTrack, Sleep, Stop, Sysrq, Underline, Unlabeled,
+    VolumeDown, VolumeUp, Wake, WebBack, WebFavorites, WebForward, WebHome, WebRefresh, WebSearch,
+    WebStop, Yen
+);
+
+#[cfg(test)]
+mod tests {
+    use glium::glutin::MouseButton;
+    use gamepad::GamepadButton;
+    use input::InputState;
+    use {Key, KeyState};
+    use super::{InputMap, Input};
+
+    #[test]
+    fn maps_inputs_to_actions_and_axes() {
+        let mut map = InputMap::new();
+        map.bind("thrust", Input::Key(Key::W));
+        map.bind("thrust", Input::Mouse(MouseButton::Left));
+        map.bind_axis("rotate", Input::Key(Key::A), Input::Key(Key::D));
+
+        let mut state = InputState::new();
+        state.key_input(Key::D, KeyState::Pressed);
+        state.button_input(MouseButton::Left, KeyState::Pressed);
+
+        assert!(map.is_active("thrust", &state));
+        assert!(map.was_triggered("thrust", &state));
+        assert_eq!(map.axis("rotate", &state), 1.0);
+        assert_eq!(map.actions(Input::Key(Key::W)), vec!("thrust"));
+
+        map.rebind("thrust", Input::Key(Key::Space));
+        assert!(!map.is_active("thrust", &state));
+    }
+
+    #[test]
+    fn input_names_round_trip() {
+        let inputs = [
+            Input::Key(Key::W), Input::Key(Key::Key1), Input::Key(Key::LShift),
+            Input::Key(Key::Capital),
+            Input::Mouse(MouseButton::Left), Input::Mouse(MouseButton::Other(4)),
+            Input::Gamepad(GamepadButton::DPadLeft),
+        ];
+
+        for input in inputs.iter() {
+            let name = input.name().unwrap();
+            assert_eq!(Input::from_name(&name), Some(*input));
+        }
+        assert_eq!(Input::from_name("Key:Nope"), None);
+    }
+
+    #[test]
+    fn saved_bindings_load_the_same() {
+        let mut map = InputMap::new();
+        map.bind("crouch", Input::Key(Key::Capital));
+        map.bind("crouch", Input::Gamepad(GamepadButton::East));
+        map.bind_axis("rotate", Input::Key(Key::Snapshot), Input::Key(Key::Scroll));
+
+        let loaded = InputMap::from_json(&map.to_json().unwrap()).unwrap();
+        assert_eq!(loaded.bindings("crouch"), map.bindings("crouch"));
+        assert_eq!(loaded.axis_bindings("rotate"), map.axis_bindings("rotate"));
+    }
+
+    #[test]
+    fn rebinding_an_action_keeps_axes() {
+        let mut map = InputMap::new();
+        map.bind_axis("move", Input::Key(Key::A), Input::Key(Key::D));
+        map.bind("move", Input::Key(Key::W));
+
+        map.rebind("move", Input::Key(Key::Up));
+        assert_eq!(map.bindings("move"), &[Input::Key(Key::Up)]);
+        assert_eq!(map.axis_bindings("move").len(), 1);
+    }
+}
@@ -0,0 +1,222 @@
+use frontend::{Rectangle, TextureId};
+
+/// The maximum amount of cells along each axis a background will generate rectangles for in one
+/// frame, to keep zooming out very far from generating millions of rectangles. Cells beyond this
+/// around the center of the view are left empty.
+const MAX_CELLS_PER_AXIS: i32 = 128;
+
+/// The smallest tile or cell size a background can have, smaller sizes are raised to this.
+const MIN_CELL_SIZE: f32 = 1.0;
+
+#[derive(Clone, Debug, RustcEncodable, RustcDecodable)]
+pub enum BackgroundFill {
+    /// Repeats a texture over the entire background.
+    Tiled {
+        texture: TextureId,
+        tile_size: [f32; 2],
+    },
+    /// Scatters sprites over the background. The background is divided into cells, each of which
+    /// has a chance to contain a sprite. Sprites are generated from the seed so they stay in place.
+    Scatter {
+        textures: Vec<TextureId>,
+        cell_size: f32,
+        /// The chance, from 0.0 to 1.0, for a cell to contain a sprite.
+        density: f32,
+        /// The minimum and maximum size of the sprites.
+        size: [f32; 2],
+        seed: u32,
+    },
+}
+
+/// A background layer inside of a camera. The parallax factor decides how much the layer follows
+/// the camera, 0.0 stays fixed on the screen while 1.0 moves along with the world.
+#[derive(Clone, Debug, RustcEncodable, RustcDecodable)]
+pub struct BackgroundInfo {
+    parallax: f32,
+    fill: BackgroundFill,
+    color: [f32; 4],
+}
+
+impl BackgroundInfo {
+    /// Creates a new background layer. Tile and cell sizes smaller than one unit are raised to one.
+    pub fn new(parallax: f32, mut fill: BackgroundFill) -> Self {
+        match fill {
+            BackgroundFill::Tiled { ref mut tile_size, .. } => {
+                tile_size[0] = tile_size[0].max(MIN_CELL_SIZE);
+                tile_size[1] = tile_size[1].max(MIN_CELL_SIZE);
+            },
+            BackgroundFill::Scatter { ref mut cell_size, .. } =>
+                *cell_size = cell_size.max(MIN_CELL_SIZE),
+        }
+
+        BackgroundInfo {
+            parallax: parallax,
+            fill: fill,
+            color: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+
+    pub fn parallax(&self) -> f32 {
+        self.parallax
+    }
+
+    pub fn fill(&self) -> &BackgroundFill {
+        &self.fill
+    }
+
+    pub fn color(&self) -> [f32; 4] {
+        self.color
+    }
+
+    pub fn set_color(&mut self, color: [f32; 4]) {
+        self.color = color;
+    }
+
+    /// Gets the position and zoom the layer is seen from, given the camera's position and zoom.
+    pub fn view(&self, camera_position: [f32; 2], camera_zoom: f32) -> ([f32; 2], f32) {
+        (
+            [camera_position[0] * self.parallax, camera_position[1] * self.parallax],
+            camera_zoom.powf(self.parallax),
+        )
+    }
+
+    /// Generates the rectangles needed to cover an area around a center, in the layer's space.
+    pub fn rectangles(&self, center: [f32; 2], area: [f32; 2]) -> Vec<Rectangle> {
+        let low = [center[0] - area[0] * 0.5, center[1] - area[1] * 0.5];
+        let high = [center[0] + area[0] * 0.5, center[1] + area[1] * 0.5];
+        let mut rects = Vec::new();
+
+        match self.fill {
+            BackgroundFill::Tiled { texture, tile_size } => {
+                let (start, end) = cell_range(low, high, tile_size);
+                for y in start[1]..end[1] {
+                    for x in start[0]..end[0] {
+                        let position = [
+                            (x as f32 + 0.5) * tile_size[0], (y as f32 + 0.5) * tile_size[1]
+                        ];
+
+                        let mut rect = Rectangle::new(position, tile_size, texture);
+                        rect.color = self.color;
+                        rects.push(rect);
+                    }
+                }
+            },
+            BackgroundFill::Scatter { ref textures, cell_size, density, size, seed } => {
+                if textures.is_empty() {
+                    return rects;
+                }
+
+                let (start, end) = cell_range(low, high, [cell_size, cell_size]);
+
+                for y in start[1]..end[1] {
+                    for x in start[0]..end[0] {
+                        // Every value we need comes from re-hashing the cell's hash
+                        let mut value = hash(seed, x, y);
+                        let mut next = || {
+                            value = hash(value, x, y);
+                            (value >> 8) as f32 / (1 << 24) as f32
+                        };
+
+                        if next() >= density {
+                            continue;
+                        }
+
+                        let position = [
+                            (x as f32 + next()) * cell_size, (y as f32 + next()) * cell_size
+                        ];
+                        let sprite_size = size[0] + (size[1] - size[0]) * next();
+                        let texture = textures[(next() * textures.len() as f32) as usize % textures.len()];
+
+                        // Vary the brightness a bit so not every sprite looks the same
+                        let brightness = 0.5 + next() * 0.5;
+                        let mut rect = Rectangle::new(position, [sprite_size, sprite_size], texture);
+                        rect.color = [
+                            self.color[0] * brightness, self.color[1] * brightness,
+                            self.color[2] * brightness, self.color[3],
+                        ];
+                        rects.push(rect);
+                    }
+                }
+            },
+        }
+
+        rects
+    }
+}
+
+/// Finds the range of cells, end exclusive, covering an area. The range is clamped around its
+/// center to at most `MAX_CELLS_PER_AXIS` cells along each axis.
+fn cell_range(low: [f32; 2], high: [f32; 2], cell_size: [f32; 2]) -> ([i32; 2], [i32; 2]) {
+    let mut start = [0; 2];
+    let mut end = [0; 2];
+
+    for axis in 0..2 {
+        // Backgrounds decoded from data don't pass through new, so guard the size here as well
+        let size = cell_size[axis].max(MIN_CELL_SIZE);
+        let low = (low[axis] / size).floor();
+        let high = (high[axis] / size).floor() + 1.0;
+
+        let half = (MAX_CELLS_PER_AXIS / 2) as f32;
+        let center = ((low + high) * 0.5).floor();
+        start[axis] = low.max(center - half) as i32;
+        end[axis] = high.min(center + half) as i32;
+    }
+
+    (start, end)
+}
+
+fn hash(seed: u32, x: i32, y: i32) -> u32 {
+    let mut value = seed
+        ^ (x as u32).wrapping_mul(0x27d4eb2d)
+        ^ (y as u32).wrapping_mul(0x165667b1);
+    value ^= value >> 15;
+    value = value.wrapping_mul(0x2c1b3c6d);
+    value ^= value >> 12;
+    value = value.wrapping_mul(0x297a2d39);
+    value ^= value >> 15;
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use frontend::TextureId;
+    use super::{BackgroundInfo, BackgroundFill, MAX_CELLS_PER_AXIS};
+
+    fn tiled(tile_size: [f32; 2]) -> BackgroundInfo {
+        BackgroundInfo::new(1.0, BackgroundFill::Tiled {
+            texture: TextureId::from_raw(0),
+            tile_size: tile_size,
+        })
+    }
+
+    #[test]
+    fn raises_zero_sizes() {
+        let rects = tiled([0.0, 0.0]).rectangles([0.0, 0.0], [4.0, 4.0]);
+        assert!(!rects.is_empty());
+        assert!(rects.iter().all(|r| r.size == [1.0, 1.0]));
+    }
+
+    #[test]
+    fn clamps_large_areas_instead_of_dropping_them() {
+        let max = MAX_CELLS_PER_AXIS as usize;
+        let rects = tiled([1.0, 1.0]).rectangles([0.0, 0.0], [100000.0, 100000.0]);
+        assert_eq!(rects.len(), max * max);
+
+        let scatter = BackgroundInfo::new(1.0, BackgroundFill::Scatter {
+            textures: vec!(TextureId::from_raw(0)),
+            cell_size: 1.0,
+            density: 1.0,
+            size: [1.0, 1.0],
+            seed: 0,
+        });
+        let rects = scatter.rectangles([0.0, 0.0], [100000.0, 100000.0]);
+        assert_eq!(rects.len(), max * max);
+    }
+
+    #[test]
+    fn covers_the_area() {
+        let rects = tiled([10.0, 10.0]).rectangles([5.0, 5.0], [20.0, 20.0]);
+        // -5 to 15 on both axes touches cells -1, 0 and 1
+        assert_eq!(rects.len(), 9);
+    }
+}
@@ -0,0 +1,248 @@
+use std::f32::consts::PI;
+use std::sync::{Arc, Mutex};
+use font;
+use frontend::{Rectangle, TextureId};
+
+const CIRCLE_SEGMENTS: usize = 24;
+
+/// The most squares a single line is made of, so very long lines don't generate millions of
+/// rectangles. Longer lines have gaps between their squares.
+const MAX_LINE_STEPS: usize = 1024;
+
+/// The thinnest lines can be. Lines are made of squares as wide as the line, so thinner lines would
+/// need endlessly many of them.
+const MIN_THICKNESS: f32 = 0.5;
+
+#[derive(Clone, Debug, PartialEq)]
+enum DebugShape {
+    Line([f32; 2], [f32; 2], [f32; 4]),
+    Box([f32; 2], [f32; 2], [f32; 4]),
+    Text([f32; 2], f32, String, [f32; 4]),
+}
+
+struct DebugShapes {
+    shapes: Vec<DebugShape>,
+    enabled: bool,
+    thickness: f32,
+}
+
+/// Draws shapes in world space over everything else, for seeing things that otherwise aren't
+/// visible, like velocities and collision bounds. Shapes are cleared after every frame, so they
+/// have to be drawn again every update.
+///
+/// Get one from `Frontend2D::debug_draw` and keep it in the model, clones all draw to the same
+/// frame.
+#[derive(Clone)]
+pub struct DebugDraw {
+    shared: Arc<Mutex<DebugShapes>>,
+}
+
+impl DebugDraw {
+    /// Creates a debug draw that's enabled in debug builds only.
+    pub fn new() -> Self {
+        DebugDraw {
+            shared: Arc::new(Mutex::new(DebugShapes {
+                shapes: Vec::new(),
+                enabled: cfg!(debug_assertions),
+                thickness: 2.0,
+            })),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.shared.lock().unwrap().enabled
+    }
+
+    /// Sets if shapes are drawn, shapes drawn while disabled are thrown away.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.shared.lock().unwrap().enabled = enabled;
+    }
+
+    pub fn toggle(&self) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.enabled = !shared.enabled;
+    }
+
+    /// Sets the thickness of lines in world units, 2.0 by default. Thicknesses below 0.5 are
+    /// raised to 0.5.
+    pub fn set_thickness(&self, thickness: f32) {
+        self.shared.lock().unwrap().thickness = thickness.max(MIN_THICKNESS);
+    }
+
+    pub fn line(&self, from: [f32; 2], to: [f32; 2], color: [f32; 4]) {
+        self.push(DebugShape::Line(from, to, color));
+    }
+
+    /// Draws a line with a head at `to`, such as a velocity starting at `from`.
+    pub fn arrow(&self, from: [f32; 2], to: [f32; 2], color: [f32; 4]) {
+        self.line(from, to, color);
+
+        let direction = [to[0] - from[0], to[1] - from[1]];
+        let length = (direction[0] * direction[0] + direction[1] * direction[1]).sqrt();
+        if length == 0.0 {
+            return;
+        }
+
+        // The head is a quarter of the arrow, so short arrows don't turn into only a head
+        let head = (length * 0.25).min(16.0);
+        let back = [-direction[0] / length * head, -direction[1] / length * head];
+        for &angle in [PI / 6.0, -PI / 6.0].iter() {
+            let (sin, cos) = angle.sin_cos();
+            let end = [
+                to[0] + back[0] * cos - back[1] * sin,
+                to[1] + back[0] * sin + back[1] * cos,
+            ];
+            self.line(to, end, color);
+        }
+    }
+
+    /// Draws the outline of a box, centered on the position like rectangles are.
+    pub fn box_outline(&self, position: [f32; 2], size: [f32; 2], color: [f32; 4]) {
+        self.push(DebugShape::Box(position, size, color));
+    }
+
+    pub fn circle(&self, center: [f32; 2], radius: f32, color: [f32; 4]) {
+        let point = |i: usize| {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * 2.0 * PI;
+            [center[0] + angle.cos() * radius, center[1] + angle.sin() * radius]
+        };
+
+        for i in 0..CIRCLE_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    /// Draws a text label with its bottom left at the position. Only digits, letters and some
+    /// punctuation are supported, lowercase letters are drawn as uppercase.
+    pub fn text(&self, position: [f32; 2], height: f32, text: &str, color: [f32; 4]) {
+        self.push(DebugShape::Text(position, height, text.to_string(), color));
+    }
+
+    fn push(&self, shape: DebugShape) {
+        let mut shared = self.shared.lock().unwrap();
+        if shared.enabled {
+            shared.shapes.push(shape);
+        }
+    }
+
+    /// Takes all shapes drawn since the last call as rectangles using a plain white texture.
+    pub fn take_rectangles(&self, white: TextureId) -> Vec<Rectangle> {
+        let mut shared = self.shared.lock().unwrap();
+        let thickness = shared.thickness;
+
+        let mut rectangles = Vec::new();
+        for shape in shared.shapes.drain(..) {
+            match shape {
+                DebugShape::Line(from, to, color) =>
+                    line_rectangles(&mut rectangles, from, to, thickness, white, color),
+                DebugShape::Box(position, size, color) =>
+                    box_rectangles(&mut rectangles, position, size, thickness, white, color),
+                DebugShape::Text(position, height, text, color) =>
+                    font::text_rectangles(&mut rectangles, position, height, &text, white, color),
+            }
+        }
+        rectangles
+    }
+}
+
+fn colored(position: [f32; 2], size: [f32; 2], texture: TextureId, color: [f32; 4]) -> Rectangle {
+    let mut rect = Rectangle::new(position, size, texture);
+    rect.color = color;
+    rect
+}
+
+/// Rectangles can't be rotated, so lines are made of overlapping squares along the line. Lines
+/// that are straight across or up can be a single rectangle.
+fn line_rectangles(
+    rectangles: &mut Vec<Rectangle>, from: [f32; 2], to: [f32; 2], thickness: f32,
+    texture: TextureId, color: [f32; 4]
+) {
+    // There's no sensible way to draw towards infinity
+    if !(from[0].is_finite() && from[1].is_finite() && to[0].is_finite() && to[1].is_finite()) {
+        return;
+    }
+
+    let center = [(from[0] + to[0]) * 0.5, (from[1] + to[1]) * 0.5];
+    let delta = [to[0] - from[0], to[1] - from[1]];
+    if delta[0] == 0.0 || delta[1] == 0.0 {
+        let size = [delta[0].abs() + thickness, delta[1].abs() + thickness];
+        rectangles.push(colored(center, size, texture, color));
+        return;
+    }
+
+    let length = (delta[0] * delta[0] + delta[1] * delta[1]).sqrt();
+    let steps = (length / (thickness * 0.5)).ceil().min(MAX_LINE_STEPS as f32) as usize;
+    for i in 0..steps + 1 {
+        let factor = i as f32 / steps as f32;
+        let position = [from[0] + delta[0] * factor, from[1] + delta[1] * factor];
+        rectangles.push(colored(position, [thickness, thickness], texture, color));
+    }
+}
+
+fn box_rectangles(
+    rectangles: &mut Vec<Rectangle>, position: [f32; 2], size: [f32; 2], thickness: f32,
+    texture: TextureId, color: [f32; 4]
+) {
+    let half = [size[0] * 0.5, size[1] * 0.5];
+    let low = [position[0] - half[0], position[1] - half[1]];
+    let high = [position[0] + half[0], position[1] + half[1]];
+
+    line_rectangles(rectangles, [low[0], low[1]], [high[0], low[1]], thickness, texture, color);
+    line_rectangles(rectangles, [low[0], high[1]], [high[0], high[1]], thickness, texture, color);
+    line_rectangles(rectangles, [low[0], low[1]], [low[0], high[1]], thickness, texture, color);
+    line_rectangles(rectangles, [high[0], low[1]], [high[0], high[1]], thickness, texture, color);
+}
+
+#[cfg(test)]
+mod tests {
+    use frontend::TextureId;
+    use super::DebugDraw;
+
+    #[test]
+    fn turns_shapes_into_rectangles_once() {
+        let debug = DebugDraw::new();
+        debug.set_enabled(true);
+        let white = TextureId::from_raw(0);
+        debug.set_thickness(2.0);
+        debug.line([0.0, 0.0], [10.0, 0.0], [1.0, 0.0, 0.0, 1.0]);
+        debug.text([0.0, 0.0], 5.0, "1", [1.0, 1.0, 1.0, 1.0]);
+
+        // The straight line is a single rectangle, the 1 has 8 pixels
+        let rectangles = debug.take_rectangles(white);
+        assert_eq!(rectangles.len(), 1 + 8);
+        assert_eq!(rectangles[0].position, [5.0, 0.0]);
+        assert_eq!(rectangles[0].size, [12.0, 2.0]);
+        assert_eq!(rectangles[1].position, [1.5, 4.5]);
+
+        // Shapes only last a frame, and aren't kept while disabled
+        assert!(debug.take_rectangles(white).is_empty());
+        debug.set_enabled(false);
+        debug.line([0.0, 0.0], [10.0, 10.0], [1.0, 1.0, 1.0, 1.0]);
+        assert!(debug.take_rectangles(white).is_empty());
+    }
+
+    #[test]
+    fn raises_thickness_to_the_minimum() {
+        let debug = DebugDraw::new();
+        debug.set_enabled(true);
+        debug.set_thickness(0.0);
+        debug.line([0.0, 0.0], [1.0, 1.0], [1.0, 1.0, 1.0, 1.0]);
+
+        // The diagonal is about 1.41 long, with squares every 0.25 units
+        let rectangles = debug.take_rectangles(TextureId::from_raw(0));
+        assert_eq!(rectangles.len(), 7);
+        assert!(rectangles.iter().all(|r| r.size == [0.5, 0.5]));
+    }
+
+    #[test]
+    fn limits_long_and_infinite_lines() {
+        let debug = DebugDraw::new();
+        debug.set_enabled(true);
+        debug.line([0.0, 0.0], [1.0e9, 1.0e9], [1.0, 1.0, 1.0, 1.0]);
+        debug.line([0.0, 0.0], [::std::f32::INFINITY, 1.0], [1.0, 1.0, 1.0, 1.0]);
+        debug.line([::std::f32::NAN, 0.0], [1.0, 1.0], [1.0, 1.0, 1.0, 1.0]);
+
+        let rectangles = debug.take_rectangles(TextureId::from_raw(0));
+        assert_eq!(rectangles.len(), super::MAX_LINE_STEPS + 1);
+    }
+}
@@ -0,0 +1,59 @@
+use std::fmt;
+use frontend::TextureId;
+
+/// An error that happened in the frontend's runtime. Fatal errors mean the runtime has stopped and
+/// nothing will be rendered anymore, recoverable errors only affect what they say they affect.
+#[derive(Clone, Debug)]
+pub enum FrontendError {
+    /// The window or its OpenGL context could not be created. Fatal.
+    WindowCreation(String),
+    /// The built in shaders could not be compiled. Fatal.
+    ProgramCreation(String),
+    /// The OpenGL context was lost. Fatal.
+    ContextLost,
+    /// The runtime stopped and can't receive commands anymore. Fatal.
+    RuntimeStopped,
+    /// A texture could not be loaded, a placeholder is used in its place. Recoverable.
+    TextureLoad(TextureId, String),
+    /// Something went wrong drawing, part of the frame may be missing. Recoverable.
+    Draw(String),
+    /// The frame could not be presented, it will be skipped. Recoverable.
+    Present(String),
+    /// A frame could not be recorded, the recorder was stopped. Recoverable.
+    Recording(String),
+}
+
+impl FrontendError {
+    pub fn is_fatal(&self) -> bool {
+        match *self {
+            FrontendError::WindowCreation(_) |
+            FrontendError::ProgramCreation(_) |
+            FrontendError::ContextLost |
+            FrontendError::RuntimeStopped => true,
+            FrontendError::TextureLoad(_, _) |
+            FrontendError::Draw(_) |
+            FrontendError::Present(_) |
+            FrontendError::Recording(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for FrontendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FrontendError::WindowCreation(ref e) => write!(f, "Could not create window: {}", e),
+            FrontendError::ProgramCreation(ref e) => write!(f, "Could not create shader program: {}", e),
+            FrontendError::ContextLost => write!(f, "The OpenGL context was lost"),
+            FrontendError::RuntimeStopped => write!(f, "The frontend runtime has stopped"),
+            FrontendError::TextureLoad(id, ref e) =>
+                write!(f, "Could not load texture {}: {}", id.raw(), e),
+            FrontendError::Draw(ref e) => write!(f, "Could not draw: {}", e),
+            FrontendError::Present(ref e) => write!(f, "Could not present frame: {}", e),
+            FrontendError::Recording(ref e) => write!(f, "Could not record frame: {}", e),
+        }
+    }
+}
+
+pub struct FrontendErrorEvent {
+    pub error: FrontendError,
+}
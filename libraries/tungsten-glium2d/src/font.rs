@@ -0,0 +1,105 @@
+use frontend::{Rectangle, TextureId};
+
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+
+/// Returns how wide text is when drawn at the given height.
+pub fn text_width(text: &str, height: f32) -> f32 {
+    let characters = text.chars().count();
+    if characters == 0 {
+        return 0.0;
+    }
+
+    // Every glyph has a pixel of space after it, except for the last
+    let pixel = height / GLYPH_HEIGHT as f32;
+    (characters * (GLYPH_WIDTH + 1) - 1) as f32 * pixel
+}
+
+/// Adds a rectangle for every pixel of the text, with its bottom left at the position. This is a
+/// tiny 3x5 font so text can be drawn without any assets, only digits, letters and some
+/// punctuation are supported. Lowercase letters are drawn as uppercase.
+pub fn text_rectangles(
+    rectangles: &mut Vec<Rectangle>, position: [f32; 2], height: f32, text: &str,
+    texture: TextureId, color: [f32; 4]
+) {
+    let pixel = height / GLYPH_HEIGHT as f32;
+
+    for (i, character) in text.chars().enumerate() {
+        // Glyphs are a pixel apart, unknown characters are left blank
+        let left = position[0] + (i * (GLYPH_WIDTH + 1)) as f32 * pixel;
+        let glyph = glyph(character.to_ascii_uppercase()).unwrap_or(0);
+
+        for row in 0..GLYPH_HEIGHT {
+            for column in 0..GLYPH_WIDTH {
+                let bit = (GLYPH_HEIGHT - 1 - row) * GLYPH_WIDTH + (GLYPH_WIDTH - 1 - column);
+                if glyph & (1 << bit) == 0 {
+                    continue;
+                }
+
+                let center = [
+                    left + (column as f32 + 0.5) * pixel,
+                    position[1] + (GLYPH_HEIGHT - row) as f32 * pixel - pixel * 0.5,
+                ];
+                let mut rect = Rectangle::new(center, [pixel, pixel], texture);
+                rect.color = color;
+                rectangles.push(rect);
+            }
+        }
+    }
+}
+
+/// Returns a 3x5 glyph with one bit per pixel, top left first.
+fn glyph(character: char) -> Option<u16> {
+    let rows: [u8; 5] = match character {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b011, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '(' => [0b010, 0b100, 0b100, 0b100, 0b010],
+        ')' => [0b010, 0b001, 0b001, 0b001, 0b010],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        '?' => [0b111, 0b001, 0b011, 0b000, 0b010],
+        _ => return None,
+    };
+
+    Some(rows.iter().fold(0, |glyph, &row| (glyph << GLYPH_WIDTH) | row as u16))
+}
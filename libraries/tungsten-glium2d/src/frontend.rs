@@ -1,17 +1,98 @@
-use std::thread::JoinHandle;
-use std::sync::mpsc::{self, Sender, Receiver};
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Sender, Receiver, TryRecvError};
 use glium::glutin::Event;
-use tungsten::{Frontend, EventDispatcher};
+use tungsten::{Frontend, EventDispatcher, AssetSource};
+use background::BackgroundInfo;
+use debug::DebugDraw;
+use error::{FrontendError, FrontendErrorEvent};
+use gamepad::{GamepadBackend, GamepadTracker, GamepadInput, Deadzones, GamepadConnectedEvent, GamepadDisconnectedEvent, GamepadButtonEvent, GamepadAxisEvent};
+use input::{InputState, InputStateEvent};
+use recording::FrameRecorder;
 use runtime::FrontendRuntime;
+use software::SoftwareRuntime;
+use stats::{RenderStats, RenderStatsEvent};
+use texture::{TextureOptions, TextureSource, TextureLoader};
+use tilemap::TileMap;
+use viewport::Viewport;
 use {Key, KeyState};
 
+/// The size of the area in world units cameras show at a zoom of 1.0.
+pub const VIEW_SIZE: [f32; 2] = [1280.0, 720.0];
+
+/// The amount of frames that can be built while the runtime is still rendering earlier ones.
+const DEFAULT_FRAMES_IN_FLIGHT: usize = 2;
+
+/// How often window events are checked while minimized, instead of rendering frames. Render waits
+/// this long on every call while minimized, which also slows down the framework's update loop.
+const MINIMIZED_POLL_INTERVAL_MS: u64 = 50;
+
 pub struct CloseRequestEvent;
 
 pub struct KeyboardInputEvent {
-    pub key: Key,
+    /// The platform specific code of the physical key, this is always there even for keys that
+    /// don't have a virtual key.
+    pub scancode: u8,
+    pub key: Option<Key>,
     pub state: KeyState,
 }
 
+/// Raised for every character typed, after the keyboard layout and things like dead keys have
+/// been applied. Use this rather than `KeyboardInputEvent` for text entry.
+pub struct TextInputEvent {
+    pub character: char,
+}
+
+/// Raised when the window gained or lost focus. All held keys and buttons are released when it
+/// loses focus, as we won't see them being released.
+pub struct FocusEvent {
+    pub focused: bool,
+}
+
+/// Raised when the window was minimized or restored. Nothing is rendered while minimized, and the
+/// update loop slows down to about 20 updates a second to keep the CPU idle.
+pub struct MinimizeEvent {
+    pub minimized: bool,
+}
+
+/// Raised when the window was resized, with its new size in pixels. This isn't raised when
+/// minimized.
+pub struct WindowResizedEvent {
+    pub size: [u32; 2],
+}
+
+/// Raised when the window was moved, with its new position on the screen.
+pub struct WindowMovedEvent {
+    pub position: [i32; 2],
+}
+
+/// Raised when a file was dragged onto the window and dropped.
+pub struct FileDroppedEvent {
+    pub path: PathBuf,
+}
+
+/// Raised when a texture or shader file changed on disk and has been reloaded, or failed to
+/// reload.
+pub struct AssetReloadEvent {
+    pub path: PathBuf,
+    pub result: Result<(), String>,
+}
+
+/// Raised when frames were skipped because the runtime was still busy with all frames in flight.
+pub struct FramesDroppedEvent {
+    /// The amount of frames dropped since the last event.
+    pub dropped: u32,
+    /// The total amount of frames dropped since the frontend was created.
+    pub total: u64,
+}
+
+/// Raised when a screenshot or captured frame has been saved, or failed to save.
+pub struct ScreenshotEvent {
+    pub result: Result<PathBuf, String>,
+}
+
 pub trait View2D<M> {
     fn render(&mut self, model: &M, info: &mut FrameRenderInfo);
 }
@@ -23,33 +104,100 @@ impl<M, F: Fn(&M, &mut FrameRenderInfo)> View2D<M> for F {
 }
 
 pub enum FrontendCommand {
-    Frame(FrameRenderInfo),
-    LoadTexture(TextureId, String),
+    /// A frame to render, with how long it took to build.
+    Frame(FrameRenderInfo, Duration),
+    /// Checks for window events without rendering, used while there's nothing to render to.
+    PollEvents,
+    LoadTexture(TextureId, TextureSource, TextureOptions),
+    /// Frees a texture for re-use, its ID is never given out again and drawing with it draws the
+    /// placeholder for missing textures. Unloading a texture twice does nothing.
+    UnloadTexture(TextureId),
+    /// Replaces the built in shaders with vertex and fragment shaders from files.
+    LoadShaders(PathBuf, PathBuf),
+    SetHotReload(bool),
+    CreateTileMap(TileMapId, TileMap),
+    SetTile(TileMapId, [u32; 2], Option<u32>),
+    /// Saves the next rendered frame as a PNG at the path.
+    Screenshot(PathBuf),
+    /// Saves every rendered frame as a numbered PNG in the directory until stopped.
+    StartCapture(PathBuf),
+    StopCapture,
+}
+
+/// A frame returned by the runtime to be re-used, with the stats of rendering it. Frames that
+/// haven't been rendered yet don't have stats.
+pub type ReturnedFrame = (FrameRenderInfo, Option<RenderStats>);
+
+pub enum RuntimeEvent {
+    Window(Event),
+    Screenshot(Result<PathBuf, String>),
+    Reload(PathBuf, Result<(), String>),
+    Error(FrontendError),
 }
 
 pub struct Frontend2D<M> {
     view: Option<Box<View2D<M>>>,
     _runtime_handle: JoinHandle<()>, // TODO: make sure the thread is told to gracefully stop
 
-    event_recv: Receiver<Event>,
+    event_recv: Receiver<RuntimeEvent>,
     command_send: Sender<FrontendCommand>,
-    batch_return_recv: Receiver<FrameRenderInfo>,
-
-    texture_id_counter: u32,
+    batch_return_send: Sender<ReturnedFrame>,
+    batch_return_recv: Receiver<ReturnedFrame>,
+    frames_in_flight: usize,
+    frames_total: usize, // Including the ones in flight
+    dropped_frames: u64,
+    dropped_unreported: u32,
+    unreported_stats: Vec<RenderStats>,
+    unreported_errors: Vec<FrontendError>,
+
+    recorder: Option<FrameRecorder>,
+    asset_source: AssetSource,
+    input: InputState,
+    input_state_events: bool,
+    minimized: bool,
+    debug: DebugDraw,
+    white_texture: Option<TextureId>,
+    debug_toggle_key: Option<Key>,
+    gamepad_backend: Option<Box<GamepadBackend>>,
+    gamepads: GamepadTracker,
+
+    /// Shared with texture loaders, IDs have to reach the runtime in order.
+    texture_ids: Arc<Mutex<u32>>,
+    tilemap_id_counter: u32,
+
+    runtime_stopped: bool,
+    stop_reported: bool,
 }
 
 impl<M> Frontend2D<M> {
     pub fn new() -> Self {
+        Self::start(|event_send, command_recv, batch_return_send| {
+            FrontendRuntime::start(event_send, command_recv, batch_return_send)
+        })
+    }
+
+    /// Creates a frontend without a window that renders frames on the CPU into an image of the
+    /// given size. Frames can be saved through `screenshot` and `start_capture`.
+    pub fn headless(width: u32, height: u32) -> Self {
+        Self::start(move |event_send, command_recv, batch_return_send| {
+            SoftwareRuntime::start(event_send, command_recv, batch_return_send, [width, height])
+        })
+    }
+
+    fn start<F>(start_runtime: F) -> Self where
+        F: FnOnce(Sender<RuntimeEvent>, Receiver<FrontendCommand>, Sender<ReturnedFrame>) -> JoinHandle<()> {
         // Set up all the channels
         let (event_send, event_recv) = mpsc::channel();
         let (command_send, command_recv) = mpsc::channel();
         let (batch_return_send, batch_return_recv) = mpsc::channel();
 
-        // Stick a single batch into the send-return loop to start out with
-        batch_return_send.send(FrameRenderInfo::new()).unwrap();
+        // Stick the batches we can have in flight into the send-return loop to start out with
+        for _ in 0..DEFAULT_FRAMES_IN_FLIGHT {
+            batch_return_send.send((FrameRenderInfo::new(), None)).unwrap();
+        }
 
         // Start up the runtime
-        let handle = FrontendRuntime::start(event_send, command_recv, batch_return_send);
+        let handle = start_runtime(event_send, command_recv, batch_return_send.clone());
 
         Frontend2D {
             view: None,
@@ -57,76 +205,476 @@ impl<M> Frontend2D<M> {
 
             event_recv: event_recv,
             command_send: command_send,
+            batch_return_send: batch_return_send,
             batch_return_recv: batch_return_recv,
+            frames_in_flight: DEFAULT_FRAMES_IN_FLIGHT,
+            frames_total: DEFAULT_FRAMES_IN_FLIGHT,
+            dropped_frames: 0,
+            dropped_unreported: 0,
+            unreported_stats: Vec::new(),
+            unreported_errors: Vec::new(),
+
+            recorder: None,
+            asset_source: AssetSource::new(
+                AssetSource::find_root("assets").unwrap_or_else(|| PathBuf::from("."))
+            ),
+            input: InputState::new(),
+            input_state_events: false,
+            minimized: false,
+            debug: DebugDraw::new(),
+            white_texture: None,
+            debug_toggle_key: Some(Key::F3),
+            gamepad_backend: None,
+            gamepads: GamepadTracker::new(Deadzones::default()),
+
+            texture_ids: Arc::new(Mutex::new(0)),
+            tilemap_id_counter: 0,
+
+            runtime_stopped: false,
+            stop_reported: false,
+        }
+    }
+
+    /// Returns true if the runtime has stopped, after this nothing will be rendered anymore.
+    pub fn runtime_stopped(&self) -> bool {
+        self.runtime_stopped
+    }
+
+    fn send(&mut self, command: FrontendCommand) {
+        // If the runtime is gone this fails, which we report as an event instead of panicking
+        if self.command_send.send(command).is_err() {
+            self.runtime_stopped = true;
+        }
+    }
+
+    /// Returns the input state as of the last update.
+    pub fn input(&self) -> &InputState {
+        &self.input
+    }
+
+    /// Sets if an `InputStateEvent` with a copy of the input state is raised every update, for
+    /// handlers that can't reach the frontend. Off by default.
+    pub fn set_input_state_events(&mut self, enabled: bool) {
+        self.input_state_events = enabled;
+    }
+
+    /// Returns true if the window is minimized, frames aren't rendered while it is.
+    pub fn is_minimized(&self) -> bool {
+        self.minimized
+    }
+
+    /// Sets how many frames can be built while the runtime is still rendering earlier ones, 2 by
+    /// default. More frames in flight means fewer dropped frames, but more latency.
+    pub fn set_frames_in_flight(&mut self, frames: usize) {
+        assert!(frames > 0);
+        self.frames_in_flight = frames;
+
+        // Add any new frames right away, extra frames are dropped as they come back
+        while self.frames_total < frames {
+            self.batch_return_send.send((FrameRenderInfo::new(), None)).unwrap();
+            self.frames_total += 1;
+        }
+    }
+
+    pub fn frames_in_flight(&self) -> usize {
+        self.frames_in_flight
+    }
 
-            texture_id_counter: 0,
+    /// Returns the total amount of frames dropped because none was free to build.
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped_frames
+    }
+
+    /// Returns the debug draw for this frontend, keep it in the model to draw from any handler.
+    /// What's drawn on it shows up over everything else on the next frame.
+    pub fn debug_draw(&mut self) -> DebugDraw {
+        // Debug shapes are all drawn with the white texture, make sure it's there
+        self.white_texture();
+        self.debug.clone()
+    }
+
+    /// Returns a plain white texture, for drawing rectangles that only have a color. It's only
+    /// loaded the first time it's asked for.
+    pub fn white_texture(&mut self) -> TextureId {
+        if let Some(white) = self.white_texture {
+            return white;
         }
+
+        let white = self.load_texture_rgba(vec!(255; 4), [1, 1], TextureOptions::pixel_art());
+        self.white_texture = Some(white);
+        white
+    }
+
+    /// Sets the key that turns debug drawing on and off, F3 by default.
+    pub fn set_debug_toggle_key(&mut self, key: Option<Key>) {
+        self.debug_toggle_key = key;
+    }
+
+    /// Sets where gamepad input comes from, there's no gamepad input without one. With the
+    /// `gamepads` feature, `GilrsGamepads` gives input from gamepads connected to the computer.
+    pub fn set_gamepad_backend<B: GamepadBackend + 'static>(&mut self, backend: B) {
+        self.gamepad_backend = Some(Box::new(backend));
+    }
+
+    pub fn set_gamepad_deadzones(&mut self, deadzones: Deadzones) {
+        self.gamepads.set_deadzones(deadzones);
     }
 
     pub fn set_view<V: View2D<M> + 'static>(&mut self, view: V) {
         self.view = Some(Box::new(view));
     }
 
-    pub fn load_texture(&mut self, path: &str) -> TextureId {
-        let id = TextureId::from_raw(self.texture_id_counter);
-        self.texture_id_counter += 1;
+    pub fn asset_root(&self) -> &Path {
+        self.asset_source.root()
+    }
 
-        let command = FrontendCommand::LoadTexture(id, path.into());
-        self.command_send.send(command).unwrap();
+    /// Sets the directory relative paths to assets are resolved from. By default this is the
+    /// `assets` directory found by `AssetSource::find_root`, or the working directory if there is
+    /// none.
+    pub fn set_asset_root<P: Into<PathBuf>>(&mut self, root: P) {
+        self.asset_source = AssetSource::new(root);
+    }
+
+    /// Sets where assets are loaded from, for loading from archives. Loose files in the root of
+    /// the source still take priority over archives.
+    pub fn set_asset_source(&mut self, source: AssetSource) {
+        self.asset_source = source;
+    }
+
+    /// Loads a texture from an image file in the asset source. Only loose files are hot reloaded.
+    pub fn load_texture(&mut self, path: &str, options: TextureOptions) -> TextureId {
+        let source = match self.asset_source.loose_path(Path::new(path)) {
+            Some(loose) => TextureSource::Path(loose),
+            None => match self.asset_source.read(Path::new(path)) {
+                Ok(data) => TextureSource::Encoded(data),
+                // Missing or damaged, the runtime reports it like any other texture that fails
+                Err(_) => TextureSource::Path(self.asset_source.root().join(path)),
+            },
+        };
+        self.load_texture_from(source, options)
+    }
+
+    /// Loads a texture from an encoded image file in memory, such as one from `include_bytes!`.
+    pub fn load_texture_bytes(&mut self, data: &[u8], options: TextureOptions) -> TextureId {
+        self.load_texture_from(TextureSource::Encoded(data.to_vec()), options)
+    }
+
+    /// Loads a texture from raw RGBA pixel data, for example a procedurally generated texture.
+    pub fn load_texture_rgba(&mut self, data: Vec<u8>, size: [u32; 2], options: TextureOptions) -> TextureId {
+        self.load_texture_from(TextureSource::Rgba(data, size), options)
+    }
+
+    pub fn load_texture_from(&mut self, source: TextureSource, options: TextureOptions) -> TextureId {
+        // Hold on to the counter until it's sent, so a loader can't send a later ID before it
+        let texture_ids = self.texture_ids.clone();
+        let mut counter = texture_ids.lock().unwrap();
+        let id = TextureId::from_raw(*counter);
+        *counter += 1;
+
+        let command = FrontendCommand::LoadTexture(id, source, options);
+        self.send(command);
 
         id
     }
+
+    /// Creates a loader for `tungsten::AssetManager` that loads `Texture`s with the options.
+    /// Images are decoded on the asset loading thread.
+    pub fn texture_loader(&self, options: TextureOptions) -> TextureLoader {
+        TextureLoader::new(
+            self.command_send.clone(), self.texture_ids.clone(), self.asset_source.clone(), options
+        )
+    }
+
+    /// Replaces the built in shaders with shaders loaded from files, relative to the asset root.
+    /// Shaders are always loose files, so they can be hot reloaded.
+    /// The result is raised as an `AssetReloadEvent` for the vertex shader, if it failed the
+    /// previous shaders are kept.
+    pub fn load_shaders(&mut self, vertex: &str, fragment: &str) {
+        let command = FrontendCommand::LoadShaders(
+            self.asset_source.root().join(vertex), self.asset_source.root().join(fragment)
+        );
+        self.send(command);
+    }
+
+    /// Sets if texture and shader files should be reloaded when they change on disk, this is
+    /// enabled by default in debug builds. Reloads are raised as `AssetReloadEvent`s.
+    pub fn set_hot_reload(&mut self, enabled: bool) {
+        self.send(FrontendCommand::SetHotReload(enabled));
+    }
+
+    /// Saves the next frame as a PNG, the result is raised as a `ScreenshotEvent`.
+    pub fn screenshot<P: Into<PathBuf>>(&mut self, path: P) {
+        self.send(FrontendCommand::Screenshot(path.into()));
+    }
+
+    /// Starts saving every frame as a numbered PNG in a directory, each raising a
+    /// `ScreenshotEvent`. The directory is created if it doesn't exist yet, if that fails a single
+    /// failed `ScreenshotEvent` is raised and nothing is captured.
+    pub fn start_capture<P: Into<PathBuf>>(&mut self, directory: P) {
+        self.send(FrontendCommand::StartCapture(directory.into()));
+    }
+
+    pub fn stop_capture(&mut self) {
+        self.send(FrontendCommand::StopCapture);
+    }
+
+    /// Sets a recorder that every frame built by the view will be recorded to, or `None` to stop
+    /// recording. If a frame can't be recorded the recorder is stopped, and the error raised as a
+    /// `FrontendErrorEvent`.
+    pub fn set_recorder(&mut self, recorder: Option<FrameRecorder>) {
+        self.recorder = recorder;
+    }
+
+    pub fn create_tilemap(&mut self, tilemap: TileMap) -> TileMapId {
+        let id = TileMapId::from_raw(self.tilemap_id_counter);
+        self.tilemap_id_counter += 1;
+
+        let command = FrontendCommand::CreateTileMap(id, tilemap);
+        self.send(command);
+
+        id
+    }
+
+    pub fn set_tile(&mut self, tilemap: TileMapId, position: [u32; 2], tile: Option<u32>) {
+        let command = FrontendCommand::SetTile(tilemap, position, tile);
+        self.send(command);
+    }
 }
 
 impl<M: 'static> Frontend<M> for Frontend2D<M> {
     fn process_events(&mut self, dispatcher: &mut EventDispatcher<M>, model: &mut M) {
-        // Process all received events
+        // Process all received events, keeping track of the input state as we go
+        self.input.start_frame();
         loop {
-            if let Ok(event) = self.event_recv.try_recv() {
-                match event {
-                    Event::Closed => dispatcher.dispatch(model, CloseRequestEvent),
-                    Event::KeyboardInput(state, _, virtual_key) =>
-                        dispatcher.dispatch(model, KeyboardInputEvent {
-                            key: virtual_key.unwrap(),
+            let event = match self.event_recv.try_recv() {
+                Ok(event) => event,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.runtime_stopped = true;
+                    break;
+                }
+            };
+
+            match event {
+                RuntimeEvent::Window(Event::KeyboardInput(state, _, Some(key))) => {
+                    if state == KeyState::Pressed && Some(key) == self.debug_toggle_key {
+                        self.debug.toggle();
+                    }
+                    self.input.key_input(key, state);
+                },
+                RuntimeEvent::Window(Event::MouseInput(state, button)) =>
+                    self.input.button_input(button, state),
+                RuntimeEvent::Window(Event::MouseMoved((x, y))) =>
+                    self.input.mouse_moved([x, y]),
+                RuntimeEvent::Window(Event::Focused(false)) =>
+                    self.input.release_all(),
+                _ => ()
+            }
+
+            match event {
+                RuntimeEvent::Window(Event::Closed) =>
+                    dispatcher.dispatch(model, CloseRequestEvent),
+                RuntimeEvent::Window(Event::KeyboardInput(state, scancode, virtual_key)) =>
+                    dispatcher.dispatch(model, KeyboardInputEvent {
+                        scancode: scancode,
+                        key: virtual_key,
+                        state: state
+                    }),
+                RuntimeEvent::Window(Event::ReceivedCharacter(character)) =>
+                    dispatcher.dispatch(model, TextInputEvent {
+                        character: character
+                    }),
+                RuntimeEvent::Window(Event::Focused(focused)) =>
+                    dispatcher.dispatch(model, FocusEvent {
+                        focused: focused
+                    }),
+                RuntimeEvent::Window(Event::Resized(width, height)) => {
+                    // There's no separate event for minimizing, the window is resized to nothing
+                    let minimized = width == 0 || height == 0;
+                    if minimized != self.minimized {
+                        self.minimized = minimized;
+                        dispatcher.dispatch(model, MinimizeEvent {
+                            minimized: minimized
+                        });
+                    }
+                    if !minimized {
+                        dispatcher.dispatch(model, WindowResizedEvent {
+                            size: [width, height]
+                        });
+                    }
+                },
+                RuntimeEvent::Window(Event::Moved(x, y)) =>
+                    dispatcher.dispatch(model, WindowMovedEvent {
+                        position: [x, y]
+                    }),
+                RuntimeEvent::Window(Event::DroppedFile(path)) =>
+                    dispatcher.dispatch(model, FileDroppedEvent {
+                        path: path
+                    }),
+                RuntimeEvent::Screenshot(result) =>
+                    dispatcher.dispatch(model, ScreenshotEvent {
+                        result: result
+                    }),
+                RuntimeEvent::Reload(path, result) =>
+                    dispatcher.dispatch(model, AssetReloadEvent {
+                        path: path,
+                        result: result
+                    }),
+                RuntimeEvent::Error(error) => {
+                    // A fatal error already tells the game the runtime stopped
+                    if error.is_fatal() {
+                        self.stop_reported = true;
+                    }
+                    dispatcher.dispatch(model, FrontendErrorEvent {
+                        error: error
+                    });
+                },
+                _ => ()
+            }
+        }
+
+        // Gamepads are polled here rather than on the runtime, they don't need the window
+        let gamepad_input = self.gamepad_backend.as_mut().map(|b| b.poll()).unwrap_or_else(Vec::new);
+        for raw_input in gamepad_input {
+            for input in self.gamepads.process(raw_input) {
+                self.input.gamepad_input(&input);
+
+                match input {
+                    GamepadInput::Connected(gamepad, name) =>
+                        dispatcher.dispatch(model, GamepadConnectedEvent {
+                            gamepad: gamepad,
+                            name: name
+                        }),
+                    GamepadInput::Disconnected(gamepad) =>
+                        dispatcher.dispatch(model, GamepadDisconnectedEvent {
+                            gamepad: gamepad
+                        }),
+                    GamepadInput::Button(gamepad, button, state) =>
+                        dispatcher.dispatch(model, GamepadButtonEvent {
+                            gamepad: gamepad,
+                            button: button,
                             state: state
                         }),
-                    _ => ()
+                    GamepadInput::Axis(gamepad, axis, value) =>
+                        dispatcher.dispatch(model, GamepadAxisEvent {
+                            gamepad: gamepad,
+                            axis: axis,
+                            value: value
+                        }),
                 }
-            } else {
-                break;
             }
         }
+
+        if self.input_state_events {
+            dispatcher.dispatch(model, InputStateEvent {
+                state: self.input.clone()
+            });
+        }
+
+        // Pass on the stats of frames that were rendered
+        for stats in self.unreported_stats.drain(..) {
+            dispatcher.dispatch(model, RenderStatsEvent {
+                stats: stats
+            });
+        }
+
+        // Errors from rendering happen without a dispatcher, so they're passed on here
+        for error in self.unreported_errors.drain(..) {
+            dispatcher.dispatch(model, FrontendErrorEvent {
+                error: error
+            });
+        }
+
+        // Let the game know if we had to skip frames
+        if self.dropped_unreported != 0 {
+            dispatcher.dispatch(model, FramesDroppedEvent {
+                dropped: self.dropped_unreported,
+                total: self.dropped_frames,
+            });
+            self.dropped_unreported = 0;
+        }
+
+        // If the runtime went away without telling us why, let the game know once
+        if self.runtime_stopped && !self.stop_reported {
+            self.stop_reported = true;
+            dispatcher.dispatch(model, FrontendErrorEvent {
+                error: FrontendError::RuntimeStopped
+            });
+        }
     }
 
     fn render(&mut self, model: &M) {
-        // Check if we receiver a batch back from the runtime
-        let mut frame = {
-            if let Ok(frame) = self.batch_return_recv.try_recv() {
-                frame
+        if self.runtime_stopped {
+            return;
+        }
+
+        // Take this update's debug shapes first, so they're cleared even if we skip the frame
+        let debug_rectangles = match self.white_texture {
+            Some(white) => self.debug.take_rectangles(white),
+            None => Vec::new(),
+        };
+
+        // Nothing would be visible, so only keep the window responsive without spinning the CPU,
+        // this throttles the whole update loop on purpose as there's nothing to show for updates
+        if self.minimized {
+            self.send(FrontendCommand::PollEvents);
+            thread::sleep(Duration::from_millis(MINIMIZED_POLL_INTERVAL_MS));
+            return;
+        }
+
+        // Check if we received a batch back from the runtime, dropping any we have too many of
+        let mut returned = None;
+        while let Ok((frame, stats)) = self.batch_return_recv.try_recv() {
+            if let Some(stats) = stats {
+                self.unreported_stats.push(stats);
+            }
+
+            if self.frames_total > self.frames_in_flight {
+                self.frames_total -= 1;
             } else {
-                // We didn't, don't render
-                return;
+                returned = Some(frame);
+                break;
             }
+        }
+        let mut frame = if let Some(frame) = returned {
+            frame
+        } else {
+            // We didn't, so all frames are still in flight and we have to skip this one
+            self.dropped_frames += 1;
+            self.dropped_unreported += 1;
+            return;
         };
 
         // Clear the batch before we continue to use it
         frame.clear();
 
         // Build up a render batch
+        let build_start = Instant::now();
         self.view.as_mut().unwrap().render(model, &mut frame);
+        let build_time = build_start.elapsed();
+
+        // Record the frame if we're asked to, a recorder that failed once is stopped
+        let recorded = self.recorder.as_mut().map(|recorder| recorder.record(&frame));
+        if let Some(Err(e)) = recorded {
+            self.recorder = None;
+            self.unreported_errors.push(FrontendError::Recording(e.to_string()));
+        }
+
+        // Draw the debug shapes over everything, after recording as they're not part of the game
+        frame.debug_overlay(debug_rectangles);
 
         // Send the batch to be rendered
-        self.command_send.send(FrontendCommand::Frame(frame)).unwrap();
+        self.send(FrontendCommand::Frame(frame, build_time));
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, RustcEncodable, RustcDecodable)]
 pub struct TextureId {
     id: u32
 }
 
 impl TextureId {
-    fn from_raw(id: u32) -> Self {
+    pub fn from_raw(id: u32) -> Self {
         TextureId {
             id: id
         }
@@ -137,23 +685,85 @@ impl TextureId {
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, RustcEncodable, RustcDecodable)]
+pub struct TileMapId {
+    id: u32
+}
+
+impl TileMapId {
+    pub fn from_raw(id: u32) -> Self {
+        TileMapId {
+            id: id
+        }
+    }
+
+    pub fn raw(&self) -> u32 {
+        self.id
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, RustcEncodable, RustcDecodable)]
 pub struct Rectangle {
     pub position: [f32; 2],
     pub size: [f32; 2],
     pub texture: TextureId,
+    /// The part of the texture to draw as `[left, bottom, right, top]` in texture coordinates, the
+    /// whole texture by default.
+    pub region: [f32; 4],
+    pub color: [f32; 4],
+    /// Rectangles in the same camera are drawn from low to high depth, so higher depths end up on
+    /// top. Rectangles at the same depth are drawn in the order they were added.
+    ///
+    /// Depth is only sorted between batches added one after another. A tilemap, background or clip
+    /// rectangle added in between splits the sorting, whatever is added after it is drawn over it
+    /// and over the rectangles before it, no matter their depth.
+    pub depth: f32,
+}
+
+impl Rectangle {
+    pub fn new(position: [f32; 2], size: [f32; 2], texture: TextureId) -> Self {
+        Rectangle {
+            position: position,
+            size: size,
+            texture: texture,
+            region: [0.0, 0.0, 1.0, 1.0],
+            color: [1.0, 1.0, 1.0, 1.0],
+            depth: 0.0,
+        }
+    }
 }
 
+/// An area in world units to limit drawing to, positioned at its center like rectangles.
+#[derive(Copy, Clone, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct ClipRect {
+    pub position: [f32; 2],
+    pub size: [f32; 2],
+}
+
+#[derive(Debug, RustcEncodable, RustcDecodable)]
 pub enum LayerInfo {
     Camera(GameCameraInfo),
     Batch(RenderBatchInfo),
+    TileMap(TileMapInfo),
+    Background(BackgroundInfo),
+    PushClip(ClipRect),
+    PopClip,
 }
 
 pub trait RenderTarget {
     fn game_camera(&mut self, position: [f32; 2]) -> &mut GameCameraInfo;
     fn batch(&mut self) -> &mut RenderBatchInfo;
+    fn tilemap(&mut self, tilemap: TileMapId, position: [f32; 2]);
+    fn background(&mut self, background: BackgroundInfo);
+    /// Limits drawing of the layers after this to the clip rectangle, until it's popped again.
+    /// Clip rectangles pushed while another is active only draw where both overlap.
+    fn push_clip(&mut self, clip: ClipRect);
+    /// Stops drawing with the last pushed clip rectangle, does nothing if none is left.
+    fn pop_clip(&mut self);
     fn layers(&self) -> &Vec<LayerInfo>;
 }
 
+#[derive(Debug, RustcEncodable, RustcDecodable)]
 pub struct RenderBatchInfo {
     rectangles: Vec<Rectangle>
 }
@@ -168,8 +778,28 @@ impl RenderBatchInfo {
     }
 }
 
+#[derive(Debug, RustcEncodable, RustcDecodable)]
+pub struct TileMapInfo {
+    tilemap: TileMapId,
+    position: [f32; 2],
+}
+
+impl TileMapInfo {
+    pub fn tilemap(&self) -> TileMapId {
+        self.tilemap
+    }
+
+    pub fn position(&self) -> [f32; 2] {
+        self.position
+    }
+}
+
+#[derive(Debug, RustcEncodable, RustcDecodable)]
 pub struct GameCameraInfo {
     position: [f32; 2],
+    zoom: f32,
+    viewport: Viewport,
+    clip_depth: u32,
     layers: Vec<LayerInfo>,
 }
 
@@ -177,6 +807,26 @@ impl GameCameraInfo {
     pub fn position(&self) -> [f32; 2] {
         self.position
     }
+
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    /// Sets the zoom of the camera, 2.0 shows everything at twice the size.
+    pub fn set_zoom(&mut self, zoom: f32) {
+        assert!(zoom > 0.0);
+        self.zoom = zoom;
+    }
+
+    pub fn viewport(&self) -> Viewport {
+        self.viewport
+    }
+
+    /// Sets the part of the screen the camera draws to, anything outside of it is left untouched.
+    /// By default cameras draw to the whole screen.
+    pub fn set_viewport(&mut self, viewport: Viewport) {
+        self.viewport = viewport;
+    }
 }
 
 impl RenderTarget for GameCameraInfo {
@@ -197,17 +847,43 @@ impl RenderTarget for GameCameraInfo {
         unreachable!();
     }
 
+    fn tilemap(&mut self, tilemap: TileMapId, position: [f32; 2]) {
+        self.layers.push(LayerInfo::TileMap(TileMapInfo {
+            tilemap: tilemap,
+            position: position,
+        }));
+    }
+
+    fn background(&mut self, background: BackgroundInfo) {
+        self.layers.push(LayerInfo::Background(background));
+    }
+
+    fn push_clip(&mut self, clip: ClipRect) {
+        self.clip_depth += 1;
+        self.layers.push(LayerInfo::PushClip(clip));
+    }
+
+    fn pop_clip(&mut self) {
+        // Popping more than was pushed would leave the renderers without a clip to return to
+        if self.clip_depth == 0 {
+            return;
+        }
+        self.clip_depth -= 1;
+        self.layers.push(LayerInfo::PopClip);
+    }
+
     fn layers(&self) -> &Vec<LayerInfo> {
         &self.layers
     }
 }
 
+#[derive(Debug, RustcEncodable, RustcDecodable)]
 pub struct FrameRenderInfo {
     layers: Vec<LayerInfo>,
 }
 
 impl FrameRenderInfo {
-    fn new() -> Self {
+    pub fn new() -> Self {
         FrameRenderInfo {
             layers: Vec::new(),
         }
@@ -216,12 +892,41 @@ impl FrameRenderInfo {
     fn clear(&mut self) {
         self.layers.clear();
     }
+
+    /// Adds a camera over all others with the rectangles for every camera in the frame, seeing the
+    /// same world area in the same part of the window, so split screen views all get them.
+    fn debug_overlay(&mut self, rectangles: Vec<Rectangle>) {
+        if rectangles.is_empty() {
+            return;
+        }
+
+        let mut views: Vec<_> = self.layers.iter()
+            .filter_map(|l| if let &LayerInfo::Camera(ref cam) = l { Some(cam) } else { None })
+            .map(|cam| (cam.position(), cam.zoom(), cam.viewport()))
+            .collect();
+        if views.is_empty() {
+            views.push(([0.0, 0.0], 1.0, Viewport::full()));
+        }
+
+        for (position, zoom, viewport) in views {
+            let camera = self.game_camera(position);
+            camera.set_zoom(zoom);
+            camera.set_viewport(viewport);
+            let batch = camera.batch();
+            for rect in &rectangles {
+                batch.rectangle(rect.clone());
+            }
+        }
+    }
 }
 
 impl RenderTarget for FrameRenderInfo {
     fn game_camera(&mut self, position: [f32; 2]) -> &mut GameCameraInfo {
         let cam = GameCameraInfo {
             position: position,
+            zoom: 1.0,
+            viewport: Viewport::full(),
+            clip_depth: 0,
             layers: Vec::new(),
         };
 
@@ -238,6 +943,20 @@ impl RenderTarget for FrameRenderInfo {
         unimplemented!();
     }
 
+    // Frames only hold cameras, everything else is drawn from a camera so it's ignored here
+
+    fn tilemap(&mut self, _tilemap: TileMapId, _position: [f32; 2]) {
+    }
+
+    fn background(&mut self, _background: BackgroundInfo) {
+    }
+
+    fn push_clip(&mut self, _clip: ClipRect) {
+    }
+
+    fn pop_clip(&mut self) {
+    }
+
     fn layers(&self) -> &Vec<LayerInfo> {
         &self.layers
     }
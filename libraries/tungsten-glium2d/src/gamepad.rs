@@ -0,0 +1,311 @@
+use std::sync::{Arc, Mutex};
+use KeyState;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GamepadId {
+    id: u32
+}
+
+impl GamepadId {
+    pub fn from_raw(id: u32) -> Self {
+        GamepadId {
+            id: id
+        }
+    }
+
+    pub fn raw(&self) -> u32 {
+        self.id
+    }
+}
+
+/// Buttons named by their position, so `South` is A on an Xbox controller and Cross on a
+/// PlayStation controller.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadButton {
+    South,
+    East,
+    West,
+    North,
+    LeftBumper,
+    RightBumper,
+    Select,
+    Start,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+/// Sticks go from -1.0 to 1.0 with up being positive, triggers go from 0.0 to 1.0.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+const AXIS_COUNT: usize = 6;
+
+/// Input coming from a gamepad backend. The frontend applies deadzones to it and dispatches it as
+/// events.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GamepadInput {
+    Connected(GamepadId, String),
+    Disconnected(GamepadId),
+    Button(GamepadId, GamepadButton, KeyState),
+    Axis(GamepadId, GamepadAxis, f32),
+}
+
+/// A source of gamepad input, set on the frontend with `Frontend2D::set_gamepad_backend`.
+pub trait GamepadBackend {
+    /// Returns all input since the last poll, oldest first.
+    fn poll(&mut self) -> Vec<GamepadInput>;
+}
+
+/// A gamepad backend that only has gamepads created in code, for testing gameplay without
+/// hardware. Clones share the same gamepads, so keep a clone to connect gamepads after giving one
+/// to the frontend.
+#[derive(Clone)]
+pub struct VirtualGamepads {
+    queue: Arc<Mutex<Vec<GamepadInput>>>,
+    next_id: Arc<Mutex<u32>>,
+}
+
+impl VirtualGamepads {
+    pub fn new() -> Self {
+        VirtualGamepads {
+            queue: Arc::new(Mutex::new(Vec::new())),
+            next_id: Arc::new(Mutex::new(0)),
+        }
+    }
+
+    pub fn connect(&self, name: &str) -> VirtualGamepad {
+        let id = {
+            let mut next_id = self.next_id.lock().unwrap();
+            *next_id += 1;
+            GamepadId::from_raw(*next_id - 1)
+        };
+
+        let gamepad = VirtualGamepad {
+            id: id,
+            queue: self.queue.clone(),
+        };
+        gamepad.send(GamepadInput::Connected(id, name.to_string()));
+        gamepad
+    }
+}
+
+impl GamepadBackend for VirtualGamepads {
+    fn poll(&mut self) -> Vec<GamepadInput> {
+        let mut queue = self.queue.lock().unwrap();
+        ::std::mem::replace(&mut *queue, Vec::new())
+    }
+}
+
+/// A gamepad connected to `VirtualGamepads`, input on it is picked up by the next poll.
+pub struct VirtualGamepad {
+    id: GamepadId,
+    queue: Arc<Mutex<Vec<GamepadInput>>>,
+}
+
+impl VirtualGamepad {
+    pub fn id(&self) -> GamepadId {
+        self.id
+    }
+
+    pub fn press(&self, button: GamepadButton) {
+        self.send(GamepadInput::Button(self.id, button, KeyState::Pressed));
+    }
+
+    pub fn release(&self, button: GamepadButton) {
+        self.send(GamepadInput::Button(self.id, button, KeyState::Released));
+    }
+
+    pub fn set_axis(&self, axis: GamepadAxis, value: f32) {
+        self.send(GamepadInput::Axis(self.id, axis, value));
+    }
+
+    pub fn disconnect(self) {
+        self.send(GamepadInput::Disconnected(self.id));
+    }
+
+    fn send(&self, input: GamepadInput) {
+        self.queue.lock().unwrap().push(input);
+    }
+}
+
+/// How far sticks and triggers have to move before they register, as a fraction of their range.
+/// Worn sticks don't return to exactly the center, so without this ships would slowly drift.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Deadzones {
+    /// Applied to the distance of a stick from its center, rather than to each axis separately.
+    pub stick: f32,
+    pub trigger: f32,
+}
+
+impl Default for Deadzones {
+    fn default() -> Self {
+        Deadzones {
+            stick: 0.2,
+            trigger: 0.1,
+        }
+    }
+}
+
+struct TrackedGamepad {
+    id: GamepadId,
+    raw_axes: [f32; AXIS_COUNT],
+    axes: [f32; AXIS_COUNT],
+}
+
+/// Applies deadzones to raw gamepad input, only passing on axis changes that are still a change
+/// after the deadzone.
+pub struct GamepadTracker {
+    deadzones: Deadzones,
+    gamepads: Vec<TrackedGamepad>,
+}
+
+impl GamepadTracker {
+    pub fn new(deadzones: Deadzones) -> Self {
+        GamepadTracker {
+            deadzones: deadzones,
+            gamepads: Vec::new(),
+        }
+    }
+
+    pub fn set_deadzones(&mut self, deadzones: Deadzones) {
+        self.deadzones = deadzones;
+    }
+
+    pub fn process(&mut self, input: GamepadInput) -> Vec<GamepadInput> {
+        match input {
+            GamepadInput::Connected(id, _) => {
+                self.gamepads.retain(|g| g.id != id);
+                self.gamepads.push(TrackedGamepad {
+                    id: id,
+                    raw_axes: [0.0; AXIS_COUNT],
+                    axes: [0.0; AXIS_COUNT],
+                });
+                vec!(input)
+            },
+            GamepadInput::Disconnected(id) => {
+                self.gamepads.retain(|g| g.id != id);
+                vec!(input)
+            },
+            GamepadInput::Button(_, _, _) => vec!(input),
+            GamepadInput::Axis(id, axis, value) => self.process_axis(id, axis, value),
+        }
+    }
+
+    fn process_axis(&mut self, id: GamepadId, axis: GamepadAxis, value: f32) -> Vec<GamepadInput> {
+        let deadzones = self.deadzones;
+        let gamepad = if let Some(gamepad) = self.gamepads.iter_mut().find(|g| g.id == id) {
+            gamepad
+        } else {
+            // Input from a gamepad we weren't told about, ignore it
+            return Vec::new();
+        };
+        gamepad.raw_axes[axis as usize] = value;
+
+        // Sticks need both their axes for the deadzone
+        let affected = match axis {
+            GamepadAxis::LeftStickX | GamepadAxis::LeftStickY =>
+                vec!(GamepadAxis::LeftStickX, GamepadAxis::LeftStickY),
+            GamepadAxis::RightStickX | GamepadAxis::RightStickY =>
+                vec!(GamepadAxis::RightStickX, GamepadAxis::RightStickY),
+            _ => vec!(axis),
+        };
+        let processed = if affected.len() == 2 {
+            let (x, y) = stick_deadzone(
+                gamepad.raw_axes[affected[0] as usize], gamepad.raw_axes[affected[1] as usize],
+                deadzones.stick
+            );
+            vec!(x, y)
+        } else {
+            vec!(trigger_deadzone(value, deadzones.trigger))
+        };
+
+        let mut changes = Vec::new();
+        for (&axis, &value) in affected.iter().zip(processed.iter()) {
+            if gamepad.axes[axis as usize] != value {
+                gamepad.axes[axis as usize] = value;
+                changes.push(GamepadInput::Axis(id, axis, value));
+            }
+        }
+        changes
+    }
+}
+
+/// Zeroes a stick near its center, and scales the rest so it still goes smoothly from 0.0 at the
+/// edge of the deadzone to 1.0.
+fn stick_deadzone(x: f32, y: f32, deadzone: f32) -> (f32, f32) {
+    let magnitude = (x * x + y * y).sqrt();
+    if magnitude <= deadzone {
+        return (0.0, 0.0);
+    }
+
+    let scaled = ((magnitude - deadzone) / (1.0 - deadzone)).min(1.0);
+    (x / magnitude * scaled, y / magnitude * scaled)
+}
+
+fn trigger_deadzone(value: f32, deadzone: f32) -> f32 {
+    if value <= deadzone {
+        0.0
+    } else {
+        ((value - deadzone) / (1.0 - deadzone)).min(1.0)
+    }
+}
+
+pub struct GamepadConnectedEvent {
+    pub gamepad: GamepadId,
+    pub name: String,
+}
+
+pub struct GamepadDisconnectedEvent {
+    pub gamepad: GamepadId,
+}
+
+pub struct GamepadButtonEvent {
+    pub gamepad: GamepadId,
+    pub button: GamepadButton,
+    pub state: KeyState,
+}
+
+/// Raised when an axis changed, with deadzones already applied.
+pub struct GamepadAxisEvent {
+    pub gamepad: GamepadId,
+    pub axis: GamepadAxis,
+    pub value: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{VirtualGamepads, GamepadBackend, GamepadTracker, GamepadInput, GamepadAxis, Deadzones};
+
+    #[test]
+    fn virtual_gamepad_input_goes_through_deadzones() {
+        let mut backend = VirtualGamepads::new();
+        let mut tracker = GamepadTracker::new(Deadzones { stick: 0.5, trigger: 0.5 });
+
+        let gamepad = backend.connect("Test Pad");
+        gamepad.set_axis(GamepadAxis::LeftStickX, 0.25);
+        gamepad.set_axis(GamepadAxis::LeftStickX, 0.75);
+
+        let mut processed = Vec::new();
+        for input in backend.poll() {
+            processed.extend(tracker.process(input));
+        }
+
+        // The small drift is eaten by the deadzone, the rest is scaled to start from 0.0
+        assert_eq!(processed, vec!(
+            GamepadInput::Connected(gamepad.id(), "Test Pad".into()),
+            GamepadInput::Axis(gamepad.id(), GamepadAxis::LeftStickX, 0.5),
+        ));
+        assert!(backend.poll().is_empty());
+    }
+}
@@ -0,0 +1,99 @@
+use gilrs::{self, Gilrs, EventType, Button, Axis};
+use gamepad::{GamepadId, GamepadButton, GamepadAxis, GamepadInput, GamepadBackend};
+use KeyState;
+
+/// A gamepad backend for gamepads connected to the computer, using gilrs. Only available with the
+/// `gamepads` feature.
+pub struct GilrsGamepads {
+    gilrs: Gilrs,
+    connected: Vec<GamepadInput>,
+}
+
+impl GilrsGamepads {
+    pub fn new() -> Result<Self, String> {
+        let gilrs = try!(Gilrs::new().map_err(|e| format!("Could not access gamepads: {}", e)));
+
+        // Gamepads that were already connected don't send an event for it
+        let connected = gilrs.gamepads()
+            .map(|(id, gamepad)| GamepadInput::Connected(gamepad_id(id), gamepad.name().to_string()))
+            .collect();
+
+        Ok(GilrsGamepads {
+            gilrs: gilrs,
+            connected: connected,
+        })
+    }
+}
+
+impl GamepadBackend for GilrsGamepads {
+    fn poll(&mut self) -> Vec<GamepadInput> {
+        let mut input = ::std::mem::replace(&mut self.connected, Vec::new());
+
+        while let Some(event) = self.gilrs.next_event() {
+            let id = gamepad_id(event.id);
+            match event.event {
+                EventType::Connected => {
+                    let name = self.gilrs.gamepad(event.id).name().to_string();
+                    input.push(GamepadInput::Connected(id, name));
+                },
+                EventType::Disconnected =>
+                    input.push(GamepadInput::Disconnected(id)),
+                EventType::ButtonPressed(button, _) => if let Some(button) = map_button(button) {
+                    input.push(GamepadInput::Button(id, button, KeyState::Pressed));
+                },
+                EventType::ButtonReleased(button, _) => if let Some(button) = map_button(button) {
+                    input.push(GamepadInput::Button(id, button, KeyState::Released));
+                },
+                // Analog triggers are buttons with a value in gilrs
+                EventType::ButtonChanged(Button::LeftTrigger2, value, _) =>
+                    input.push(GamepadInput::Axis(id, GamepadAxis::LeftTrigger, value)),
+                EventType::ButtonChanged(Button::RightTrigger2, value, _) =>
+                    input.push(GamepadInput::Axis(id, GamepadAxis::RightTrigger, value)),
+                EventType::AxisChanged(axis, value, _) => if let Some(axis) = map_axis(axis) {
+                    input.push(GamepadInput::Axis(id, axis, value));
+                },
+                _ => ()
+            }
+        }
+
+        input
+    }
+}
+
+fn gamepad_id(id: gilrs::GamepadId) -> GamepadId {
+    let raw: usize = id.into();
+    GamepadId::from_raw(raw as u32)
+}
+
+fn map_button(button: Button) -> Option<GamepadButton> {
+    let button = match button {
+        Button::South => GamepadButton::South,
+        Button::East => GamepadButton::East,
+        Button::West => GamepadButton::West,
+        Button::North => GamepadButton::North,
+        Button::LeftTrigger => GamepadButton::LeftBumper,
+        Button::RightTrigger => GamepadButton::RightBumper,
+        Button::Select => GamepadButton::Select,
+        Button::Start => GamepadButton::Start,
+        Button::LeftThumb => GamepadButton::LeftStick,
+        Button::RightThumb => GamepadButton::RightStick,
+        Button::DPadUp => GamepadButton::DPadUp,
+        Button::DPadDown => GamepadButton::DPadDown,
+        Button::DPadLeft => GamepadButton::DPadLeft,
+        Button::DPadRight => GamepadButton::DPadRight,
+        _ => return None,
+    };
+    Some(button)
+}
+
+fn map_axis(axis: Axis) -> Option<GamepadAxis> {
+    // Sticks already have up as positive in gilrs
+    let axis = match axis {
+        Axis::LeftStickX => GamepadAxis::LeftStickX,
+        Axis::LeftStickY => GamepadAxis::LeftStickY,
+        Axis::RightStickX => GamepadAxis::RightStickX,
+        Axis::RightStickY => GamepadAxis::RightStickY,
+        _ => return None,
+    };
+    Some(axis)
+}
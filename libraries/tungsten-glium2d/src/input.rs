@@ -0,0 +1,216 @@
+use glium::glutin::MouseButton;
+use gamepad::{GamepadId, GamepadButton, GamepadAxis, GamepadInput};
+use {Key, KeyState};
+
+/// Which modifier keys are held down, either the left or right one counts.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub control: bool,
+    pub alt: bool,
+    /// The Windows or Command key.
+    pub logo: bool,
+}
+
+/// A snapshot of the keyboard and mouse. Anything "this frame" happened since the previous
+/// snapshot.
+#[derive(Clone, Debug)]
+pub struct InputState {
+    keys_down: Vec<Key>,
+    keys_pressed: Vec<Key>,
+    keys_released: Vec<Key>,
+
+    mouse_position: [i32; 2],
+    buttons_down: Vec<MouseButton>,
+    buttons_pressed: Vec<MouseButton>,
+    buttons_released: Vec<MouseButton>,
+
+    gamepad_buttons_down: Vec<(GamepadId, GamepadButton)>,
+    gamepad_buttons_pressed: Vec<(GamepadId, GamepadButton)>,
+    gamepad_buttons_released: Vec<(GamepadId, GamepadButton)>,
+    gamepad_axes: Vec<(GamepadId, GamepadAxis, f32)>,
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        InputState {
+            keys_down: Vec::new(),
+            keys_pressed: Vec::new(),
+            keys_released: Vec::new(),
+
+            mouse_position: [0, 0],
+            buttons_down: Vec::new(),
+            buttons_pressed: Vec::new(),
+            buttons_released: Vec::new(),
+
+            gamepad_buttons_down: Vec::new(),
+            gamepad_buttons_pressed: Vec::new(),
+            gamepad_buttons_released: Vec::new(),
+            gamepad_axes: Vec::new(),
+        }
+    }
+
+    pub fn is_key_down(&self, key: Key) -> bool {
+        self.keys_down.contains(&key)
+    }
+
+    pub fn was_key_pressed(&self, key: Key) -> bool {
+        self.keys_pressed.contains(&key)
+    }
+
+    pub fn was_key_released(&self, key: Key) -> bool {
+        self.keys_released.contains(&key)
+    }
+
+    pub fn keys_down(&self) -> &[Key] {
+        &self.keys_down
+    }
+
+    /// The position of the mouse in pixels from the top left of the window.
+    pub fn mouse_position(&self) -> [i32; 2] {
+        self.mouse_position
+    }
+
+    pub fn is_button_down(&self, button: MouseButton) -> bool {
+        self.buttons_down.contains(&button)
+    }
+
+    pub fn was_button_pressed(&self, button: MouseButton) -> bool {
+        self.buttons_pressed.contains(&button)
+    }
+
+    pub fn was_button_released(&self, button: MouseButton) -> bool {
+        self.buttons_released.contains(&button)
+    }
+
+    /// Returns true if the button is held down on any gamepad.
+    pub fn is_gamepad_button_down(&self, button: GamepadButton) -> bool {
+        self.gamepad_buttons_down.iter().any(|&(_, b)| b == button)
+    }
+
+    pub fn was_gamepad_button_pressed(&self, button: GamepadButton) -> bool {
+        self.gamepad_buttons_pressed.iter().any(|&(_, b)| b == button)
+    }
+
+    pub fn was_gamepad_button_released(&self, button: GamepadButton) -> bool {
+        self.gamepad_buttons_released.iter().any(|&(_, b)| b == button)
+    }
+
+    /// Returns the value of an axis on the gamepad that has it pushed furthest, with deadzones
+    /// applied.
+    pub fn gamepad_axis(&self, axis: GamepadAxis) -> f32 {
+        self.gamepad_axes.iter()
+            .filter(|&&(_, a, _)| a == axis)
+            .fold(0.0, |furthest, &(_, _, value)| if value.abs() > furthest.abs() { value } else { furthest })
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        Modifiers {
+            shift: self.is_key_down(Key::LShift) || self.is_key_down(Key::RShift),
+            control: self.is_key_down(Key::LControl) || self.is_key_down(Key::RControl),
+            alt: self.is_key_down(Key::LMenu) || self.is_key_down(Key::RMenu),
+            logo: self.is_key_down(Key::LWin) || self.is_key_down(Key::RWin),
+        }
+    }
+
+    /// Forgets what happened this frame, called by the frontend before new events come in.
+    pub fn start_frame(&mut self) {
+        self.keys_pressed.clear();
+        self.keys_released.clear();
+        self.buttons_pressed.clear();
+        self.buttons_released.clear();
+        self.gamepad_buttons_pressed.clear();
+        self.gamepad_buttons_released.clear();
+    }
+
+    pub fn key_input(&mut self, key: Key, state: KeyState) {
+        update(&mut self.keys_down, &mut self.keys_pressed, &mut self.keys_released, key, state);
+    }
+
+    pub fn button_input(&mut self, button: MouseButton, state: KeyState) {
+        update(&mut self.buttons_down, &mut self.buttons_pressed, &mut self.buttons_released, button, state);
+    }
+
+    pub fn gamepad_input(&mut self, input: &GamepadInput) {
+        match *input {
+            GamepadInput::Connected(_, _) => (),
+            GamepadInput::Disconnected(id) => {
+                // Anything held on the gamepad is released with it
+                let (released, down): (Vec<_>, Vec<_>) =
+                    self.gamepad_buttons_down.iter().partition(|&&(g, _)| g == id);
+                self.gamepad_buttons_down = down;
+                self.gamepad_buttons_released.extend(released);
+                self.gamepad_axes.retain(|&(g, _, _)| g != id);
+            },
+            GamepadInput::Button(id, button, state) => update(
+                &mut self.gamepad_buttons_down, &mut self.gamepad_buttons_pressed,
+                &mut self.gamepad_buttons_released, (id, button), state
+            ),
+            GamepadInput::Axis(id, axis, value) => {
+                self.gamepad_axes.retain(|&(g, a, _)| g != id || a != axis);
+                self.gamepad_axes.push((id, axis, value));
+            },
+        }
+    }
+
+    pub fn mouse_moved(&mut self, position: [i32; 2]) {
+        self.mouse_position = position;
+    }
+
+    /// Releases everything held down, for when the window loses focus and we won't hear about
+    /// keys being released anymore.
+    pub fn release_all(&mut self) {
+        self.keys_released.extend(self.keys_down.drain(..));
+        self.buttons_released.extend(self.buttons_down.drain(..));
+    }
+}
+
+fn update<T: Copy + PartialEq>(down: &mut Vec<T>, pressed: &mut Vec<T>, released: &mut Vec<T>, input: T, state: KeyState) {
+    let index = down.iter().position(|d| *d == input);
+
+    match (state, index) {
+        // Held keys repeat their pressed events, those aren't new presses
+        (KeyState::Pressed, Some(_)) => (),
+        (KeyState::Pressed, None) => {
+            down.push(input);
+            pressed.push(input);
+        },
+        (KeyState::Released, Some(index)) => {
+            released.push(down.remove(index));
+        },
+        (KeyState::Released, None) => (),
+    }
+}
+
+/// Raised every update with the latest input state if `Frontend2D::set_input_state_events` is
+/// turned on, so the model can keep a copy to read from.
+pub struct InputStateEvent {
+    pub state: InputState,
+}
+
+#[cfg(test)]
+mod tests {
+    use {Key, KeyState};
+    use super::InputState;
+
+    #[test]
+    fn tracks_held_keys_and_edges() {
+        let mut input = InputState::new();
+        input.key_input(Key::W, KeyState::Pressed);
+        assert!(input.is_key_down(Key::W));
+        assert!(input.was_key_pressed(Key::W));
+
+        // Repeats while holding the key aren't presses
+        input.start_frame();
+        input.key_input(Key::W, KeyState::Pressed);
+        assert!(input.is_key_down(Key::W));
+        assert!(!input.was_key_pressed(Key::W));
+
+        input.start_frame();
+        input.key_input(Key::LShift, KeyState::Pressed);
+        input.key_input(Key::W, KeyState::Released);
+        assert!(!input.is_key_down(Key::W));
+        assert!(input.was_key_released(Key::W));
+        assert!(input.modifiers().shift);
+    }
+}
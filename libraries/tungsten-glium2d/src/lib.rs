@@ -1,13 +1,52 @@
 extern crate cgmath;
+#[cfg(feature = "gamepads")]
+extern crate gilrs;
 #[macro_use] extern crate glium;
 extern crate image;
+extern crate rustc_serialize;
 extern crate tungsten;
 
+mod actions;
+mod background;
+mod debug;
+mod error;
+mod font;
 mod frontend;
+mod gamepad;
+#[cfg(feature = "gamepads")]
+mod gilrs_backend;
+mod input;
+mod order;
+mod particles;
+mod recording;
 mod runtime;
+mod software;
+mod stats;
+mod texture;
+mod tilemap;
+mod ui;
+mod viewport;
+mod watcher;
 
-pub use frontend::{CloseRequestEvent, View2D, Frontend2D, FrameRenderInfo, RenderBatchInfo, KeyboardInputEvent, RenderTarget, Rectangle, GameCameraInfo, LayerInfo, TextureId};
+pub use actions::{Input, InputMap, AxisBinding};
+pub use background::{BackgroundInfo, BackgroundFill};
+pub use debug::DebugDraw;
+pub use error::{FrontendError, FrontendErrorEvent};
+pub use frontend::{CloseRequestEvent, FramesDroppedEvent, View2D, Frontend2D, FrameRenderInfo, RenderBatchInfo, KeyboardInputEvent, TextInputEvent, FocusEvent, MinimizeEvent, WindowResizedEvent, WindowMovedEvent, FileDroppedEvent, ScreenshotEvent, AssetReloadEvent, RenderTarget, Rectangle, ClipRect, GameCameraInfo, LayerInfo, TextureId, TileMapId, TileMapInfo};
+pub use gamepad::{GamepadId, GamepadButton, GamepadAxis, GamepadInput, GamepadBackend, VirtualGamepads, VirtualGamepad, Deadzones, GamepadConnectedEvent, GamepadDisconnectedEvent, GamepadButtonEvent, GamepadAxisEvent};
+#[cfg(feature = "gamepads")]
+pub use gilrs_backend::GilrsGamepads;
+pub use input::{InputState, InputStateEvent, Modifiers};
+pub use particles::{ParticleEmitter, EmitterSettings, EmissionShape, Curve, Lerp};
+pub use recording::{FrameRecorder, FrameChange, read_recording, diff_frames};
+pub use software::SoftwareRenderer;
+pub use stats::{RenderStats, RenderStatsEvent};
+pub use texture::{TextureOptions, TextureSource, Texture, TextureLoader, Filter, WrapMode};
+pub use tilemap::TileMap;
+pub use ui::{Ui, UiEvents, Skin, NineSlice, Direction, WidgetId, WidgetEvent};
+pub use viewport::Viewport;
 
 // Re-export as utility
 pub use glium::glutin::VirtualKeyCode as Key;
 pub use glium::glutin::ElementState as KeyState;
+pub use glium::glutin::MouseButton;
@@ -0,0 +1,125 @@
+use std::cmp::Ordering;
+use frontend::{LayerInfo, Rectangle};
+
+/// A single step in drawing a camera's layers.
+pub enum DrawStep<'a> {
+    /// Rectangles from one or more batches, sorted by depth.
+    Rectangles(Vec<&'a Rectangle>),
+    /// Any other layer, drawn as it is.
+    Layer(&'a LayerInfo),
+}
+
+/// Splits a camera's layers into the steps to draw them in. Consecutive batches share all their
+/// state, so they are merged into one step, with their rectangles sorted by depth. Rectangles at
+/// the same depth keep the order they were added in. Other layers stay where they were added, and
+/// rectangles are never sorted across them.
+pub fn draw_steps<'a>(layers: &'a [LayerInfo]) -> Vec<DrawStep<'a>> {
+    let mut steps = Vec::new();
+    let mut rectangles: Vec<&Rectangle> = Vec::new();
+
+    for layer in layers {
+        if let &LayerInfo::Batch(ref batch) = layer {
+            rectangles.extend(batch.rectangles().iter());
+        } else {
+            flush_rectangles(&mut steps, &mut rectangles);
+            steps.push(DrawStep::Layer(layer));
+        }
+    }
+    flush_rectangles(&mut steps, &mut rectangles);
+
+    steps
+}
+
+fn flush_rectangles<'a>(steps: &mut Vec<DrawStep<'a>>, rectangles: &mut Vec<&'a Rectangle>) {
+    if rectangles.is_empty() {
+        return;
+    }
+
+    // This sort is stable, so push order is kept for equal depths
+    let mut sorted = ::std::mem::replace(rectangles, Vec::new());
+    sorted.sort_by(|a, b| a.depth.partial_cmp(&b.depth).unwrap_or(Ordering::Equal));
+    steps.push(DrawStep::Rectangles(sorted));
+}
+
+#[cfg(test)]
+mod tests {
+    use frontend::{FrameRenderInfo, LayerInfo, RenderTarget, Rectangle, TextureId, TileMapId, ClipRect};
+    use super::{draw_steps, DrawStep};
+
+    fn rect(x: f32, depth: f32) -> Rectangle {
+        let mut rect = Rectangle::new([x, 0.0], [1.0, 1.0], TextureId::from_raw(0));
+        rect.depth = depth;
+        rect
+    }
+
+    fn depths(step: &DrawStep) -> Vec<f32> {
+        match *step {
+            DrawStep::Rectangles(ref rects) => rects.iter().map(|r| r.depth).collect(),
+            DrawStep::Layer(_) => panic!("Expected rectangles"),
+        }
+    }
+
+    #[test]
+    fn merges_consecutive_batches_and_sorts_stably() {
+        let mut frame = FrameRenderInfo::new();
+        {
+            let camera = frame.game_camera([0.0, 0.0]);
+            camera.batch().rectangle(rect(0.0, 1.0));
+            camera.batch().rectangle(rect(1.0, 0.0));
+            camera.batch().rectangle(rect(2.0, 1.0));
+            camera.tilemap(TileMapId::from_raw(0), [0.0, 0.0]);
+            camera.batch().rectangle(rect(3.0, -1.0));
+        }
+
+        let camera = match frame.layers()[0] {
+            LayerInfo::Camera(ref camera) => camera,
+            _ => unreachable!(),
+        };
+        let steps = draw_steps(camera.layers());
+
+        assert_eq!(steps.len(), 3);
+        match steps[0] {
+            DrawStep::Rectangles(ref rects) => {
+                let order: Vec<f32> = rects.iter().map(|r| r.position[0]).collect();
+                assert_eq!(order, vec!(1.0, 0.0, 2.0));
+            },
+            _ => panic!("Expected rectangles first"),
+        }
+        match steps[1] {
+            DrawStep::Layer(&LayerInfo::TileMap(_)) => (),
+            _ => panic!("Expected the tilemap in between"),
+        }
+        match steps[2] {
+            DrawStep::Rectangles(ref rects) => assert_eq!(rects.len(), 1),
+            _ => panic!("Expected rectangles last"),
+        }
+    }
+
+    #[test]
+    fn does_not_sort_depth_across_clips() {
+        let mut frame = FrameRenderInfo::new();
+        {
+            let camera = frame.game_camera([0.0, 0.0]);
+            camera.batch().rectangle(rect(0.0, 5.0));
+            camera.push_clip(ClipRect { position: [0.0, 0.0], size: [1.0, 1.0] });
+            camera.batch().rectangle(rect(1.0, 2.0));
+            camera.batch().rectangle(rect(2.0, -5.0));
+            camera.pop_clip();
+            camera.pop_clip();
+            camera.batch().rectangle(rect(3.0, -10.0));
+        }
+
+        let camera = match frame.layers()[0] {
+            LayerInfo::Camera(ref camera) => camera,
+            _ => unreachable!(),
+        };
+        let steps = draw_steps(camera.layers());
+
+        // Lower depths after the clip are still drawn over the rectangle before it, popping more
+        // than was pushed does nothing
+        assert_eq!(steps.len(), 5);
+        assert_eq!(depths(&steps[0]), vec!(5.0));
+        assert_eq!(depths(&steps[2]), vec!(-5.0, 2.0));
+        assert_eq!(depths(&steps[4]), vec!(-10.0));
+    }
+}
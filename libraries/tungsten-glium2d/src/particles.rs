@@ -0,0 +1,340 @@
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use frontend::{Rectangle, RenderBatchInfo, TextureId};
+
+/// A value that can be linearly interpolated between two keys of a curve.
+pub trait Lerp: Copy {
+    fn lerp(&self, other: &Self, factor: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(&self, other: &f32, factor: f32) -> f32 {
+        self + (other - self) * factor
+    }
+}
+
+impl Lerp for [f32; 2] {
+    fn lerp(&self, other: &[f32; 2], factor: f32) -> [f32; 2] {
+        [self[0].lerp(&other[0], factor), self[1].lerp(&other[1], factor)]
+    }
+}
+
+impl Lerp for [f32; 4] {
+    fn lerp(&self, other: &[f32; 4], factor: f32) -> [f32; 4] {
+        [
+            self[0].lerp(&other[0], factor), self[1].lerp(&other[1], factor),
+            self[2].lerp(&other[2], factor), self[3].lerp(&other[3], factor),
+        ]
+    }
+}
+
+/// A value over the lifetime of a particle, keyed from 0.0 (spawned) to 1.0 (expired).
+#[derive(Clone, Debug)]
+pub struct Curve<T> {
+    keys: Vec<(f32, T)>,
+}
+
+impl<T: Lerp> Curve<T> {
+    pub fn constant(value: T) -> Self {
+        Curve {
+            keys: vec!((0.0, value)),
+        }
+    }
+
+    pub fn linear(start: T, end: T) -> Self {
+        Curve {
+            keys: vec!((0.0, start), (1.0, end)),
+        }
+    }
+
+    /// Adds a key to the curve, keys have to be added in order of time.
+    pub fn key(mut self, time: f32, value: T) -> Self {
+        assert!(self.keys.last().map(|k| k.0 <= time).unwrap_or(true));
+        self.keys.push((time, value));
+        self
+    }
+
+    pub fn sample(&self, time: f32) -> T {
+        let first = &self.keys[0];
+        if time <= first.0 {
+            return first.1;
+        }
+
+        // Find the two keys we're in between and interpolate between them
+        for pair in self.keys.windows(2) {
+            let (start, end) = (pair[0], pair[1]);
+            if time < end.0 {
+                let factor = (time - start.0) / (end.0 - start.0);
+                return start.1.lerp(&end.1, factor);
+            }
+        }
+
+        self.keys[self.keys.len() - 1].1
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum EmissionShape {
+    Point,
+    Circle(f32),
+    Rectangle([f32; 2]),
+}
+
+/// Counts up for every new emitter settings, so emitters get different seeds by default.
+static NEXT_SEED: AtomicUsize = AtomicUsize::new(0);
+
+pub struct EmitterSettings {
+    pub texture: TextureId,
+    pub shape: EmissionShape,
+    /// Particles emitted per second while emitting.
+    pub rate: f32,
+    pub max_particles: usize,
+    /// Minimum and maximum lifetime of a particle in seconds.
+    pub lifetime: [f32; 2],
+    /// Minimum and maximum starting speed of a particle in units per second.
+    pub speed: [f32; 2],
+    /// The direction particles are emitted in, in radians.
+    pub direction: f32,
+    /// The angle around the direction particles can be emitted in, in radians.
+    pub spread: f32,
+    pub gravity: [f32; 2],
+    pub size: Curve<f32>,
+    pub color: Curve<[f32; 4]>,
+    /// The seed particles are randomized from. Every new settings gets a different one, set it
+    /// yourself to make an effect play out the same every time.
+    pub seed: u32,
+}
+
+impl EmitterSettings {
+    pub fn new(texture: TextureId) -> Self {
+        EmitterSettings {
+            texture: texture,
+            shape: EmissionShape::Point,
+            rate: 10.0,
+            max_particles: 1000,
+            lifetime: [1.0, 1.0],
+            speed: [32.0, 32.0],
+            direction: 0.0,
+            spread: PI * 2.0,
+            gravity: [0.0, 0.0],
+            size: Curve::constant(8.0),
+            color: Curve::constant([1.0, 1.0, 1.0, 1.0]),
+            seed: next_seed(),
+        }
+    }
+}
+
+fn next_seed() -> u32 {
+    // Spread the counter out so neighbouring seeds don't start off similar
+    (NEXT_SEED.fetch_add(1, Ordering::Relaxed) as u32).wrapping_add(1).wrapping_mul(0x9E3779B9)
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Particle {
+    position: [f32; 2],
+    velocity: [f32; 2],
+    age: f32,
+    lifetime: f32,
+}
+
+/// Simple xorshift generator, particles don't need anything better than this.
+struct Random {
+    state: u32,
+}
+
+impl Random {
+    fn new(seed: u32) -> Self {
+        Random {
+            state: if seed == 0 { 0x9E3779B9 } else { seed }
+        }
+    }
+
+    fn next(&mut self) -> u32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state
+    }
+
+    /// Returns a value between 0.0 and 1.0.
+    fn unit(&mut self) -> f32 {
+        (self.next() >> 8) as f32 / (1 << 24) as f32
+    }
+
+    fn range(&mut self, range: [f32; 2]) -> f32 {
+        range[0] + (range[1] - range[0]) * self.unit()
+    }
+}
+
+/// Emits and simulates particles at a world position. The model updates the emitter, the view
+/// renders it into a batch of the camera it should appear in.
+pub struct ParticleEmitter {
+    settings: EmitterSettings,
+    position: [f32; 2],
+    emitting: bool,
+    emit_accumulator: f32,
+    particles: Vec<Particle>,
+    random: Random,
+}
+
+impl ParticleEmitter {
+    pub fn new(settings: EmitterSettings, position: [f32; 2]) -> Self {
+        let random = Random::new(settings.seed);
+        ParticleEmitter {
+            settings: settings,
+            position: position,
+            emitting: true,
+            emit_accumulator: 0.0,
+            particles: Vec::new(),
+            random: random,
+        }
+    }
+
+    pub fn settings(&self) -> &EmitterSettings {
+        &self.settings
+    }
+
+    pub fn settings_mut(&mut self) -> &mut EmitterSettings {
+        &mut self.settings
+    }
+
+    pub fn position(&self) -> [f32; 2] {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: [f32; 2]) {
+        self.position = position;
+    }
+
+    pub fn is_emitting(&self) -> bool {
+        self.emitting
+    }
+
+    /// Starts or stops continuous emission, particles already alive keep simulating.
+    pub fn set_emitting(&mut self, emitting: bool) {
+        self.emitting = emitting;
+        self.emit_accumulator = 0.0;
+    }
+
+    pub fn particle_count(&self) -> usize {
+        self.particles.len()
+    }
+
+    /// Emits a number of particles at once, for example for an explosion.
+    pub fn burst(&mut self, count: usize) {
+        for _ in 0..count {
+            self.emit();
+        }
+    }
+
+    pub fn update(&mut self, delta: f32) {
+        // Age and remove expired particles, order doesn't matter so we can swap remove
+        let mut i = 0;
+        while i < self.particles.len() {
+            self.particles[i].age += delta;
+            if self.particles[i].age >= self.particles[i].lifetime {
+                self.particles.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+
+        // Move all remaining particles
+        let gravity = self.settings.gravity;
+        for particle in &mut self.particles {
+            particle.velocity[0] += gravity[0] * delta;
+            particle.velocity[1] += gravity[1] * delta;
+            particle.position[0] += particle.velocity[0] * delta;
+            particle.position[1] += particle.velocity[1] * delta;
+        }
+
+        // Emit new particles for the time that passed
+        if self.emitting {
+            self.emit_accumulator += self.settings.rate * delta;
+            while self.emit_accumulator >= 1.0 {
+                self.emit_accumulator -= 1.0;
+                self.emit();
+            }
+        }
+    }
+
+    pub fn render(&self, batch: &mut RenderBatchInfo) {
+        for particle in &self.particles {
+            let time = particle.age / particle.lifetime;
+            let size = self.settings.size.sample(time);
+
+            let mut rect = Rectangle::new(particle.position, [size, size], self.settings.texture);
+            rect.color = self.settings.color.sample(time);
+            batch.rectangle(rect);
+        }
+    }
+
+    fn emit(&mut self) {
+        if self.particles.len() >= self.settings.max_particles {
+            return;
+        }
+
+        // Find the position in the emission shape to spawn at
+        let offset = match self.settings.shape {
+            EmissionShape::Point => [0.0, 0.0],
+            EmissionShape::Circle(radius) => {
+                let angle = self.random.unit() * PI * 2.0;
+                let distance = radius * self.random.unit().sqrt();
+                [angle.cos() * distance, angle.sin() * distance]
+            },
+            EmissionShape::Rectangle(size) => [
+                (self.random.unit() - 0.5) * size[0],
+                (self.random.unit() - 0.5) * size[1],
+            ],
+        };
+
+        // Find the velocity to spawn with
+        let angle = self.settings.direction + (self.random.unit() - 0.5) * self.settings.spread;
+        let speed = self.random.range(self.settings.speed);
+
+        let lifetime = self.random.range(self.settings.lifetime);
+        self.particles.push(Particle {
+            position: [self.position[0] + offset[0], self.position[1] + offset[1]],
+            velocity: [angle.cos() * speed, angle.sin() * speed],
+            age: 0.0,
+            lifetime: lifetime,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use frontend::TextureId;
+    use super::{Curve, EmitterSettings, ParticleEmitter};
+
+    #[test]
+    fn curve_interpolates_between_keys() {
+        let curve = Curve::linear(0.0, 10.0).key(1.0, 20.0);
+
+        assert_eq!(curve.sample(-1.0), 0.0);
+        assert_eq!(curve.sample(0.5), 5.0);
+        assert_eq!(curve.sample(2.0), 20.0);
+    }
+
+    #[test]
+    fn particles_expire_after_lifetime() {
+        let mut settings = EmitterSettings::new(TextureId::from_raw(0));
+        settings.lifetime = [1.0, 1.0];
+        let mut emitter = ParticleEmitter::new(settings, [0.0, 0.0]);
+        emitter.set_emitting(false);
+
+        emitter.burst(10);
+        emitter.update(0.5);
+        assert_eq!(emitter.particle_count(), 10);
+
+        emitter.update(0.6);
+        assert_eq!(emitter.particle_count(), 0);
+    }
+
+    #[test]
+    fn settings_get_different_seeds() {
+        let first = EmitterSettings::new(TextureId::from_raw(0));
+        let second = EmitterSettings::new(TextureId::from_raw(0));
+        assert!(first.seed != second.seed);
+    }
+}
@@ -0,0 +1,251 @@
+use std::fs::File;
+use std::io::{self, Write, BufRead, BufReader, BufWriter};
+use std::path::Path;
+use rustc_serialize::json;
+use frontend::{FrameRenderInfo, GameCameraInfo, LayerInfo, Rectangle, RenderTarget};
+
+impl FrameRenderInfo {
+    pub fn to_json(&self) -> String {
+        json::encode(self).unwrap()
+    }
+
+    pub fn from_json(data: &str) -> Result<Self, json::DecoderError> {
+        json::decode(data)
+    }
+}
+
+/// Records a stream of frames to a file, one frame per line.
+pub struct FrameRecorder {
+    writer: BufWriter<File>,
+    frames: u32,
+}
+
+impl FrameRecorder {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = try!(File::create(path));
+
+        Ok(FrameRecorder {
+            writer: BufWriter::new(file),
+            frames: 0,
+        })
+    }
+
+    pub fn record(&mut self, frame: &FrameRenderInfo) -> io::Result<()> {
+        try!(writeln!(self.writer, "{}", frame.to_json()));
+        self.frames += 1;
+        Ok(())
+    }
+
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+}
+
+/// Reads back all frames recorded by a `FrameRecorder`.
+pub fn read_recording<P: AsRef<Path>>(path: P) -> io::Result<Vec<FrameRenderInfo>> {
+    let reader = BufReader::new(try!(File::open(path)));
+    let mut frames = Vec::new();
+
+    for line in reader.lines() {
+        let line = try!(line);
+        let frame = try!(FrameRenderInfo::from_json(&line)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}", e))));
+        frames.push(frame);
+    }
+
+    Ok(frames)
+}
+
+/// A structural difference between two frames. Layers are identified by their path of indices
+/// from the frame down, so `[1, 0]` is the first layer in the second camera.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FrameChange {
+    LayerAdded { layer: Vec<usize> },
+    LayerRemoved { layer: Vec<usize> },
+    /// The layer at this path is a different kind of layer, a different tilemap or background, or a
+    /// camera with a different viewport.
+    LayerChanged { layer: Vec<usize> },
+    CameraMoved { layer: Vec<usize>, from: [f32; 2], to: [f32; 2] },
+    CameraZoomed { layer: Vec<usize>, from: f32, to: f32 },
+    RectangleAdded { layer: Vec<usize>, rectangle: Rectangle },
+    RectangleRemoved { layer: Vec<usize>, rectangle: Rectangle },
+    RectangleMoved { layer: Vec<usize>, from: [f32; 2], rectangle: Rectangle },
+}
+
+/// Finds the structural differences going from one frame to another.
+pub fn diff_frames(from: &FrameRenderInfo, to: &FrameRenderInfo) -> Vec<FrameChange> {
+    let mut changes = Vec::new();
+    diff_layers(&mut changes, &mut Vec::new(), from.layers(), to.layers());
+    changes
+}
+
+fn diff_layers(
+    changes: &mut Vec<FrameChange>, path: &mut Vec<usize>, from: &[LayerInfo], to: &[LayerInfo]
+) {
+    let length = ::std::cmp::max(from.len(), to.len());
+    for i in 0..length {
+        path.push(i);
+
+        match (from.get(i), to.get(i)) {
+            (Some(from), Some(to)) => diff_layer(changes, path, from, to),
+            (Some(_), None) => changes.push(FrameChange::LayerRemoved { layer: path.clone() }),
+            (None, Some(_)) => changes.push(FrameChange::LayerAdded { layer: path.clone() }),
+            (None, None) => unreachable!(),
+        }
+
+        path.pop();
+    }
+}
+
+fn diff_layer(changes: &mut Vec<FrameChange>, path: &mut Vec<usize>, from: &LayerInfo, to: &LayerInfo) {
+    match (from, to) {
+        (&LayerInfo::Camera(ref from), &LayerInfo::Camera(ref to)) =>
+            diff_cameras(changes, path, from, to),
+        (&LayerInfo::Batch(ref from), &LayerInfo::Batch(ref to)) =>
+            diff_rectangles(changes, path, from.rectangles(), to.rectangles()),
+        (&LayerInfo::TileMap(ref from), &LayerInfo::TileMap(ref to)) => {
+            if from.tilemap() != to.tilemap() || from.position() != to.position() {
+                changes.push(FrameChange::LayerChanged { layer: path.clone() });
+            }
+        },
+        (&LayerInfo::PushClip(from), &LayerInfo::PushClip(to)) => {
+            if from != to {
+                changes.push(FrameChange::LayerChanged { layer: path.clone() });
+            }
+        },
+        (&LayerInfo::PopClip, &LayerInfo::PopClip) => (),
+        (&LayerInfo::Background(ref from), &LayerInfo::Background(ref to)) => {
+            // Backgrounds are generated, so compare what they're generated from
+            if json::encode(from).unwrap() != json::encode(to).unwrap() {
+                changes.push(FrameChange::LayerChanged { layer: path.clone() });
+            }
+        },
+        _ => changes.push(FrameChange::LayerChanged { layer: path.clone() }),
+    }
+}
+
+fn diff_cameras(
+    changes: &mut Vec<FrameChange>, path: &mut Vec<usize>, from: &GameCameraInfo, to: &GameCameraInfo
+) {
+    if from.position() != to.position() {
+        changes.push(FrameChange::CameraMoved {
+            layer: path.clone(), from: from.position(), to: to.position()
+        });
+    }
+    if from.zoom() != to.zoom() {
+        changes.push(FrameChange::CameraZoomed {
+            layer: path.clone(), from: from.zoom(), to: to.zoom()
+        });
+    }
+    if from.viewport() != to.viewport() {
+        changes.push(FrameChange::LayerChanged { layer: path.clone() });
+    }
+
+    diff_layers(changes, path, from.layers(), to.layers());
+}
+
+fn diff_rectangles(
+    changes: &mut Vec<FrameChange>, path: &[usize], from: &[Rectangle], to: &[Rectangle]
+) {
+    let mut from_matched = vec![false; from.len()];
+    let mut to_matched = vec![false; to.len()];
+
+    // First match up all rectangles that didn't change at all
+    for (i, rect) in from.iter().enumerate() {
+        let found = to.iter().enumerate().position(|(j, other)| !to_matched[j] && rect == other);
+        if let Some(j) = found {
+            from_matched[i] = true;
+            to_matched[j] = true;
+        }
+    }
+
+    // Then match up rectangles that only changed position
+    for (i, rect) in from.iter().enumerate() {
+        if from_matched[i] {
+            continue;
+        }
+
+        let found = to.iter().enumerate().position(|(j, other)| {
+            !to_matched[j] &&
+                rect.texture == other.texture && rect.size == other.size && rect.color == other.color &&
+                rect.region == other.region && rect.depth == other.depth
+        });
+        if let Some(j) = found {
+            from_matched[i] = true;
+            to_matched[j] = true;
+            changes.push(FrameChange::RectangleMoved {
+                layer: path.to_vec(), from: rect.position, rectangle: to[j].clone()
+            });
+        }
+    }
+
+    // Anything left over has been removed or added
+    for (i, rect) in from.iter().enumerate() {
+        if !from_matched[i] {
+            changes.push(FrameChange::RectangleRemoved { layer: path.to_vec(), rectangle: rect.clone() });
+        }
+    }
+    for (j, rect) in to.iter().enumerate() {
+        if !to_matched[j] {
+            changes.push(FrameChange::RectangleAdded { layer: path.to_vec(), rectangle: rect.clone() });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use frontend::{FrameRenderInfo, RenderTarget, Rectangle, TextureId};
+    use super::{diff_frames, FrameChange};
+
+    fn frame(rects: Vec<Rectangle>) -> FrameRenderInfo {
+        let mut frame = FrameRenderInfo::new();
+        {
+            let batch = frame.game_camera([0.0, 0.0]).batch();
+            for rect in rects {
+                batch.rectangle(rect);
+            }
+        }
+        frame
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let texture = TextureId::from_raw(2);
+        let original = frame(vec!(Rectangle::new([1.0, 2.0], [3.0, 4.0], texture)));
+
+        let decoded = FrameRenderInfo::from_json(&original.to_json()).unwrap();
+
+        assert_eq!(diff_frames(&original, &decoded), vec!());
+    }
+
+    #[test]
+    fn diff_finds_added_removed_and_moved() {
+        let a = TextureId::from_raw(0);
+        let b = TextureId::from_raw(1);
+        let from = frame(vec!(
+            Rectangle::new([0.0, 0.0], [1.0, 1.0], a),
+            Rectangle::new([5.0, 0.0], [1.0, 1.0], a),
+            Rectangle::new([9.0, 0.0], [1.0, 1.0], b),
+        ));
+        let to = frame(vec!(
+            Rectangle::new([0.0, 0.0], [1.0, 1.0], a),
+            Rectangle::new([6.0, 0.0], [1.0, 1.0], a),
+            Rectangle::new([9.0, 0.0], [2.0, 2.0], b),
+        ));
+
+        let changes = diff_frames(&from, &to);
+
+        assert_eq!(changes, vec!(
+            FrameChange::RectangleMoved {
+                layer: vec!(0, 0), from: [5.0, 0.0],
+                rectangle: Rectangle::new([6.0, 0.0], [1.0, 1.0], a)
+            },
+            FrameChange::RectangleRemoved {
+                layer: vec!(0, 0), rectangle: Rectangle::new([9.0, 0.0], [1.0, 1.0], b)
+            },
+            FrameChange::RectangleAdded {
+                layer: vec!(0, 0), rectangle: Rectangle::new([9.0, 0.0], [2.0, 2.0], b)
+            },
+        ));
+    }
+}
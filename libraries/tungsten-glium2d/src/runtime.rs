@@ -1,29 +1,66 @@
-use std::sync::mpsc::{Sender, Receiver};
+use std::sync::mpsc::{self, Sender, Receiver};
 use std::sync::{Arc, Barrier};
 use std::thread::{self, JoinHandle};
-use std::fs::File;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use cgmath::Matrix3;
 use glium::backend::glutin_backend::GlutinFacade;
 use glium::draw_parameters::DrawParameters;
-use glium::glutin::{Event, WindowBuilder};
+use glium::glutin::WindowBuilder;
 use glium::index::{NoIndices, PrimitiveType};
-use glium::texture::RawImage2d;
+use glium::draw_parameters::TimeElapsedQuery;
+use glium::texture::{RawImage2d, MipmapsOption};
 use glium::texture::srgb_texture2d_array::SrgbTexture2dArray;
-use glium::uniforms::{Uniforms, MagnifySamplerFilter, UniformValue, Sampler, AsUniformValue};
-use glium::{Frame, DisplayBuild, Surface, VertexBuffer, Program, Blend};
-use image;
-use {FrameRenderInfo, RenderBatchInfo, GameCameraInfo, RenderTarget, LayerInfo};
-use frontend::{FrontendCommand, TextureId};
+use glium::uniforms::{Uniforms, MagnifySamplerFilter, MinifySamplerFilter, SamplerWrapFunction};
+use glium::uniforms::{UniformValue, Sampler, AsUniformValue};
+use glium::{Frame, DisplayBuild, Surface, VertexBuffer, Program, Blend, Rect, SwapBuffersError};
+use image::{ImageBuffer, RgbaImage, imageops};
+use {FrameRenderInfo, Rectangle, GameCameraInfo, RenderTarget, LayerInfo, TileMapInfo, BackgroundInfo};
+use error::FrontendError;
+use order::{self, DrawStep};
+use frontend::{FrontendCommand, RuntimeEvent, ReturnedFrame, TextureId};
+use texture::{self, TextureOptions, TextureSource, Filter, WrapMode};
+use tilemap::{TileMap, CHUNK_SIZE};
+use stats::RenderStats;
+use viewport::{CameraView, ClipStack, PixelRect};
+use watcher::FileWatcher;
 
 #[derive(Copy, Clone)]
 struct Vertex2D {
     i_position: [f32; 2],
     i_texture_coord: [f32; 2],
     i_sampler_id: u32,
-    i_texture_id: u32
+    i_texture_id: u32,
+    i_color: [f32; 4],
 }
 
-implement_vertex!(Vertex2D, i_position, i_texture_coord, i_sampler_id, i_texture_id);
+implement_vertex!(Vertex2D, i_position, i_texture_coord, i_sampler_id, i_texture_id, i_color);
+
+/// Adds the two triangles making up a quad between two corners to a vertex list.
+fn push_quad(
+    vertices: &mut Vec<Vertex2D>, low: [f32; 2], high: [f32; 2],
+    uv_low: [f32; 2], uv_high: [f32; 2], texture: TextureLocation, color: [f32; 4]
+) {
+    let corners = [
+        ([low[0], low[1]], [uv_low[0], uv_low[1]]),
+        ([high[0], low[1]], [uv_high[0], uv_low[1]]),
+        ([high[0], high[1]], [uv_high[0], uv_high[1]]),
+        ([low[0], low[1]], [uv_low[0], uv_low[1]]),
+        ([high[0], high[1]], [uv_high[0], uv_high[1]]),
+        ([low[0], high[1]], [uv_low[0], uv_high[1]]),
+    ];
+
+    for &(position, texture_coord) in corners.iter() {
+        vertices.push(Vertex2D {
+            i_position: position,
+            i_texture_coord: texture_coord,
+            i_sampler_id: texture.array, i_texture_id: texture.index,
+            i_color: color,
+        });
+    }
+}
 
 struct Uniforms2D<'a> {
     matrix: [[f32; 3]; 3],
@@ -42,82 +79,181 @@ impl<'a> Uniforms for Uniforms2D<'a> {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 struct TextureLocation {
     array: u32,
     index: u32,
 }
 
+/// Where the placeholder for textures that could not be loaded is stored. It's shared by all of
+/// them, so it's never replaced or freed.
+const MISSING_LOCATION: TextureLocation = TextureLocation { array: 0, index: 0 };
+
 type RawImageData = (Vec<u8>, (u32, u32));
 
+/// The maximum amount of texture arrays, this has to match the amount of samplers in the shader.
+const MAX_TEXTURE_ARRAYS: usize = 16;
+
+/// Textures of the same size and with the same options share a texture array.
+struct TextureGroup {
+    size: u32,
+    options: TextureOptions,
+    images: Vec<RawImageData>, // This should match directly to the texture array's entries
+    free: Vec<u32>, // Entries of unloaded textures, to be re-used by new textures
+}
+
 struct Textures {
     sizes: Vec<u32>,
-    id_registry: Vec<TextureLocation>, // The entries in here point to inside images and texture_array
-    images: Vec<Vec<RawImageData>>, // This should match directly to texture_array's entries
+    id_registry: Vec<TextureLocation>, // The entries in here point to inside groups and texture_array
+    id_options: Vec<TextureOptions>, // The options textures were loaded with, to move them between groups
+    groups: Vec<TextureGroup>, // This should match directly to texture_array's entries
     texture_arrays: Option<Vec<SrgbTexture2dArray>>,
 }
 
 impl Textures {
     fn new() -> Self {
-        let sizes = vec!(16, 32, 64, 128, 256, 512, 1024, 2048);
-        let images = vec![Vec::new(); sizes.len()];
+        let missing = texture::missing_texture();
+        let dimensions = missing.dimensions();
 
         Textures {
-            sizes: sizes,
+            sizes: vec!(16, 32, 64, 128, 256, 512, 1024, 2048),
             id_registry: Vec::new(),
-            images: images,
+            id_options: Vec::new(),
+            groups: vec!(TextureGroup {
+                size: dimensions.0,
+                options: TextureOptions::default(),
+                images: vec!((missing.into_raw(), dimensions)),
+                free: Vec::new(),
+            }),
             texture_arrays: None,
         }
     }
 
-    fn load(&mut self, path: &str) -> u32 {
-        // Load the image
-        let image_file = image::load(
-            File::open(path).unwrap(), image::PNG
-        ).unwrap().to_rgba();
+    /// Loads a texture under the next ID. If it can't be loaded the placeholder is used in its
+    /// place, so the IDs stay in sync with the frontend, and the error is returned.
+    fn load(&mut self, id: TextureId, source: &TextureSource, options: TextureOptions) -> Result<(), String> {
+        assert_eq!(self.id_registry.len() as u32, id.raw());
+        self.id_registry.push(MISSING_LOCATION);
+        self.id_options.push(options);
+
+        let image = try!(self.decode(source));
+        let location = try!(self.store(image, options));
+        self.id_registry[id.raw() as usize] = location;
+
+        Ok(())
+    }
+
+    /// Replaces the image of an already loaded texture, returns true if the texture had to be
+    /// moved to a different texture array. If the new image can't be stored the old one is kept.
+    fn reload(&mut self, id: TextureId, source: &TextureSource) -> Result<bool, String> {
+        let image = try!(self.decode(source));
+        let location = self.get(id);
+
+        // If the size is the same we can just replace the image in place, unless it's the shared
+        // placeholder
+        if location != MISSING_LOCATION && self.groups[location.array as usize].size == (image.1).0 {
+            self.groups[location.array as usize].images[location.index as usize] = image;
+            self.texture_arrays = None;
+            return Ok(false);
+        }
+
+        // If not, we need to move it to a different array, leaving its old entry to be re-used
+        let options = self.id_options[id.raw() as usize];
+        let new_location = try!(self.store(image, options));
+        self.free(location);
+        self.id_registry[id.raw() as usize] = new_location;
+        Ok(true)
+    }
+
+    /// Frees the entry of a texture so a new texture can be stored in its place. The ID shows the
+    /// placeholder from now on, so unloading it again does nothing.
+    fn unload(&mut self, id: TextureId) {
+        let location = self.get(id);
+        self.free(location);
+        self.id_registry[id.raw() as usize] = MISSING_LOCATION;
+    }
+
+    fn free(&mut self, location: TextureLocation) {
+        if location != MISSING_LOCATION {
+            self.groups[location.array as usize].free.push(location.index);
+        }
+    }
 
+    fn decode(&self, source: &TextureSource) -> Result<RawImageData, String> {
         // Get the texture data from the image
+        let image_file = try!(source.decode());
         let image_dimensions = image_file.dimensions();
         let image_raw = image_file.into_raw();
 
         // Make sure the data of the texture follows the requirements
-        assert_eq!(image_dimensions.0, image_dimensions.1); // Square
+        if image_dimensions.0 != image_dimensions.1 || !self.sizes.contains(&image_dimensions.0) {
+            return Err("Texture must be square, power of two and 16x16 or higher.".into());
+        }
 
-        // Find the array to store the texture in, then store it
-        let array = self.sizes.iter().position(|v| *v == image_dimensions.0)
-            .expect("Texture must be power of two and 16x16 or higher.");
-        let index = self.images[array].len();
-        self.images[array].push((image_raw, image_dimensions));
+        Ok((image_raw, image_dimensions))
+    }
 
-        // Store a lookup for this texture
-        let id = self.id_registry.len();
-        self.id_registry.push(TextureLocation {
-            array: array as u32,
-            index: index as u32,
-        });
+    fn store(&mut self, image: RawImageData, options: TextureOptions) -> Result<TextureLocation, String> {
+        // Find the group to store the texture in, or create a new one, then store it. The
+        // placeholder's group is skipped, its only entry can't be re-used.
+        let size = (image.1).0;
+        let existing = self.groups.iter().skip(1).position(|g| g.size == size && g.options == options);
+        let array = match existing {
+            Some(array) => array + 1,
+            None => {
+                if self.groups.len() >= MAX_TEXTURE_ARRAYS {
+                    return Err(format!(
+                        "Can't use more than {} combinations of texture sizes and options at once",
+                        MAX_TEXTURE_ARRAYS - 1
+                    ));
+                }
+
+                self.groups.push(TextureGroup {
+                    size: size,
+                    options: options,
+                    images: Vec::new(),
+                    free: Vec::new(),
+                });
+                self.groups.len() - 1
+            }
+        };
+        let index = {
+            let group = &mut self.groups[array];
+            match group.free.pop() {
+                Some(index) => {
+                    group.images[index as usize] = image;
+                    index as usize
+                },
+                None => {
+                    group.images.push(image);
+                    group.images.len() - 1
+                }
+            }
+        };
 
         // Invalidate the texture arrays because of the new texture
-        //TODO: Allow texture unloading and re-use reclaimed space
         self.texture_arrays = None;
 
-        // Return the id of the texture
-        id as u32
+        Ok(TextureLocation {
+            array: array as u32,
+            index: index as u32,
+        })
     }
 
-    fn prepare_for_frame(&mut self, display: &GlutinFacade) {
+    fn prepare_for_frame(&mut self, display: &GlutinFacade) -> Result<(), FrontendError> {
         // If the texture arrays exists, we don't need to regenerate
         if self.texture_arrays.is_some() {
-            return;
+            return Ok(());
         }
 
         let mut arrays = Vec::new();
 
-        // Go through all the images
-        for image_array in &self.images {
+        // Go through all the groups
+        for group in &self.groups {
             let mut textures = Vec::new();
 
             // Go through all the images for this array
-            for image in image_array {
+            for image in &group.images {
                 let texture = RawImage2d::from_raw_rgba_reversed(
                     image.0.clone(), image.1
                 );
@@ -125,28 +261,36 @@ impl Textures {
             }
 
             // Create and store the texture array
-            // If we have 0 textures, just create an empty one with space for one
-            let array = if textures.len() != 0 {
-                SrgbTexture2dArray::new(display, textures).unwrap()
+            let mipmaps = if group.options.mipmaps {
+                MipmapsOption::AutoGeneratedMipmaps
             } else {
-                SrgbTexture2dArray::empty(display, 1, 1, 1).unwrap()
+                MipmapsOption::NoMipmap
             };
+            let array = try!(
+                SrgbTexture2dArray::with_mipmaps(display, textures, mipmaps)
+                    .map_err(|e| FrontendError::Draw(format!("Could not create texture array: {:?}", e)))
+            );
             arrays.push(array);
         }
 
         // Store the new texture arrays
         self.texture_arrays = Some(arrays);
+
+        Ok(())
     }
 
     fn samplers<'a>(&'a self) -> Vec<Sampler<'a, SrgbTexture2dArray>> {
         let arrays = self.texture_arrays.as_ref().unwrap();
 
         let mut samplers = Vec::new();
-        for array in arrays {
+        for (array, group) in arrays.iter().zip(&self.groups) {
+            let options = group.options;
             samplers.push(
                 array
                     .sampled()
-                    .magnify_filter(MagnifySamplerFilter::Linear)
+                    .magnify_filter(magnify_filter(options.magnify))
+                    .minify_filter(minify_filter(options.minify, options.mipmaps))
+                    .wrap_function(wrap_function(options.wrap))
             );
         }
 
@@ -158,29 +302,142 @@ impl Textures {
     }
 }
 
+fn magnify_filter(filter: Filter) -> MagnifySamplerFilter {
+    match filter {
+        Filter::Nearest => MagnifySamplerFilter::Nearest,
+        Filter::Linear => MagnifySamplerFilter::Linear,
+    }
+}
+
+fn minify_filter(filter: Filter, mipmaps: bool) -> MinifySamplerFilter {
+    match (filter, mipmaps) {
+        (Filter::Nearest, false) => MinifySamplerFilter::Nearest,
+        (Filter::Linear, false) => MinifySamplerFilter::Linear,
+        (Filter::Nearest, true) => MinifySamplerFilter::NearestMipmapNearest,
+        (Filter::Linear, true) => MinifySamplerFilter::LinearMipmapLinear,
+    }
+}
+
+fn wrap_function(wrap: WrapMode) -> SamplerWrapFunction {
+    match wrap {
+        WrapMode::Clamp => SamplerWrapFunction::Clamp,
+        WrapMode::Repeat => SamplerWrapFunction::Repeat,
+        WrapMode::MirroredRepeat => SamplerWrapFunction::Mirror,
+    }
+}
+
+struct CachedTileMap {
+    tilemap: TileMap,
+    chunks: Vec<Option<VertexBuffer<Vertex2D>>>, // None if the chunk has to be rebuilt
+}
+
+impl CachedTileMap {
+    fn new(tilemap: TileMap) -> Self {
+        let chunks = tilemap.chunks();
+        let amount = (chunks[0] * chunks[1]) as usize;
+
+        CachedTileMap {
+            tilemap: tilemap,
+            chunks: (0..amount).map(|_| None).collect(),
+        }
+    }
+
+    fn set(&mut self, position: [u32; 2], tile: Option<u32>) {
+        self.tilemap.set(position, tile);
+
+        // Invalidate the chunk the tile is in
+        let index = self.chunk_index([position[0] / CHUNK_SIZE, position[1] / CHUNK_SIZE]);
+        self.chunks[index] = None;
+    }
+
+    fn invalidate(&mut self) {
+        for chunk in &mut self.chunks {
+            *chunk = None;
+        }
+    }
+
+    fn chunk_index(&self, chunk: [u32; 2]) -> usize {
+        (chunk[1] * self.tilemap.chunks()[0] + chunk[0]) as usize
+    }
+
+    fn build_chunk(
+        &mut self, display: &GlutinFacade, textures: &Textures, chunk: [u32; 2]
+    ) -> Result<(), FrontendError> {
+        let index = self.chunk_index(chunk);
+        if self.chunks[index].is_some() {
+            return Ok(());
+        }
+
+        // Chunks are built relative to the tilemap, so the tilemap can be moved for free
+        let tex_data = textures.get(self.tilemap.tileset());
+        let tile_size = self.tilemap.tile_size();
+        let mut vertices = Vec::new();
+        {
+            let tilemap = &self.tilemap;
+            tilemap.for_each_in_chunk(chunk, |position, tile| {
+                let low = [position[0] as f32 * tile_size[0], position[1] as f32 * tile_size[1]];
+                let high = [low[0] + tile_size[0], low[1] + tile_size[1]];
+                let (uv_low, uv_high) = tilemap.tile_uv(tile);
+
+                push_quad(&mut vertices, low, high, uv_low, uv_high, tex_data, [1.0, 1.0, 1.0, 1.0]);
+            });
+        }
+
+        self.chunks[index] = Some(try!(vertex_buffer(display, &vertices)));
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, PartialEq)]
+enum WatchedAsset {
+    Texture(TextureId),
+    Shaders,
+}
+
 pub struct FrontendRuntime {
-    event_send: Sender<Event>,
+    event_send: Sender<RuntimeEvent>,
     command_recv: Receiver<FrontendCommand>,
-    batch_return_send: Sender<FrameRenderInfo>,
+    batch_return_send: Sender<ReturnedFrame>,
 
     display: GlutinFacade,
     program: Program,
+    shader_paths: Option<(PathBuf, PathBuf)>,
 
     textures: Textures,
+    tilemaps: Vec<CachedTileMap>,
+
+    watcher: FileWatcher<WatchedAsset>,
+    hot_reload: bool,
+
+    screenshot: Option<PathBuf>,
+    capture: Option<(PathBuf, u32)>,
+    image_send: Sender<PendingImage>,
+
+    frame_query: Option<TimeElapsedQuery>,
+    pending_queries: Vec<TimeElapsedQuery>, // Oldest first
+    gpu_time: Option<Duration>,
 }
 
 impl FrontendRuntime {
     pub fn start(
-        event_send: Sender<Event>,
-        command_recv: Receiver<FrontendCommand>, batch_return_send: Sender<FrameRenderInfo>
+        event_send: Sender<RuntimeEvent>,
+        command_recv: Receiver<FrontendCommand>, batch_return_send: Sender<ReturnedFrame>
     ) -> JoinHandle<()> {
         let init_barrier = Arc::new(Barrier::new(2));
         let barrier_clone = init_barrier.clone();
 
         // Actually start the runtime thread
         let handle = thread::spawn(move || {
-            let runtime = FrontendRuntime::new(event_send, command_recv, batch_return_send);
-            runtime.run(barrier_clone);
+            let error_send = event_send.clone();
+            match FrontendRuntime::new(event_send, command_recv, batch_return_send) {
+                Ok(runtime) => runtime.run(barrier_clone),
+                Err(error) => {
+                    // We can't run without a window, report why and let the frontend continue
+                    let _ = error_send.send(RuntimeEvent::Error(error));
+                    barrier_clone.wait();
+                }
+            }
         });
 
         // Wait for the runtime to be done initializing
@@ -190,30 +447,50 @@ impl FrontendRuntime {
     }
 
     fn new(
-        event_send: Sender<Event>,
-        command_recv: Receiver<FrontendCommand>, batch_return_send: Sender<FrameRenderInfo>
-    ) -> Self {
-        let display = WindowBuilder::new()
-            .with_dimensions(1280, 720)
-            .with_title("Tungsten".into())
-            .build_glium().unwrap();
-
-        let program = Program::from_source(
-            &display,
-            include_str!("shader.vert.glsl"), include_str!("shader.frag.glsl"),
-            None
-        ).unwrap();
+        event_send: Sender<RuntimeEvent>,
+        command_recv: Receiver<FrontendCommand>, batch_return_send: Sender<ReturnedFrame>
+    ) -> Result<Self, FrontendError> {
+        let display = try!(
+            WindowBuilder::new()
+                .with_dimensions(1280, 720)
+                .with_title("Tungsten".into())
+                .build_glium()
+                .map_err(|e| FrontendError::WindowCreation(format!("{:?}", e)))
+        );
+
+        let program = try!(
+            Program::from_source(
+                &display,
+                include_str!("shader.vert.glsl"), include_str!("shader.frag.glsl"),
+                None
+            ).map_err(|e| FrontendError::ProgramCreation(format!("{:?}", e)))
+        );
+
+        let image_send = start_image_saver(event_send.clone());
 
-        FrontendRuntime {
+        Ok(FrontendRuntime {
             event_send: event_send,
             command_recv: command_recv,
             batch_return_send: batch_return_send,
 
             display: display,
             program: program,
+            shader_paths: None,
 
             textures: Textures::new(),
-        }
+            tilemaps: Vec::new(),
+
+            watcher: FileWatcher::new(Duration::from_millis(500)),
+            hot_reload: cfg!(debug_assertions),
+
+            screenshot: None,
+            capture: None,
+            image_send: image_send,
+
+            frame_query: None,
+            pending_queries: Vec::new(),
+            gpu_time: None,
+        })
     }
 
     fn run(mut self, init_barrier: Arc<Barrier>) {
@@ -221,93 +498,324 @@ impl FrontendRuntime {
 
         // Actually run the frontend loop
         loop {
-            // Get the next queued command
-            let command = self.command_recv.recv().unwrap();
+            // Get the next queued command, if the frontend is gone we should stop
+            let command = if let Ok(command) = self.command_recv.recv() {
+                command
+            } else {
+                return;
+            };
 
             // Handle the command
             match command {
-                FrontendCommand::Frame(frame) => {
+                FrontendCommand::Frame(frame, build_time) => {
                     // Check events for this frame
                     // We could do this separated from rendering but it's simpler if we just
                     // block on the recv.
-                    for ev in self.display.poll_events() {
-                        self.event_send.send(ev).unwrap();
+                    self.poll_events();
+
+                    // Pick up any changes to files we've loaded
+                    if self.hot_reload {
+                        self.reload_changed();
                     }
 
-                    // Render the frame
-                    let glium_frame = self.render_frame(&frame);
+                    // Time the frame on the GPU if we can, not all drivers support this
+                    let start = Instant::now();
+                    self.frame_query = TimeElapsedQuery::new(&self.display).ok();
+
+                    // Render the frame, if part of it fails we still finish what we have
+                    let mut stats = RenderStats::new();
+                    let mut glium_frame = self.display.draw();
+                    if let Err(error) = self.render_frame(&mut glium_frame, &frame, &mut stats) {
+                        self.send_event(RuntimeEvent::Error(error));
+                    }
+                    stats.cpu_time = start.elapsed();
+                    stats.build_time = build_time;
+                    stats.gpu_time = self.poll_gpu_time();
 
                     // Return the batch and finish the frame (flipping the buffers)
-                    self.batch_return_send.send(frame).unwrap();
-                    glium_frame.finish().unwrap();
+                    let _ = self.batch_return_send.send((frame, Some(stats)));
+                    match glium_frame.finish() {
+                        Ok(()) => {
+                            // Now that the frame is in the front buffer, save it if we were asked to
+                            self.save_captures();
+                        },
+                        Err(SwapBuffersError::ContextLost) => {
+                            // Nothing we can do without a context, stop the runtime
+                            self.send_event(RuntimeEvent::Error(FrontendError::ContextLost));
+                            return;
+                        },
+                        Err(SwapBuffersError::AlreadySwapped) => {
+                            let error = FrontendError::Present("Buffers were already swapped".into());
+                            self.send_event(RuntimeEvent::Error(error));
+                        },
+                    }
+                },
+                FrontendCommand::PollEvents => {
+                    self.poll_events();
+                },
+                FrontendCommand::LoadTexture(id, source, options) => {
+                    // If the texture can't be loaded a placeholder is used, report why
+                    if let Err(e) = self.textures.load(id, &source, options) {
+                        self.send_event(RuntimeEvent::Error(FrontendError::TextureLoad(id, e)));
+                    }
+
+                    if let TextureSource::Path(path) = source {
+                        self.watcher.watch(WatchedAsset::Texture(id), path);
+                    }
+                },
+                FrontendCommand::UnloadTexture(id) => {
+                    self.textures.unload(id);
+                    self.watcher.unwatch(&WatchedAsset::Texture(id));
+                },
+                FrontendCommand::LoadShaders(vertex, fragment) => {
+                    // Replace the files of previous shaders, those shouldn't reload anything
+                    self.watcher.unwatch(&WatchedAsset::Shaders);
+                    self.watcher.watch(WatchedAsset::Shaders, vertex.clone());
+                    self.watcher.watch(WatchedAsset::Shaders, fragment.clone());
+                    self.shader_paths = Some((vertex.clone(), fragment));
+
+                    // If the shaders fail, report it and keep using the ones we had
+                    let result = self.load_shaders();
+                    self.send_event(RuntimeEvent::Reload(vertex, result));
+                },
+                FrontendCommand::SetHotReload(enabled) => {
+                    self.hot_reload = enabled;
+                },
+                FrontendCommand::CreateTileMap(id, tilemap) => {
+                    assert_eq!(self.tilemaps.len() as u32, id.raw());
+                    self.tilemaps.push(CachedTileMap::new(tilemap));
+                },
+                FrontendCommand::SetTile(id, position, tile) => {
+                    self.tilemaps[id.raw() as usize].set(position, tile);
+                },
+                FrontendCommand::Screenshot(path) => {
+                    self.screenshot = Some(path);
                 },
-                FrontendCommand::LoadTexture(id, path) => {
-                    let loaded_at = self.textures.load(&path);
-                    assert_eq!(loaded_at, id.raw());
+                FrontendCommand::StartCapture(directory) => {
+                    match fs::create_dir_all(&directory) {
+                        Ok(()) => self.capture = Some((directory, 0)),
+                        Err(e) => {
+                            let error = format!("Could not create {}: {}", directory.display(), e);
+                            self.send_event(RuntimeEvent::Screenshot(Err(error)));
+                        },
+                    }
+                },
+                FrontendCommand::StopCapture => {
+                    self.capture = None;
                 }
             }
         }
     }
 
-    fn render_frame(&mut self, info: &FrameRenderInfo) -> Frame {
-        // Prepare for the frame
-        self.textures.prepare_for_frame(&self.display);
+    fn poll_events(&self) {
+        for ev in self.display.poll_events() {
+            self.send_event(RuntimeEvent::Window(ev));
+        }
+    }
+
+    fn send_event(&self, event: RuntimeEvent) {
+        // If the frontend is gone the next recv fails and stops the runtime, nothing to do here
+        let _ = self.event_send.send(event);
+    }
+
+    /// Returns the GPU time of the most recent frame that finished rendering.
+    fn poll_gpu_time(&mut self) -> Option<Duration> {
+        if let Some(query) = self.frame_query.take() {
+            self.pending_queries.push(query);
+        }
+
+        // Don't wait on the GPU, just take the results that are already there
+        while !self.pending_queries.is_empty() && self.pending_queries[0].is_ready() {
+            let nanoseconds = self.pending_queries.remove(0).get();
+            self.gpu_time = Some(Duration::new(0, nanoseconds));
+        }
+
+        self.gpu_time
+    }
+
+    fn reload_changed(&mut self) {
+        // Both shader files are compiled together, so they only need to be reloaded once per poll
+        let mut shaders_reloaded = false;
 
-        // Start a new frame
-        let mut frame = self.display.draw();
+        for (asset, path) in self.watcher.poll() {
+            let result = match asset {
+                WatchedAsset::Texture(id) => self.reload_texture(id, &path),
+                WatchedAsset::Shaders => {
+                    if shaders_reloaded {
+                        continue;
+                    }
+                    shaders_reloaded = true;
+                    self.load_shaders()
+                },
+            };
+            self.send_event(RuntimeEvent::Reload(path, result));
+        }
+    }
+
+    fn reload_texture(&mut self, id: TextureId, path: &Path) -> Result<(), String> {
+        let moved = try!(self.textures.reload(id, &TextureSource::Path(path.to_path_buf())));
+
+        // Tilemap chunks know where their tileset is stored, so they have to be rebuilt if it moved
+        if moved {
+            for tilemap in &mut self.tilemaps {
+                tilemap.invalidate();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn load_shaders(&mut self) -> Result<(), String> {
+        let &(ref vertex, ref fragment) = self.shader_paths.as_ref().unwrap();
+        let vertex_source = try!(read_to_string(vertex));
+        let fragment_source = try!(read_to_string(fragment));
+
+        let program = try!(
+            Program::from_source(&self.display, &vertex_source, &fragment_source, None)
+                .map_err(|e| format!("Could not compile shaders: {:?}", e))
+        );
+        self.program = program;
+
+        Ok(())
+    }
+
+    fn save_captures(&mut self) {
+        if let Some(path) = self.screenshot.take() {
+            self.save_front_buffer(path);
+        }
+
+        let capture_path = if let Some((ref directory, ref mut number)) = self.capture {
+            *number += 1;
+            Some(directory.join(format!("frame_{:05}.png", number)))
+        } else {
+            None
+        };
+        if let Some(path) = capture_path {
+            self.save_front_buffer(path);
+        }
+    }
+
+    /// Reads back the frame and hands it to the image saver, which raises the result.
+    fn save_front_buffer(&self, path: PathBuf) {
+        let raw: RawImage2d<u8> = self.display.read_front_buffer();
+        let image = PendingImage {
+            path: path,
+            width: raw.width,
+            height: raw.height,
+            data: raw.data.into_owned(),
+        };
+        let _ = self.image_send.send(image);
+    }
+
+    fn render_frame(
+        &mut self, frame: &mut Frame, info: &FrameRenderInfo, stats: &mut RenderStats
+    ) -> Result<(), FrontendError> {
         frame.clear_color(0.05, 0.05, 0.05, 1.0);
 
+        // Prepare for the frame
+        try!(self.textures.prepare_for_frame(&self.display));
+
         // Go over all the cameras
         // TODO: Support nested cameras
+        let (width, height) = frame.get_dimensions();
         for layer in info.layers() {
             if let &LayerInfo::Camera(ref camera) = layer {
-                self.render_camera(&mut frame, camera);
+                let view = CameraView::new(&camera.viewport(), [width, height]);
+                try!(self.render_camera(frame, camera, view, stats));
             } else {
-                unimplemented!();
+                return Err(FrontendError::Draw("Only cameras can be drawn in a frame".into()));
             }
         }
 
-        frame
+        Ok(())
     }
 
-    fn render_camera(&mut self, frame: &mut Frame, camera: &GameCameraInfo) {
-        // Go over all the batches
+    fn render_camera(
+        &mut self, frame: &mut Frame, camera: &GameCameraInfo, mut view: CameraView, stats: &mut RenderStats
+    ) -> Result<(), FrontendError> {
+        // Nothing to draw if the camera's viewport is entirely off screen
+        if view.viewport.width == 0 || view.viewport.height == 0 {
+            return Ok(());
+        }
+
+        // Go over all the layers, with batches merged and sorted
         // TODO: Support nested cameras
-        for layer in camera.layers() {
-            if let &LayerInfo::Batch(ref batch) = layer {
-                self.render_batch(frame, camera, batch);
-            } else {
-                unimplemented!();
+        let mut clips = ClipStack::new(view.viewport);
+        for step in order::draw_steps(camera.layers()) {
+            // Clips change what part of the viewport we draw to for the steps after them
+            match step {
+                DrawStep::Layer(&LayerInfo::PushClip(clip)) => {
+                    clips.push(view.to_pixels(camera.position(), camera.zoom(), clip.position, clip.size));
+                    view.clip = clips.current();
+                    continue;
+                },
+                DrawStep::Layer(&LayerInfo::PopClip) => {
+                    clips.pop();
+                    view.clip = clips.current();
+                    continue;
+                },
+                _ => (),
             }
+
+            stats.batches += 1;
+            try!(match step {
+                DrawStep::Rectangles(rectangles) =>
+                    self.render_batch(frame, camera, view, &rectangles, stats),
+                DrawStep::Layer(&LayerInfo::TileMap(ref tilemap)) =>
+                    self.render_tilemap(frame, camera, view, tilemap, stats),
+                DrawStep::Layer(&LayerInfo::Background(ref background)) =>
+                    self.render_background(frame, camera, view, background, stats),
+                _ => Err(FrontendError::Draw("Cameras can't be drawn in cameras".into())),
+            });
         }
+
+        Ok(())
     }
 
-    fn render_batch(&mut self, frame: &mut Frame, camera: &GameCameraInfo, batch: &RenderBatchInfo) {
+    fn render_batch(
+        &mut self, frame: &mut Frame, camera: &GameCameraInfo, view: CameraView,
+        rectangles: &[&Rectangle], stats: &mut RenderStats
+    ) -> Result<(), FrontendError> {
         // Create the uniforms for the camera
         // TODO: Share between batches
-        let proj_matrix: Matrix3<f32> = [
-            [2.0/1280.0, 0.0, 0.0],
-            [0.0, 2.0/720.0, 0.0],
-            [0.0, 0.0, 1.0]
-        ].into();
-
-        let cam_pos = camera.position();
-        let view_matrix: Matrix3<f32> = [
-            [1.0, 0.0, 0.0],
-            [0.0, 1.0, 0.0],
-            [-cam_pos[0], -cam_pos[1], 1.0]
-        ].into();
-
-        let matrix_raw: [[f32; 3]; 3] = (proj_matrix * view_matrix).into();
         let uniforms = Uniforms2D {
-            matrix: matrix_raw,
+            matrix: camera_matrix(camera.position(), view.area(camera.zoom()), [0.0, 0.0]),
             samplers: &self.textures.samplers(),
         };
 
+        self.render_rectangles(frame, &uniforms, view, rectangles, stats)
+    }
+
+    fn render_background(
+        &mut self, frame: &mut Frame, camera: &GameCameraInfo, view: CameraView,
+        background: &BackgroundInfo, stats: &mut RenderStats
+    ) -> Result<(), FrontendError> {
+        // Backgrounds are seen from their own position and zoom depending on their parallax
+        let (position, zoom) = background.view(camera.position(), camera.zoom());
+        let area = view.area(zoom);
+        let generated = background.rectangles(position, area);
+        let rects: Vec<&Rectangle> = generated.iter().collect();
+
+        let uniforms = Uniforms2D {
+            matrix: camera_matrix(position, area, [0.0, 0.0]),
+            samplers: &self.textures.samplers(),
+        };
+
+        self.render_rectangles(frame, &uniforms, view, &rects, stats)
+    }
+
+    fn render_rectangles(
+        &self, frame: &mut Frame, uniforms: &Uniforms2D, view: CameraView,
+        rectangles: &[&Rectangle], stats: &mut RenderStats
+    ) -> Result<(), FrontendError> {
+        if rectangles.is_empty() {
+            return Ok(());
+        }
+
         // Create all the vertices for the rectangles
         // TODO: Use a persistent memory mapped buffer
         let mut vertices = Vec::new();
-        for rect in batch.rectangles() {
+        for rect in rectangles {
             let pos = &rect.position;
             let size = &rect.size;
             let size = [size[0] * 0.5, size[1] * 0.5];
@@ -315,53 +823,234 @@ impl FrontendRuntime {
             // Get the texture data
             let tex_data = self.textures.get(rect.texture);
 
-            vertices.push(Vertex2D {
-                i_position: [pos[0] - size[0], pos[1] - size[1]],
-                i_texture_coord: [0.0, 0.0],
-                i_sampler_id: tex_data.array, i_texture_id: tex_data.index,
-            });
-            vertices.push(Vertex2D {
-                i_position: [pos[0] + size[0], pos[1] - size[1]],
-                i_texture_coord: [1.0, 0.0],
-                i_sampler_id: tex_data.array, i_texture_id: tex_data.index,
-            });
-            vertices.push(Vertex2D {
-                i_position: [pos[0] + size[0], pos[1] + size[1]],
-                i_texture_coord: [1.0, 1.0],
-                i_sampler_id: tex_data.array, i_texture_id: tex_data.index,
-            });
-
-            vertices.push(Vertex2D {
-                i_position: [pos[0] - size[0], pos[1] - size[1]],
-                i_texture_coord: [0.0, 0.0],
-                i_sampler_id: tex_data.array, i_texture_id: tex_data.index,
-            });
-            vertices.push(Vertex2D {
-                i_position: [pos[0] + size[0], pos[1] + size[1]],
-                i_texture_coord: [1.0, 1.0],
-                i_sampler_id: tex_data.array, i_texture_id: tex_data.index,
-            });
-            vertices.push(Vertex2D {
-                i_position: [pos[0] - size[0], pos[1] + size[1]],
-                i_texture_coord: [0.0, 1.0],
-                i_sampler_id: tex_data.array, i_texture_id: tex_data.index,
-            });
+            let region = &rect.region;
+            push_quad(
+                &mut vertices,
+                [pos[0] - size[0], pos[1] - size[1]], [pos[0] + size[0], pos[1] + size[1]],
+                [region[0], region[1]], [region[2], region[3]], tex_data, rect.color
+            );
         }
 
         // Turn the vertices into a vertex buffer
-        let vertex_buffer = VertexBuffer::new(&self.display, &vertices).unwrap();
+        let vertex_buffer = try!(vertex_buffer(&self.display, &vertices));
         let indices = NoIndices(PrimitiveType::TrianglesList);
 
-        // Set up the draw parameters
-        let params = DrawParameters {
-            blend: Blend::alpha_blending(),
-            .. Default::default()
-        };
-
         // Actually do the draw call
+        stats.draw_calls += 1;
+        stats.vertices += vertices.len() as u32;
+        stats.sampled_textures += uniforms.samplers.len() as u32;
         frame.draw(
             &vertex_buffer, &indices, &self.program,
-            &uniforms, &params
-        ).unwrap();
+            uniforms, &draw_parameters(view, self.frame_query.as_ref())
+        ).map_err(|e| FrontendError::Draw(format!("{:?}", e)))
+    }
+
+    fn render_tilemap(
+        &mut self, frame: &mut Frame, camera: &GameCameraInfo, view: CameraView, info: &TileMapInfo,
+        stats: &mut RenderStats
+    ) -> Result<(), FrontendError> {
+        let cached = &mut self.tilemaps[info.tilemap().raw() as usize];
+
+        // Find out which chunks we can see, and make sure they're built
+        let area = view.area(camera.zoom());
+        let (start, end) = cached.tilemap.visible_chunks(info.position(), camera.position(), area);
+        for y in start[1]..end[1] {
+            for x in start[0]..end[0] {
+                try!(cached.build_chunk(&self.display, &self.textures, [x, y]));
+            }
+        }
+
+        // The chunks are relative to the tilemap, so offset by its position
+        let uniforms = Uniforms2D {
+            matrix: camera_matrix(camera.position(), area, info.position()),
+            samplers: &self.textures.samplers(),
+        };
+        let indices = NoIndices(PrimitiveType::TrianglesList);
+
+        // Draw all the visible chunks
+        for y in start[1]..end[1] {
+            for x in start[0]..end[0] {
+                let index = cached.chunk_index([x, y]);
+                let vertex_buffer = cached.chunks[index].as_ref().unwrap();
+
+                stats.draw_calls += 1;
+                stats.vertices += vertex_buffer.len() as u32;
+                stats.sampled_textures += uniforms.samplers.len() as u32;
+
+                try!(
+                    frame.draw(
+                        vertex_buffer, &indices, &self.program,
+                        &uniforms, &draw_parameters(view, self.frame_query.as_ref())
+                    ).map_err(|e| FrontendError::Draw(format!("{:?}", e)))
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A frame read back from the front buffer, waiting to be saved.
+struct PendingImage {
+    path: PathBuf,
+    width: u32,
+    height: u32,
+    /// Stored with the bottom row first, as OpenGL gives it.
+    data: Vec<u8>,
+}
+
+/// Starts a thread that saves read back frames as PNG, so encoding doesn't hold up rendering. The
+/// thread stops once the runtime drops its sender.
+fn start_image_saver(event_send: Sender<RuntimeEvent>) -> Sender<PendingImage> {
+    let (image_send, image_recv) = mpsc::channel::<PendingImage>();
+
+    thread::spawn(move || {
+        for image in image_recv {
+            let result = save_image(image);
+            let _ = event_send.send(RuntimeEvent::Screenshot(result));
+        }
+    });
+
+    image_send
+}
+
+fn save_image(image: PendingImage) -> Result<PathBuf, String> {
+    let path = image.path;
+    let buffer: RgbaImage = try!(
+        ImageBuffer::from_raw(image.width, image.height, image.data)
+            .ok_or_else(|| "Front buffer data did not match its size".to_string())
+    );
+    let buffer = imageops::flip_vertical(&buffer);
+
+    try!(buffer.save(&path).map_err(|e| format!("Could not save {}: {}", path.display(), e)));
+    Ok(path)
+}
+
+fn read_to_string(path: &Path) -> Result<String, String> {
+    let mut source = String::new();
+    try!(
+        File::open(path).and_then(|mut f| f.read_to_string(&mut source))
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))
+    );
+    Ok(source)
+}
+
+fn vertex_buffer(display: &GlutinFacade, vertices: &[Vertex2D]) -> Result<VertexBuffer<Vertex2D>, FrontendError> {
+    VertexBuffer::new(display, vertices)
+        .map_err(|e| FrontendError::Draw(format!("Could not create vertex buffer: {:?}", e)))
+}
+
+/// Creates the matrix to go from world units offset by a position into the space of a camera
+/// showing an area of world units.
+fn camera_matrix(cam_pos: [f32; 2], area: [f32; 2], offset: [f32; 2]) -> [[f32; 3]; 3] {
+    let proj_matrix: Matrix3<f32> = [
+        [2.0 / area[0], 0.0, 0.0],
+        [0.0, 2.0 / area[1], 0.0],
+        [0.0, 0.0, 1.0]
+    ].into();
+
+    let view_matrix: Matrix3<f32> = [
+        [1.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0],
+        [offset[0] - cam_pos[0], offset[1] - cam_pos[1], 1.0]
+    ].into();
+
+    (proj_matrix * view_matrix).into()
+}
+
+fn draw_parameters<'a>(view: CameraView, query: Option<&'a TimeElapsedQuery>) -> DrawParameters<'a> {
+    // The viewport maps the camera onto its part of the screen, the scissor makes sure nothing
+    // ends up outside of it or the current clip rectangle
+    DrawParameters {
+        blend: Blend::alpha_blending(),
+        viewport: Some(rect(view.viewport)),
+        scissor: Some(rect(view.clip)),
+        time_elapsed_query: query,
+        .. Default::default()
+    }
+}
+
+fn rect(pixels: PixelRect) -> Rect {
+    Rect {
+        left: pixels.left,
+        bottom: pixels.bottom,
+        width: pixels.width,
+        height: pixels.height,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use frontend::TextureId;
+    use texture::{TextureOptions, TextureSource, Filter, WrapMode};
+    use super::{Textures, MISSING_LOCATION, MAX_TEXTURE_ARRAYS};
+
+    fn square(size: u32) -> TextureSource {
+        TextureSource::Rgba(vec![255; (size * size * 4) as usize], [size, size])
+    }
+
+    /// Every combination of options, 24 in total.
+    fn all_options() -> Vec<TextureOptions> {
+        let filters = [Filter::Nearest, Filter::Linear];
+        let wraps = [WrapMode::Clamp, WrapMode::Repeat, WrapMode::MirroredRepeat];
+        let mut options = Vec::new();
+        for &magnify in &filters {
+            for &minify in &filters {
+                for &mipmaps in &[false, true] {
+                    for &wrap in &wraps {
+                        options.push(TextureOptions {
+                            magnify: magnify, minify: minify, mipmaps: mipmaps, wrap: wrap
+                        });
+                    }
+                }
+            }
+        }
+        options
+    }
+
+    #[test]
+    fn uses_placeholder_when_out_of_texture_arrays() {
+        let mut textures = Textures::new();
+        let options = all_options();
+
+        // The placeholder takes up one array, the rest fill up until there's no room left
+        for (i, &options) in options.iter().enumerate() {
+            let id = TextureId::from_raw(i as u32);
+            let result = textures.load(id, &square(32), options);
+            if i < MAX_TEXTURE_ARRAYS - 1 {
+                assert!(result.is_ok());
+                assert!(textures.get(id) != MISSING_LOCATION);
+            } else {
+                assert!(result.is_err());
+                assert_eq!(textures.get(id), MISSING_LOCATION);
+            }
+        }
+        assert_eq!(textures.groups.len(), MAX_TEXTURE_ARRAYS);
+
+        // Textures in arrays that already exist still load
+        let id = TextureId::from_raw(options.len() as u32);
+        assert!(textures.load(id, &square(32), options[0]).is_ok());
+
+        // Moving to a size without an array keeps the old texture
+        let location = textures.get(id);
+        assert!(textures.reload(id, &square(64)).is_err());
+        assert_eq!(textures.get(id), location);
+    }
+
+    #[test]
+    fn unloading_twice_frees_once() {
+        let mut textures = Textures::new();
+        let first = TextureId::from_raw(0);
+        textures.load(first, &square(16), TextureOptions::default()).unwrap();
+        textures.unload(first);
+        textures.unload(first);
+        assert_eq!(textures.get(first), MISSING_LOCATION);
+
+        // Only one new texture gets the freed entry
+        let second = TextureId::from_raw(1);
+        let third = TextureId::from_raw(2);
+        textures.load(second, &square(16), TextureOptions::default()).unwrap();
+        textures.load(third, &square(16), TextureOptions::default()).unwrap();
+        assert!(textures.get(second) != textures.get(third));
     }
 }
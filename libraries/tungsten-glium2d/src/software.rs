@@ -0,0 +1,581 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Sender, Receiver};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use image::{ImageBuffer, RgbaImage, Rgba};
+use {FrameRenderInfo, Rectangle, GameCameraInfo, RenderTarget, LayerInfo, TileMapInfo, BackgroundInfo};
+use error::FrontendError;
+use order::{self, DrawStep};
+use frontend::{FrontendCommand, RuntimeEvent, ReturnedFrame, TextureId, TileMapId};
+use texture::{self, TextureOptions, TextureSource, Filter, WrapMode};
+use tilemap::TileMap;
+use stats::RenderStats;
+use viewport::{CameraView, ClipStack, PixelRect};
+use watcher::FileWatcher;
+
+/// Maps world positions into pixels of the target image, following the same projection as the
+/// GL runtime.
+#[derive(Copy, Clone)]
+struct Projection {
+    position: [f32; 2],
+    area: [f32; 2],
+    offset: [f32; 2],
+    viewport: PixelRect,
+    clip: PixelRect,
+    target_height: u32,
+}
+
+impl Projection {
+    fn to_pixel(&self, point: [f32; 2]) -> [f32; 2] {
+        let ndc = [
+            (point[0] + self.offset[0] - self.position[0]) * 2.0 / self.area[0],
+            (point[1] + self.offset[1] - self.position[1]) * 2.0 / self.area[1],
+        ];
+
+        // Images have their origin in the top left, rather than the bottom left
+        let top = self.target_height - self.viewport.bottom - self.viewport.height;
+        [
+            self.viewport.left as f32 + (ndc[0] + 1.0) * 0.5 * self.viewport.width as f32,
+            top as f32 + (1.0 - ndc[1]) * 0.5 * self.viewport.height as f32,
+        ]
+    }
+}
+
+/// Renders frames on the CPU into an RGBA image, without needing a GPU. Useful for testing views
+/// and for running without OpenGL. Blending happens directly on the sRGB values, so results may
+/// differ very slightly from the GL runtime. Mipmaps are not supported, minified textures are
+/// sampled directly with the minify filter.
+pub struct SoftwareRenderer {
+    size: [u32; 2],
+    clear_color: [u8; 4],
+    textures: Vec<(RgbaImage, TextureOptions)>,
+    tilemaps: Vec<TileMap>,
+}
+
+impl SoftwareRenderer {
+    pub fn new(width: u32, height: u32) -> Self {
+        SoftwareRenderer {
+            size: [width, height],
+            clear_color: [13, 13, 13, 255],
+            textures: Vec::new(),
+            tilemaps: Vec::new(),
+        }
+    }
+
+    pub fn set_clear_color(&mut self, color: [u8; 4]) {
+        self.clear_color = color;
+    }
+
+    /// Loads a texture, textures get the same IDs as they would in the same order through
+    /// `Frontend2D`'s texture loading functions.
+    pub fn load_texture(&mut self, source: &TextureSource, options: TextureOptions)
+        -> Result<TextureId, String> {
+        let image = try!(source.decode());
+        Ok(self.add_texture(image, options))
+    }
+
+    pub fn add_texture(&mut self, image: RgbaImage, options: TextureOptions) -> TextureId {
+        self.textures.push((image, options));
+        TextureId::from_raw(self.textures.len() as u32 - 1)
+    }
+
+    /// Frees the image of a texture, drawing with it afterwards draws the placeholder for missing
+    /// textures, the same as the GL runtime.
+    pub fn unload_texture(&mut self, id: TextureId) {
+        self.textures[id.raw() as usize].0 = texture::missing_texture();
+    }
+
+    /// Replaces the image of an already loaded texture.
+    pub fn reload_texture(&mut self, id: TextureId, source: &TextureSource) -> Result<(), String> {
+        let image = try!(source.decode());
+        self.textures[id.raw() as usize].0 = image;
+        Ok(())
+    }
+
+    pub fn create_tilemap(&mut self, tilemap: TileMap) -> TileMapId {
+        self.tilemaps.push(tilemap);
+        TileMapId::from_raw(self.tilemaps.len() as u32 - 1)
+    }
+
+    pub fn set_tile(&mut self, tilemap: TileMapId, position: [u32; 2], tile: Option<u32>) {
+        self.tilemaps[tilemap.raw() as usize].set(position, tile);
+    }
+
+    pub fn render(&self, info: &FrameRenderInfo) -> Result<RgbaImage, FrontendError> {
+        let mut target = ImageBuffer::from_pixel(self.size[0], self.size[1], Rgba(self.clear_color));
+
+        // Go over all the cameras
+        // TODO: Support nested cameras
+        for layer in info.layers() {
+            if let &LayerInfo::Camera(ref camera) = layer {
+                let view = CameraView::new(&camera.viewport(), self.size);
+                try!(self.render_camera(&mut target, camera, view));
+            } else {
+                return Err(FrontendError::Draw("Only cameras can be drawn in a frame".into()));
+            }
+        }
+
+        Ok(target)
+    }
+
+    fn render_camera(
+        &self, target: &mut RgbaImage, camera: &GameCameraInfo, mut view: CameraView
+    ) -> Result<(), FrontendError> {
+        // Go over all the layers, with batches merged and sorted
+        // TODO: Support nested cameras
+        let mut clips = ClipStack::new(view.viewport);
+        for step in order::draw_steps(camera.layers()) {
+            match step {
+                DrawStep::Layer(&LayerInfo::PushClip(clip)) => {
+                    clips.push(view.to_pixels(camera.position(), camera.zoom(), clip.position, clip.size));
+                    view.clip = clips.current();
+                },
+                DrawStep::Layer(&LayerInfo::PopClip) => {
+                    clips.pop();
+                    view.clip = clips.current();
+                },
+                DrawStep::Rectangles(rectangles) => self.render_batch(target, camera, view, &rectangles),
+                DrawStep::Layer(&LayerInfo::TileMap(ref tilemap)) =>
+                    self.render_tilemap(target, camera, view, tilemap),
+                DrawStep::Layer(&LayerInfo::Background(ref background)) =>
+                    self.render_background(target, camera, view, background),
+                _ => return Err(FrontendError::Draw("Cameras can't be drawn in cameras".into())),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn render_batch(
+        &self, target: &mut RgbaImage, camera: &GameCameraInfo, view: CameraView, rectangles: &[&Rectangle]
+    ) {
+        let projection = self.projection(camera.position(), view, camera.zoom(), [0.0, 0.0]);
+        self.render_rectangles(target, projection, rectangles);
+    }
+
+    fn render_background(
+        &self, target: &mut RgbaImage, camera: &GameCameraInfo, view: CameraView, background: &BackgroundInfo
+    ) {
+        let (position, zoom) = background.view(camera.position(), camera.zoom());
+        let area = view.area(zoom);
+        let generated = background.rectangles(position, area);
+        let rects: Vec<&Rectangle> = generated.iter().collect();
+
+        let projection = self.projection(position, view, zoom, [0.0, 0.0]);
+        self.render_rectangles(target, projection, &rects);
+    }
+
+    fn render_rectangles(&self, target: &mut RgbaImage, projection: Projection, rectangles: &[&Rectangle]) {
+        for rect in rectangles {
+            let size = [rect.size[0] * 0.5, rect.size[1] * 0.5];
+            let low = [rect.position[0] - size[0], rect.position[1] - size[1]];
+            let high = [rect.position[0] + size[0], rect.position[1] + size[1]];
+
+            let region = &rect.region;
+            self.draw_quad(
+                target, projection, low, high,
+                [region[0], region[1]], [region[2], region[3]], rect.texture, rect.color
+            );
+        }
+    }
+
+    fn render_tilemap(
+        &self, target: &mut RgbaImage, camera: &GameCameraInfo, view: CameraView, info: &TileMapInfo
+    ) {
+        let tilemap = &self.tilemaps[info.tilemap().raw() as usize];
+        let projection = self.projection(camera.position(), view, camera.zoom(), info.position());
+
+        // Only draw the chunks that are visible
+        let area = view.area(camera.zoom());
+        let (start, end) = tilemap.visible_chunks(info.position(), camera.position(), area);
+        let tile_size = tilemap.tile_size();
+
+        for y in start[1]..end[1] {
+            for x in start[0]..end[0] {
+                tilemap.for_each_in_chunk([x, y], |position, tile| {
+                    let low = [position[0] as f32 * tile_size[0], position[1] as f32 * tile_size[1]];
+                    let high = [low[0] + tile_size[0], low[1] + tile_size[1]];
+                    let (uv_low, uv_high) = tilemap.tile_uv(tile);
+
+                    self.draw_quad(
+                        target, projection, low, high,
+                        uv_low, uv_high, tilemap.tileset(), [1.0, 1.0, 1.0, 1.0]
+                    );
+                });
+            }
+        }
+    }
+
+    fn projection(&self, position: [f32; 2], view: CameraView, zoom: f32, offset: [f32; 2]) -> Projection {
+        Projection {
+            position: position,
+            area: view.area(zoom),
+            offset: offset,
+            viewport: view.viewport,
+            clip: view.clip,
+            target_height: self.size[1],
+        }
+    }
+
+    fn draw_quad(
+        &self, target: &mut RgbaImage, projection: Projection, low: [f32; 2], high: [f32; 2],
+        uv_low: [f32; 2], uv_high: [f32; 2], texture: TextureId, color: [f32; 4]
+    ) {
+        let (ref texture, options) = self.textures[texture.raw() as usize];
+
+        // Find the area on the target this quad covers, the y axis is flipped in pixels
+        let corner_a = projection.to_pixel(low);
+        let corner_b = projection.to_pixel(high);
+        let pixel_low = [corner_a[0].min(corner_b[0]), corner_a[1].min(corner_b[1])];
+        let pixel_high = [corner_a[0].max(corner_b[0]), corner_a[1].max(corner_b[1])];
+
+        // If there's more than one texel per pixel the texture is being minified
+        let texels = (uv_high[0] - uv_low[0]).abs() * texture.width() as f32;
+        let filter = if texels > pixel_high[0] - pixel_low[0] {
+            options.minify
+        } else {
+            options.magnify
+        };
+
+        // Like the GPU, only fill pixels with their centers inside the quad, and inside the clip
+        let clip = projection.clip;
+        let top = projection.target_height - clip.bottom - clip.height;
+        let (min_x, max_x) = (clip.left, clip.left + clip.width);
+        let (min_y, max_y) = (top, top + clip.height);
+        let start_x = clamp_pixel((pixel_low[0] - 0.5).ceil(), min_x, max_x);
+        let end_x = clamp_pixel((pixel_high[0] - 0.5).ceil(), min_x, max_x);
+        let start_y = clamp_pixel((pixel_low[1] - 0.5).ceil(), min_y, max_y);
+        let end_y = clamp_pixel((pixel_high[1] - 0.5).ceil(), min_y, max_y);
+
+        for y in start_y..end_y {
+            for x in start_x..end_x {
+                // Find the texture coordinate at this pixel's center
+                let factor = [
+                    (x as f32 + 0.5 - corner_a[0]) / (corner_b[0] - corner_a[0]),
+                    (y as f32 + 0.5 - corner_a[1]) / (corner_b[1] - corner_a[1]),
+                ];
+                let uv = [
+                    uv_low[0] + (uv_high[0] - uv_low[0]) * factor[0],
+                    uv_low[1] + (uv_high[1] - uv_low[1]) * factor[1],
+                ];
+
+                let texel = sample(texture, uv, filter, options.wrap);
+                let source = [
+                    texel[0] * color[0], texel[1] * color[1],
+                    texel[2] * color[2], texel[3] * color[3],
+                ];
+                blend(target.get_pixel_mut(x, y), source);
+            }
+        }
+    }
+}
+
+fn clamp_pixel(value: f32, min: u32, max: u32) -> u32 {
+    if value < min as f32 {
+        min
+    } else if value > max as f32 {
+        max
+    } else {
+        value as u32
+    }
+}
+
+/// Samples a texture at a texture coordinate, returned as colors from 0.0 to 1.0.
+fn sample(texture: &RgbaImage, uv: [f32; 2], filter: Filter, wrap: WrapMode) -> [f32; 4] {
+    let (width, height) = texture.dimensions();
+
+    // Textures coordinates start at the bottom left, like they do on the GPU
+    let x = uv[0] * width as f32;
+    let y = (1.0 - uv[1]) * height as f32;
+
+    match filter {
+        Filter::Nearest => texel(texture, x.floor() as i64, y.floor() as i64, wrap),
+        Filter::Linear => {
+            // Interpolate between the four texels around the coordinate
+            let x = x - 0.5;
+            let y = y - 0.5;
+            let (x0, y0) = (x.floor() as i64, y.floor() as i64);
+            let (fx, fy) = (x - x.floor(), y - y.floor());
+
+            let top = lerp(texel(texture, x0, y0, wrap), texel(texture, x0 + 1, y0, wrap), fx);
+            let bottom = lerp(texel(texture, x0, y0 + 1, wrap), texel(texture, x0 + 1, y0 + 1, wrap), fx);
+            lerp(top, bottom, fy)
+        },
+    }
+}
+
+fn texel(texture: &RgbaImage, x: i64, y: i64, wrap: WrapMode) -> [f32; 4] {
+    let x = wrap_coordinate(x, texture.width() as i64, wrap);
+    let y = wrap_coordinate(y, texture.height() as i64, wrap);
+
+    let texel = texture.get_pixel(x as u32, y as u32).data;
+    [
+        texel[0] as f32 / 255.0, texel[1] as f32 / 255.0,
+        texel[2] as f32 / 255.0, texel[3] as f32 / 255.0,
+    ]
+}
+
+fn wrap_coordinate(value: i64, size: i64, wrap: WrapMode) -> i64 {
+    match wrap {
+        WrapMode::Clamp => if value < 0 { 0 } else if value >= size { size - 1 } else { value },
+        WrapMode::Repeat => ((value % size) + size) % size,
+        WrapMode::MirroredRepeat => {
+            let value = ((value % (size * 2)) + size * 2) % (size * 2);
+            if value < size { value } else { size * 2 - 1 - value }
+        },
+    }
+}
+
+fn lerp(a: [f32; 4], b: [f32; 4], factor: f32) -> [f32; 4] {
+    [
+        a[0] + (b[0] - a[0]) * factor, a[1] + (b[1] - a[1]) * factor,
+        a[2] + (b[2] - a[2]) * factor, a[3] + (b[3] - a[3]) * factor,
+    ]
+}
+
+/// Blends a color onto a pixel using standard alpha blending.
+fn blend(pixel: &mut Rgba<u8>, source: [f32; 4]) {
+    let alpha = source[3];
+    for i in 0..4 {
+        let destination = pixel.data[i] as f32 / 255.0;
+        let value = if i < 3 {
+            source[i] * alpha + destination * (1.0 - alpha)
+        } else {
+            alpha + destination * (1.0 - alpha)
+        };
+        pixel.data[i] = (value * 255.0).round().max(0.0).min(255.0) as u8;
+    }
+}
+
+/// Runs the same commands as the GL runtime, but renders them using a `SoftwareRenderer`. There is
+/// no window, so no window events will ever be raised.
+pub struct SoftwareRuntime {
+    event_send: Sender<RuntimeEvent>,
+    command_recv: Receiver<FrontendCommand>,
+    batch_return_send: Sender<ReturnedFrame>,
+
+    renderer: SoftwareRenderer,
+    last_frame: Option<RgbaImage>,
+
+    watcher: FileWatcher<TextureId>,
+    hot_reload: bool,
+
+    screenshot: Option<PathBuf>,
+    capture: Option<(PathBuf, u32)>,
+}
+
+impl SoftwareRuntime {
+    pub fn start(
+        event_send: Sender<RuntimeEvent>,
+        command_recv: Receiver<FrontendCommand>, batch_return_send: Sender<ReturnedFrame>,
+        size: [u32; 2]
+    ) -> JoinHandle<()> {
+        thread::spawn(move || {
+            let runtime = SoftwareRuntime {
+                event_send: event_send,
+                command_recv: command_recv,
+                batch_return_send: batch_return_send,
+
+                renderer: SoftwareRenderer::new(size[0], size[1]),
+                last_frame: None,
+
+                watcher: FileWatcher::new(Duration::from_millis(500)),
+                hot_reload: cfg!(debug_assertions),
+
+                screenshot: None,
+                capture: None,
+            };
+            runtime.run();
+        })
+    }
+
+    fn run(mut self) {
+        loop {
+            // Get the next queued command, if the frontend is gone we should stop
+            let command = if let Ok(command) = self.command_recv.recv() {
+                command
+            } else {
+                return;
+            };
+
+            match command {
+                FrontendCommand::Frame(frame, build_time) => {
+                    if self.hot_reload {
+                        self.reload_changed();
+                    }
+
+                    // If the frame can't be drawn, report it and keep the last one we had
+                    let start = Instant::now();
+                    match self.renderer.render(&frame) {
+                        Ok(image) => self.last_frame = Some(image),
+                        Err(e) => self.send_event(RuntimeEvent::Error(e)),
+                    }
+
+                    let mut stats = RenderStats::new();
+                    stats.cpu_time = start.elapsed();
+                    stats.build_time = build_time;
+                    self.send_batch(frame, stats);
+                    self.save_captures();
+                },
+                FrontendCommand::PollEvents => {
+                    // We don't have a window, so there are never any events
+                },
+                FrontendCommand::LoadTexture(id, source, options) => {
+                    // If the texture can't be loaded, use a placeholder to keep the IDs in sync
+                    let image = match source.decode() {
+                        Ok(image) => image,
+                        Err(e) => {
+                            self.send_event(RuntimeEvent::Error(FrontendError::TextureLoad(id, e)));
+                            texture::missing_texture()
+                        }
+                    };
+                    let loaded_at = self.renderer.add_texture(image, options);
+                    assert_eq!(loaded_at.raw(), id.raw());
+
+                    if let TextureSource::Path(path) = source {
+                        self.watcher.watch(id, path);
+                    }
+                },
+                FrontendCommand::UnloadTexture(id) => {
+                    self.renderer.unload_texture(id);
+                    self.watcher.unwatch(&id);
+                },
+                FrontendCommand::LoadShaders(_, _) => {
+                    // We don't use shaders, nothing to do here
+                },
+                FrontendCommand::SetHotReload(enabled) => {
+                    self.hot_reload = enabled;
+                },
+                FrontendCommand::CreateTileMap(id, tilemap) => {
+                    let created_at = self.renderer.create_tilemap(tilemap);
+                    assert_eq!(created_at.raw(), id.raw());
+                },
+                FrontendCommand::SetTile(id, position, tile) => {
+                    self.renderer.set_tile(id, position, tile);
+                },
+                FrontendCommand::Screenshot(path) => {
+                    self.screenshot = Some(path);
+                },
+                FrontendCommand::StartCapture(directory) => {
+                    match fs::create_dir_all(&directory) {
+                        Ok(()) => self.capture = Some((directory, 0)),
+                        Err(e) => {
+                            let error = format!("Could not create {}: {}", directory.display(), e);
+                            self.send_event(RuntimeEvent::Screenshot(Err(error)));
+                        },
+                    }
+                },
+                FrontendCommand::StopCapture => {
+                    self.capture = None;
+                }
+            }
+        }
+    }
+
+    fn send_event(&self, event: RuntimeEvent) {
+        // If the frontend is gone the next recv fails and stops the runtime, nothing to do here
+        let _ = self.event_send.send(event);
+    }
+
+    fn send_batch(&self, frame: FrameRenderInfo, stats: RenderStats) {
+        let _ = self.batch_return_send.send((frame, Some(stats)));
+    }
+
+    fn reload_changed(&mut self) {
+        for (id, path) in self.watcher.poll() {
+            let result = self.renderer.reload_texture(id, &TextureSource::Path(path.clone()));
+            self.send_event(RuntimeEvent::Reload(path, result));
+        }
+    }
+
+    fn save_captures(&mut self) {
+        if let Some(path) = self.screenshot.take() {
+            let result = self.save_last_frame(&path).map(|_| path);
+            self.send_event(RuntimeEvent::Screenshot(result));
+        }
+
+        let capture_path = if let Some((ref directory, ref mut number)) = self.capture {
+            *number += 1;
+            Some(directory.join(format!("frame_{:05}.png", number)))
+        } else {
+            None
+        };
+        if let Some(path) = capture_path {
+            let result = self.save_last_frame(&path).map(|_| path);
+            self.send_event(RuntimeEvent::Screenshot(result));
+        }
+    }
+
+    fn save_last_frame(&self, path: &Path) -> Result<(), String> {
+        let frame = try!(
+            self.last_frame.as_ref().ok_or_else(|| "No frame has been rendered yet".to_string())
+        );
+        frame.save(path).map_err(|e| format!("Could not save {}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{ImageBuffer, Rgba};
+    use {FrameRenderInfo, RenderTarget, Rectangle, TextureOptions, Viewport};
+    use super::SoftwareRenderer;
+
+    #[test]
+    fn renders_rectangle_at_camera_center() {
+        let mut renderer = SoftwareRenderer::new(128, 72);
+        renderer.set_clear_color([0, 0, 0, 255]);
+        let white = renderer.add_texture(
+            ImageBuffer::from_pixel(16, 16, Rgba([255, 255, 255, 255])), TextureOptions::default()
+        );
+
+        // A 200x200 rectangle in world units is 20x20 pixels at a tenth of the view size
+        let mut frame = FrameRenderInfo::new();
+        frame.game_camera([100.0, 0.0]).batch().rectangle(Rectangle::new([100.0, 0.0], [200.0, 200.0], white));
+        let image = renderer.render(&frame).unwrap();
+
+        assert_eq!(image.get_pixel(64, 36).data, [255, 255, 255, 255]);
+        assert_eq!(image.get_pixel(54, 26).data, [255, 255, 255, 255]);
+        assert_eq!(image.get_pixel(53, 25).data, [0, 0, 0, 255]);
+        assert_eq!(image.get_pixel(74, 46).data, [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn blends_transparent_rectangles() {
+        let mut renderer = SoftwareRenderer::new(16, 16);
+        renderer.set_clear_color([0, 0, 0, 255]);
+        let white = renderer.add_texture(
+            ImageBuffer::from_pixel(16, 16, Rgba([255, 255, 255, 255])), TextureOptions::default()
+        );
+
+        let mut frame = FrameRenderInfo::new();
+        let mut rect = Rectangle::new([0.0, 0.0], [2000.0, 2000.0], white);
+        rect.color = [1.0, 0.0, 0.0, 0.5];
+        frame.game_camera([0.0, 0.0]).batch().rectangle(rect);
+        let image = renderer.render(&frame).unwrap();
+
+        assert_eq!(image.get_pixel(8, 8).data, [128, 0, 0, 255]);
+    }
+
+    #[test]
+    fn clips_cameras_to_their_viewport() {
+        let mut renderer = SoftwareRenderer::new(128, 72);
+        renderer.set_clear_color([0, 0, 0, 255]);
+        let white = renderer.add_texture(
+            ImageBuffer::from_pixel(16, 16, Rgba([255, 255, 255, 255])), TextureOptions::default()
+        );
+
+        // The rectangle covers the whole view, but the camera only draws to the right half
+        let mut frame = FrameRenderInfo::new();
+        {
+            let camera = frame.game_camera([0.0, 0.0]);
+            camera.set_viewport(Viewport::Normalized { position: [0.5, 0.0], size: [0.5, 1.0] });
+            camera.batch().rectangle(Rectangle::new([0.0, 0.0], [2000.0, 2000.0], white));
+        }
+        let image = renderer.render(&frame).unwrap();
+
+        assert_eq!(image.get_pixel(63, 36).data, [0, 0, 0, 255]);
+        assert_eq!(image.get_pixel(64, 36).data, [255, 255, 255, 255]);
+        assert_eq!(image.get_pixel(127, 0).data, [255, 255, 255, 255]);
+    }
+}
@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+/// Measurements of a single rendered frame. Draw calls, vertices, batches and sampled textures are
+/// only counted by the GL runtime.
+#[derive(Clone, Debug, Default)]
+pub struct RenderStats {
+    pub draw_calls: u32,
+    pub vertices: u32,
+    /// The amount of batches, tilemaps and backgrounds rendered.
+    pub batches: u32,
+    /// The amount of texture arrays made available to draw calls, added up over all draw calls.
+    /// Glium decides which of these actually need to be bound again, so this is an upper bound.
+    pub sampled_textures: u32,
+    /// How long the GPU took to render a frame, if timer queries are supported. The GPU runs
+    /// behind, so this is for the most recent frame it finished rather than this one.
+    pub gpu_time: Option<Duration>,
+    /// How long the runtime took to submit the frame.
+    pub cpu_time: Duration,
+    /// How long the view took to build the frame on the game thread.
+    pub build_time: Duration,
+}
+
+impl RenderStats {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+/// Raised for every frame the runtime finished rendering.
+pub struct RenderStatsEvent {
+    pub stats: RenderStats,
+}
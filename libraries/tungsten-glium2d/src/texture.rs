@@ -0,0 +1,159 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::Sender;
+use image::{self, RgbaImage, ImageBuffer, Rgba};
+use tungsten::{AssetLoader, AssetSource};
+use frontend::{FrontendCommand, TextureId};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Filter {
+    Nearest,
+    Linear,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum WrapMode {
+    Clamp,
+    Repeat,
+    MirroredRepeat,
+}
+
+/// How a texture should be sampled when drawn larger or smaller than its actual size.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TextureOptions {
+    pub magnify: Filter,
+    pub minify: Filter,
+    /// Generates smaller versions of the texture to sample from when minifying, this prevents
+    /// shimmering when zoomed out.
+    pub mipmaps: bool,
+    pub wrap: WrapMode,
+}
+
+impl TextureOptions {
+    /// Keeps pixels sharp when zoomed in, but still smooths them out when zoomed out.
+    pub fn pixel_art() -> Self {
+        TextureOptions {
+            magnify: Filter::Nearest,
+            .. Default::default()
+        }
+    }
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        TextureOptions {
+            magnify: Filter::Linear,
+            minify: Filter::Linear,
+            mipmaps: true,
+            wrap: WrapMode::Clamp,
+        }
+    }
+}
+
+/// Where to get the data for a texture from.
+#[derive(Clone, Debug)]
+pub enum TextureSource {
+    /// An image file on disk.
+    Path(PathBuf),
+    /// An encoded image file in memory, for example from `include_bytes!`.
+    Encoded(Vec<u8>),
+    /// Raw RGBA pixel data, with the width and height of the image.
+    Rgba(Vec<u8>, [u32; 2]),
+}
+
+impl TextureSource {
+    pub fn decode(&self) -> Result<RgbaImage, String> {
+        match *self {
+            TextureSource::Path(ref path) => image::open(path)
+                .map(|i| i.to_rgba())
+                .map_err(|e| format!("Could not load {}: {}", path.display(), e)),
+            TextureSource::Encoded(ref data) => image::load_from_memory(data)
+                .map(|i| i.to_rgba())
+                .map_err(|e| format!("Could not decode texture: {}", e)),
+            TextureSource::Rgba(ref data, size) => ImageBuffer::from_raw(size[0], size[1], data.clone())
+                .ok_or_else(|| format!("RGBA data does not match size {}x{}", size[0], size[1])),
+        }
+    }
+}
+
+/// The texture used in place of textures that failed to load, a magenta and black checkerboard.
+pub fn missing_texture() -> RgbaImage {
+    ImageBuffer::from_fn(16, 16, |x, y| {
+        if (x / 4 + y / 4) % 2 == 0 {
+            Rgba([255, 0, 255, 255])
+        } else {
+            Rgba([0, 0, 0, 255])
+        }
+    })
+}
+
+/// A texture loaded through a `tungsten::AssetManager`. The texture is unloaded when the asset is
+/// dropped, so don't keep using its ID after the last handle to it is gone.
+#[derive(Debug)]
+pub struct Texture {
+    pub id: TextureId,
+    pub size: [u32; 2],
+    unload_send: Mutex<Sender<FrontendCommand>>,
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        // If the runtime has stopped there's nothing left to unload
+        if let Ok(send) = self.unload_send.lock() {
+            let _ = send.send(FrontendCommand::UnloadTexture(self.id));
+        }
+    }
+}
+
+/// Loads textures for a `tungsten::AssetManager`, get one from `Frontend2D::texture_loader`.
+pub struct TextureLoader {
+    command_send: Sender<FrontendCommand>,
+    texture_ids: Arc<Mutex<u32>>,
+    source: AssetSource,
+    options: TextureOptions,
+}
+
+impl TextureLoader {
+    pub fn new(
+        command_send: Sender<FrontendCommand>, texture_ids: Arc<Mutex<u32>>, source: AssetSource,
+        options: TextureOptions
+    ) -> Self {
+        TextureLoader {
+            command_send: command_send,
+            texture_ids: texture_ids,
+            source: source,
+            options: options,
+        }
+    }
+}
+
+impl AssetLoader for TextureLoader {
+    type Asset = Texture;
+
+    fn load(&mut self, path: &Path, data: Vec<u8>) -> Result<Texture, String> {
+        // Decode here rather than in the runtime, so big images don't hold up rendering
+        let image = try!(TextureSource::Encoded(data).decode()
+            .map_err(|e| format!("{}: {}", path.display(), e)));
+        let size = [image.width(), image.height()];
+
+        // Loose files are sent by path so the runtime watches them for hot reloading, it decodes
+        // them again but that only happens during development
+        let source = match self.source.loose_path(path) {
+            Some(loose) => TextureSource::Path(loose),
+            None => TextureSource::Rgba(image.into_raw(), size),
+        };
+
+        // The runtime expects IDs in order, so the counter is held until the texture is sent
+        let mut counter = self.texture_ids.lock().unwrap();
+        let id = TextureId::from_raw(*counter);
+        try!(self.command_send.send(FrontendCommand::LoadTexture(id, source, self.options))
+            .map_err(|_| "The frontend runtime has stopped".to_string()));
+        *counter += 1;
+
+        Ok(Texture {
+            id: id,
+            size: size,
+            unload_send: Mutex::new(self.command_send.clone()),
+        })
+    }
+}
@@ -0,0 +1,154 @@
+use frontend::TextureId;
+
+/// The amount of tiles along each side of a chunk the runtime caches geometry for.
+pub const CHUNK_SIZE: u32 = 16;
+
+/// A grid of tiles that all come from one tileset texture. Once created in the frontend the
+/// runtime keeps the geometry cached, so only changed chunks have to be rebuilt.
+#[derive(Clone, Debug)]
+pub struct TileMap {
+    tileset: TextureId,
+    tileset_columns: u32,
+    tile_size: [f32; 2],
+    size: [u32; 2],
+    tiles: Vec<Option<u32>>,
+}
+
+impl TileMap {
+    /// Creates a new empty tilemap. The tileset is a square texture divided into
+    /// `tileset_columns` by `tileset_columns` tiles, indexed from the top left, row by row.
+    pub fn new(tileset: TextureId, tileset_columns: u32, tile_size: [f32; 2], size: [u32; 2]) -> Self {
+        assert!(tileset_columns > 0);
+
+        TileMap {
+            tileset: tileset,
+            tileset_columns: tileset_columns,
+            tile_size: tile_size,
+            size: size,
+            tiles: vec![None; (size[0] * size[1]) as usize],
+        }
+    }
+
+    pub fn tileset(&self) -> TextureId {
+        self.tileset
+    }
+
+    pub fn tile_size(&self) -> [f32; 2] {
+        self.tile_size
+    }
+
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    pub fn get(&self, position: [u32; 2]) -> Option<u32> {
+        self.tiles[self.index(position)]
+    }
+
+    pub fn set(&mut self, position: [u32; 2], tile: Option<u32>) {
+        let index = self.index(position);
+        self.tiles[index] = tile;
+    }
+
+    /// The amount of chunks along each axis of this tilemap.
+    pub fn chunks(&self) -> [u32; 2] {
+        [
+            (self.size[0] + CHUNK_SIZE - 1) / CHUNK_SIZE,
+            (self.size[1] + CHUNK_SIZE - 1) / CHUNK_SIZE,
+        ]
+    }
+
+    /// Gets the texture coordinates of the lower left and upper right corners of a tile.
+    pub fn tile_uv(&self, tile: u32) -> ([f32; 2], [f32; 2]) {
+        let columns = self.tileset_columns as f32;
+        let column = (tile % self.tileset_columns) as f32;
+        let row = (tile / self.tileset_columns) as f32;
+
+        // Textures are stored flipped, so row 0 is at the top of the texture coordinates
+        (
+            [column / columns, 1.0 - (row + 1.0) / columns],
+            [(column + 1.0) / columns, 1.0 - row / columns],
+        )
+    }
+
+    /// Calls `func` with the tile position and tile of every non-empty tile in a chunk.
+    pub fn for_each_in_chunk<F: FnMut([u32; 2], u32)>(&self, chunk: [u32; 2], mut func: F) {
+        let start = [chunk[0] * CHUNK_SIZE, chunk[1] * CHUNK_SIZE];
+        let end = [
+            ::std::cmp::min(start[0] + CHUNK_SIZE, self.size[0]),
+            ::std::cmp::min(start[1] + CHUNK_SIZE, self.size[1]),
+        ];
+
+        for y in start[1]..end[1] {
+            for x in start[0]..end[0] {
+                if let Some(tile) = self.get([x, y]) {
+                    func([x, y], tile);
+                }
+            }
+        }
+    }
+
+    /// Finds the range of chunks, end exclusive, visible in an area around a center, in the
+    /// space the tilemap is positioned in.
+    pub fn visible_chunks(&self, map_position: [f32; 2], center: [f32; 2], area: [f32; 2])
+        -> ([u32; 2], [u32; 2]) {
+        let chunks = self.chunks();
+        let mut start = [0, 0];
+        let mut end = [0, 0];
+
+        for axis in 0..2 {
+            let chunk_size = self.tile_size[axis] * CHUNK_SIZE as f32;
+            let low = (center[axis] - area[axis] * 0.5 - map_position[axis]) / chunk_size;
+            let high = (center[axis] + area[axis] * 0.5 - map_position[axis]) / chunk_size;
+
+            start[axis] = clamp_chunk(low.floor(), chunks[axis]);
+            end[axis] = clamp_chunk(high.floor() + 1.0, chunks[axis]);
+        }
+
+        (start, end)
+    }
+
+    fn index(&self, position: [u32; 2]) -> usize {
+        assert!(position[0] < self.size[0] && position[1] < self.size[1]);
+        (position[1] * self.size[0] + position[0]) as usize
+    }
+}
+
+fn clamp_chunk(value: f32, chunks: u32) -> u32 {
+    if value < 0.0 {
+        0
+    } else if value > chunks as f32 {
+        chunks
+    } else {
+        value as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use frontend::TextureId;
+    use super::TileMap;
+
+    #[test]
+    fn visible_chunks_are_clamped_to_map() {
+        let map = TileMap::new(TextureId::from_raw(0), 1, [16.0, 16.0], [64, 64]);
+
+        // Each chunk is 256 units, looking at the middle of the second chunk
+        let (start, end) = map.visible_chunks([0.0, 0.0], [384.0, 384.0], [100.0, 100.0]);
+        assert_eq!(start, [1, 1]);
+        assert_eq!(end, [2, 2]);
+
+        // Looking at an area way larger than the map
+        let (start, end) = map.visible_chunks([0.0, 0.0], [0.0, 0.0], [10000.0, 10000.0]);
+        assert_eq!(start, [0, 0]);
+        assert_eq!(end, [4, 4]);
+    }
+
+    #[test]
+    fn tile_uv_starts_at_top_left() {
+        let map = TileMap::new(TextureId::from_raw(0), 2, [16.0, 16.0], [1, 1]);
+
+        assert_eq!(map.tile_uv(0), ([0.0, 0.5], [0.5, 1.0]));
+        assert_eq!(map.tile_uv(3), ([0.5, 0.0], [1.0, 0.5]));
+    }
+}
@@ -0,0 +1,889 @@
+use std::mem;
+use std::sync::{Arc, Mutex};
+use tungsten::{EventDispatcher, EventSource};
+use font;
+use frontend::{Rectangle, RenderTarget, GameCameraInfo, TextureId, VIEW_SIZE};
+use input::InputState;
+use {Key, MouseButton};
+
+const SLIDER_WIDTH: f32 = 160.0;
+const TEXT_FIELD_WIDTH: f32 = 200.0;
+
+/// The starting value of the FNV-1a hash widget IDs are made with.
+const ID_SEED: u64 = 0xcbf29ce484222325;
+
+/// Identifies a widget across frames. Panels are identified by their name, widgets by their panel
+/// and their own name, which for buttons and checkboxes is their text.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct WidgetId {
+    id: u64
+}
+
+impl WidgetId {
+    /// The ID of a panel that isn't inside any other panel.
+    pub fn root(name: &str) -> Self {
+        WidgetId {
+            id: hash_name(ID_SEED, name)
+        }
+    }
+
+    /// The ID of a widget or panel inside this panel.
+    pub fn child(&self, name: &str) -> Self {
+        WidgetId {
+            id: hash_name(hash_name(self.id, "/"), name)
+        }
+    }
+}
+
+fn hash_name(seed: u64, name: &str) -> u64 {
+    name.bytes().fold(seed, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Horizontal,
+    Vertical,
+}
+
+/// A texture that can be stretched to any size without distorting its borders. The corners are
+/// drawn as they are, the edges are stretched along their length and the center is stretched to
+/// fill the rest.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct NineSlice {
+    pub texture: TextureId,
+    /// The size of the borders in the texture, in texture coordinates.
+    pub texture_border: f32,
+    /// The size the borders are drawn at, in world units.
+    pub border: f32,
+}
+
+impl NineSlice {
+    pub fn new(texture: TextureId, texture_border: f32, border: f32) -> Self {
+        NineSlice {
+            texture: texture,
+            texture_border: texture_border,
+            border: border,
+        }
+    }
+
+    /// A nine-slice without borders, which stretches the whole texture.
+    pub fn plain(texture: TextureId) -> Self {
+        Self::new(texture, 0.0, 0.0)
+    }
+
+    /// Returns the rectangles that make up the nine-slice covering the area.
+    pub fn rectangles(&self, low: [f32; 2], high: [f32; 2], color: [f32; 4]) -> Vec<Rectangle> {
+        // Borders can't be bigger than half the area, or they'd overlap
+        let border = self.border.min((high[0] - low[0]) * 0.5).min((high[1] - low[1]) * 0.5);
+        let xs = [low[0], low[0] + border, high[0] - border, high[0]];
+        let ys = [low[1], low[1] + border, high[1] - border, high[1]];
+        let uvs = [0.0, self.texture_border, 1.0 - self.texture_border, 1.0];
+
+        let mut rectangles = Vec::new();
+        for y in 0..3 {
+            for x in 0..3 {
+                let size = [xs[x + 1] - xs[x], ys[y + 1] - ys[y]];
+                if size[0] <= 0.0 || size[1] <= 0.0 {
+                    continue;
+                }
+
+                let position = [xs[x] + size[0] * 0.5, ys[y] + size[1] * 0.5];
+                let mut rect = Rectangle::new(position, size, self.texture);
+                rect.region = [uvs[x], uvs[y], uvs[x + 1], uvs[y + 1]];
+                rect.color = color;
+                rectangles.push(rect);
+            }
+        }
+        rectangles
+    }
+}
+
+/// How widgets look. Colors tint the nine-slices, so a skin can use a single white texture and
+/// only colors.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Skin {
+    /// A plain white texture, used for text and other details.
+    pub white: TextureId,
+    pub panel: NineSlice,
+    pub button: NineSlice,
+    /// Used for text fields, checkboxes and slider tracks.
+    pub field: NineSlice,
+    pub text_height: f32,
+    /// The space between the edge of a widget and its contents.
+    pub padding: f32,
+    /// The space between widgets in a panel.
+    pub spacing: f32,
+    pub panel_color: [f32; 4],
+    pub normal_color: [f32; 4],
+    pub hovered_color: [f32; 4],
+    pub pressed_color: [f32; 4],
+    pub focused_color: [f32; 4],
+    pub text_color: [f32; 4],
+}
+
+impl Skin {
+    /// A skin made of only colored rectangles, for when there's no art yet.
+    pub fn plain(white: TextureId) -> Self {
+        Skin {
+            white: white,
+            panel: NineSlice::plain(white),
+            button: NineSlice::plain(white),
+            field: NineSlice::plain(white),
+            text_height: 15.0,
+            padding: 8.0,
+            spacing: 6.0,
+            panel_color: [0.1, 0.1, 0.15, 0.9],
+            normal_color: [0.25, 0.25, 0.35, 1.0],
+            hovered_color: [0.35, 0.35, 0.5, 1.0],
+            pressed_color: [0.15, 0.15, 0.25, 1.0],
+            focused_color: [0.3, 0.3, 0.6, 1.0],
+            text_color: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+}
+
+/// Something that happened to a widget, dispatched as an event by the `UiEvents` of its `Ui`. The
+/// same interactions are also returned by the widget functions as they're called.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WidgetEvent {
+    /// A button was clicked, or activated with the keyboard.
+    Clicked(WidgetId),
+    Toggled(WidgetId, bool),
+    ValueChanged(WidgetId, f32),
+    TextChanged(WidgetId, String),
+    /// Return was pressed in a text field.
+    Submitted(WidgetId, String),
+    /// Keyboard focus moved to another widget, or away from all of them.
+    FocusChanged(Option<WidgetId>),
+}
+
+/// Dispatches the `WidgetEvent`s of a `Ui`. Get one with `Ui::events` and add it to the framework
+/// with `Framework::add_event_source`.
+#[derive(Clone)]
+pub struct UiEvents {
+    queue: Arc<Mutex<Vec<WidgetEvent>>>,
+}
+
+impl UiEvents {
+    fn new() -> Self {
+        UiEvents {
+            queue: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    fn push(&self, events: Vec<WidgetEvent>) {
+        self.queue.lock().unwrap().extend(events);
+    }
+
+    /// Takes the events that haven't been dispatched yet, oldest first.
+    pub fn take(&self) -> Vec<WidgetEvent> {
+        mem::replace(&mut *self.queue.lock().unwrap(), Vec::new())
+    }
+}
+
+impl<M: 'static> EventSource<M> for UiEvents {
+    fn process_events(&mut self, dispatcher: &mut EventDispatcher<M>, model: &mut M) {
+        for event in self.take() {
+            dispatcher.dispatch(model, event);
+        }
+    }
+}
+
+/// The input the UI needs, gathered from every `handle_input` since the last frame.
+#[derive(Clone, Debug, Default)]
+struct UiInput {
+    mouse: [f32; 2],
+    down: bool,
+    pressed: bool,
+    released: bool,
+    tab: bool,
+    shift: bool,
+    enter: bool,
+    back: bool,
+    left: bool,
+    right: bool,
+    text: String,
+}
+
+/// A widget added during the frame, laid out once its panel ends.
+enum Element {
+    Panel(Panel),
+    Label(String),
+    Image(TextureId, [f32; 2]),
+    Button(WidgetId, String),
+    Checkbox(WidgetId, String, bool),
+    /// A slider with how far along its range the value is, from 0.0 to 1.0.
+    Slider(WidgetId, f32),
+    TextField(WidgetId, String),
+}
+
+struct Panel {
+    id: WidgetId,
+    direction: Direction,
+    /// Only set for panels at the root, the rest is positioned by their panel.
+    position: Option<[f32; 2]>,
+    children: Vec<Element>,
+}
+
+/// A small immediate mode UI of panels with widgets, drawn with rectangles into a camera.
+///
+/// Every frame the UI is built again between `begin` and `end`, by calling the widget functions
+/// inside of panels. Widgets return what happened to them, for example `button` returns true when
+/// it was clicked. The same interactions are dispatched as `WidgetEvent`s by the event source from
+/// `events`. Widgets that aren't called in a frame simply aren't there. Widgets are hit-tested
+/// against where they were in the previous frame, so they have to be shown for a frame before
+/// they can be clicked.
+///
+/// The UI is in world units as seen by a camera at `[0.0, 0.0]` with a zoom of 1.0 covering the
+/// whole window, so mouse positions line up. Pass it the input state every update with
+/// `handle_input`, and the characters of `TextInputEvent`s with `text_input`.
+pub struct Ui {
+    skin: Skin,
+    events: UiEvents,
+    window_size: [u32; 2],
+    input: UiInput,
+    /// Panels that have been started but not ended yet, innermost last.
+    open: Vec<Panel>,
+    /// Where interactive widgets ended up in the last frame, in the order they were drawn.
+    bounds: Vec<(WidgetId, [f32; 2], [f32; 2])>,
+    next_bounds: Vec<(WidgetId, [f32; 2], [f32; 2])>,
+    rectangles: Vec<Rectangle>,
+    next_rectangles: Vec<Rectangle>,
+    hovered: Option<WidgetId>,
+    pressed: Option<WidgetId>,
+    focused: Option<WidgetId>,
+    /// The widget the mouse was pressed and released on since the last frame.
+    clicked: Option<WidgetId>,
+}
+
+impl Ui {
+    pub fn new(skin: Skin) -> Self {
+        Ui {
+            skin: skin,
+            events: UiEvents::new(),
+            window_size: [VIEW_SIZE[0] as u32, VIEW_SIZE[1] as u32],
+            input: UiInput::default(),
+            open: Vec::new(),
+            bounds: Vec::new(),
+            next_bounds: Vec::new(),
+            rectangles: Vec::new(),
+            next_rectangles: Vec::new(),
+            hovered: None,
+            pressed: None,
+            focused: None,
+            clicked: None,
+        }
+    }
+
+    /// Returns the event source that dispatches this UI's `WidgetEvent`s.
+    pub fn events(&self) -> UiEvents {
+        self.events.clone()
+    }
+
+    /// Sets the size of the window in pixels, to turn mouse positions into UI positions. Call this
+    /// from a `WindowResizedEvent` handler.
+    pub fn set_window_size(&mut self, size: [u32; 2]) {
+        self.window_size = size;
+    }
+
+    pub fn skin(&self) -> &Skin {
+        &self.skin
+    }
+
+    pub fn set_skin(&mut self, skin: Skin) {
+        self.skin = skin;
+    }
+
+    pub fn focused(&self) -> Option<WidgetId> {
+        self.focused
+    }
+
+    /// Returns the area a widget covered in the last frame as its low and high corners, if it
+    /// was there and can be interacted with.
+    pub fn bounds(&self, widget: WidgetId) -> Option<([f32; 2], [f32; 2])> {
+        self.bounds.iter()
+            .find(|&&(id, _, _)| id == widget)
+            .map(|&(_, low, high)| (low, high))
+    }
+
+    /// Returns the topmost widget that could be interacted with at the position in the last frame.
+    pub fn widget_at(&self, position: [f32; 2]) -> Option<WidgetId> {
+        // Later widgets are drawn on top, so check them first
+        self.bounds.iter().rev()
+            .find(|&&(_, low, high)| {
+                position[0] >= low[0] && position[0] < high[0] &&
+                    position[1] >= low[1] && position[1] < high[1]
+            })
+            .map(|&(id, _, _)| id)
+    }
+
+    /// Gathers the input for the next frame.
+    pub fn handle_input(&mut self, state: &InputState) {
+        let input = &mut self.input;
+        input.mouse = mouse_position(self.window_size, state.mouse_position());
+        input.down = state.is_button_down(MouseButton::Left);
+        input.pressed |= state.was_button_pressed(MouseButton::Left);
+        input.released |= state.was_button_released(MouseButton::Left);
+        input.tab |= state.was_key_pressed(Key::Tab);
+        input.shift = state.modifiers().shift;
+        input.enter |= state.was_key_pressed(Key::Return);
+        input.back |= state.was_key_pressed(Key::Back);
+        input.left |= state.was_key_pressed(Key::Left);
+        input.right |= state.was_key_pressed(Key::Right);
+    }
+
+    /// Types a character into the focused text field in the next frame, if there is one.
+    pub fn text_input(&mut self, character: char) {
+        if !character.is_control() {
+            self.input.text.push(character);
+        }
+    }
+
+    /// Starts building a new frame of the UI, using the input gathered since the last one.
+    pub fn begin(&mut self) {
+        let mut events = Vec::new();
+
+        // Anything left from a frame that wasn't ended is thrown away
+        self.open.clear();
+        self.next_bounds.clear();
+        self.next_rectangles.clear();
+
+        let mouse = self.input.mouse;
+        self.hovered = self.widget_at(mouse);
+        self.clicked = None;
+
+        // Clicking anywhere moves focus, clicking on nothing clears it
+        if self.input.pressed {
+            self.pressed = self.hovered;
+            let hovered = self.hovered;
+            self.set_focus(hovered, &mut events);
+        }
+
+        // A click only counts if it's released on the same widget
+        if self.input.released && self.pressed == self.hovered {
+            self.clicked = self.pressed;
+        }
+        if !self.input.down {
+            self.pressed = None;
+        }
+
+        if self.input.tab {
+            let forward = !self.input.shift;
+            self.cycle_focus(forward, &mut events);
+        }
+
+        self.events.push(events);
+    }
+
+    /// Finishes the frame, after which it's drawn by `render`. Panels that are still open are
+    /// ended first.
+    pub fn end(&mut self) {
+        while !self.open.is_empty() {
+            self.end_panel();
+        }
+
+        self.rectangles = mem::replace(&mut self.next_rectangles, Vec::new());
+        self.bounds = mem::replace(&mut self.next_bounds, Vec::new());
+
+        // Widgets that are gone can't keep focus, or they'd still take keyboard input
+        if let Some(focused) = self.focused {
+            if self.bounds(focused).is_none() {
+                let mut events = Vec::new();
+                self.set_focus(None, &mut events);
+                self.events.push(events);
+            }
+        }
+
+        // The input has been used, only keep what's still going on
+        let input = &mut self.input;
+        *input = UiInput {
+            mouse: input.mouse,
+            down: input.down,
+            shift: input.shift,
+            .. Default::default()
+        };
+    }
+
+    /// Starts a panel that isn't inside any other panel, centered on the position. Widgets added
+    /// until the matching `end_panel` are placed in it.
+    pub fn begin_panel(&mut self, name: &str, position: [f32; 2], direction: Direction) {
+        self.open.push(Panel {
+            id: WidgetId::root(name),
+            direction: direction,
+            position: Some(position),
+            children: Vec::new(),
+        });
+    }
+
+    /// Starts a panel inside the current panel, to combine layout directions. Does nothing
+    /// outside of a panel.
+    pub fn begin_subpanel(&mut self, name: &str, direction: Direction) {
+        if let Some(id) = self.child_id(name) {
+            self.open.push(Panel {
+                id: id,
+                direction: direction,
+                position: None,
+                children: Vec::new(),
+            });
+        }
+    }
+
+    /// Ends the current panel. Does nothing if there's no panel to end.
+    pub fn end_panel(&mut self) {
+        let panel = match self.open.pop() {
+            Some(panel) => panel,
+            None => return,
+        };
+
+        match panel.position {
+            Some(position) => {
+                let element = Element::Panel(panel);
+                let size = self.measure(&element);
+                let low = [position[0] - size[0] * 0.5, position[1] - size[1] * 0.5];
+                self.arrange(&element, low, size);
+            },
+            None => self.add(Element::Panel(panel)),
+        }
+    }
+
+    pub fn label(&mut self, text: &str) {
+        self.add(Element::Label(text.to_string()));
+    }
+
+    pub fn image(&mut self, texture: TextureId, size: [f32; 2]) {
+        self.add(Element::Image(texture, size));
+    }
+
+    /// Adds a button, returns true if it was clicked or activated with the keyboard.
+    pub fn button(&mut self, text: &str) -> bool {
+        let id = match self.child_id(text) {
+            Some(id) => id,
+            None => return false,
+        };
+
+        let clicked = self.clicked == Some(id) || (self.focused == Some(id) && self.input.enter);
+        if clicked {
+            self.events.push(vec!(WidgetEvent::Clicked(id)));
+        }
+
+        self.add(Element::Button(id, text.to_string()));
+        clicked
+    }
+
+    /// Adds a checkbox showing `checked`, returns true if it was toggled.
+    pub fn checkbox(&mut self, text: &str, checked: &mut bool) -> bool {
+        let id = match self.child_id(text) {
+            Some(id) => id,
+            None => return false,
+        };
+
+        let toggled = self.clicked == Some(id) || (self.focused == Some(id) && self.input.enter);
+        if toggled {
+            *checked = !*checked;
+            self.events.push(vec!(WidgetEvent::Toggled(id, *checked)));
+        }
+
+        self.add(Element::Checkbox(id, text.to_string(), *checked));
+        toggled
+    }
+
+    /// Adds a slider for a value between min and max, returns true if it was changed. The slider
+    /// follows the mouse while held, and moves in steps of a twentieth of its range with the arrow
+    /// keys when focused. Values outside of the range are moved into it without counting as a
+    /// change.
+    pub fn slider(&mut self, name: &str, value: &mut f32, min: f32, max: f32) -> bool {
+        let id = match self.child_id(name) {
+            Some(id) => id,
+            None => return false,
+        };
+
+        let max = max.max(min);
+        *value = value.max(min).min(max);
+        let mut new_value = *value;
+
+        let held = (self.pressed == Some(id) && self.input.down) || self.clicked == Some(id);
+        if let (true, Some((low, high))) = (held, self.bounds(id)) {
+            let left = low[0] + self.skin.padding;
+            let right = high[0] - self.skin.padding;
+            if right > left {
+                let factor = ((self.input.mouse[0] - left) / (right - left)).max(0.0).min(1.0);
+                new_value = min + (max - min) * factor;
+            }
+        }
+
+        if self.focused == Some(id) {
+            let step = (max - min) / 20.0;
+            if self.input.left {
+                new_value -= step;
+            }
+            if self.input.right {
+                new_value += step;
+            }
+            new_value = new_value.max(min).min(max);
+        }
+
+        let changed = new_value != *value;
+        if changed {
+            *value = new_value;
+            self.events.push(vec!(WidgetEvent::ValueChanged(id, new_value)));
+        }
+
+        let factor = if max > min { (new_value - min) / (max - min) } else { 0.0 };
+        self.add(Element::Slider(id, factor));
+        changed
+    }
+
+    /// Adds a text field editing the text, returns true if return was pressed in it.
+    pub fn text_field(&mut self, name: &str, text: &mut String) -> bool {
+        let id = match self.child_id(name) {
+            Some(id) => id,
+            None => return false,
+        };
+
+        let mut submitted = false;
+        if self.focused == Some(id) {
+            let mut changed = !self.input.text.is_empty();
+            text.push_str(&self.input.text);
+            if self.input.back && text.pop().is_some() {
+                changed = true;
+            }
+
+            if changed {
+                self.events.push(vec!(WidgetEvent::TextChanged(id, text.clone())));
+            }
+            if self.input.enter {
+                self.events.push(vec!(WidgetEvent::Submitted(id, text.clone())));
+                submitted = true;
+            }
+        }
+
+        self.add(Element::TextField(id, text.clone()));
+        submitted
+    }
+
+    /// The ID of a widget in the current panel, if there is one.
+    fn child_id(&self, name: &str) -> Option<WidgetId> {
+        self.open.last().map(|panel| panel.id.child(name))
+    }
+
+    /// Adds an element to the current panel. Elements outside of any panel are left out.
+    fn add(&mut self, element: Element) {
+        if let Some(panel) = self.open.last_mut() {
+            panel.children.push(element);
+        }
+    }
+
+    fn set_focus(&mut self, focus: Option<WidgetId>, events: &mut Vec<WidgetEvent>) {
+        if focus != self.focused {
+            self.focused = focus;
+            events.push(WidgetEvent::FocusChanged(focus));
+        }
+    }
+
+    fn cycle_focus(&mut self, forward: bool, events: &mut Vec<WidgetEvent>) {
+        let focusable: Vec<_> = self.bounds.iter().map(|&(id, _, _)| id).collect();
+        if focusable.is_empty() {
+            return;
+        }
+
+        let current = self.focused.and_then(|f| focusable.iter().position(|&id| id == f));
+        let next = match (current, forward) {
+            (Some(i), true) => (i + 1) % focusable.len(),
+            (Some(i), false) => (i + focusable.len() - 1) % focusable.len(),
+            (None, true) => 0,
+            (None, false) => focusable.len() - 1,
+        };
+        self.set_focus(Some(focusable[next]), events);
+    }
+
+    /// Returns the size an element needs for its contents.
+    fn measure(&self, element: &Element) -> [f32; 2] {
+        let skin = &self.skin;
+        let text_size = |text: &str| [font::text_width(text, skin.text_height), skin.text_height];
+
+        match *element {
+            Element::Panel(ref panel) => {
+                let sizes: Vec<_> = panel.children.iter().map(|c| self.measure(c)).collect();
+                let (along, across) = if panel.direction == Direction::Horizontal { (0, 1) } else { (1, 0) };
+
+                let spacing = skin.spacing * (sizes.len().max(1) - 1) as f32;
+                let mut size = [0.0, 0.0];
+                size[along] = sizes.iter().fold(spacing, |total, s| total + s[along]);
+                size[across] = sizes.iter().fold(0.0, |max: f32, s| max.max(s[across]));
+                [size[0] + skin.padding * 2.0, size[1] + skin.padding * 2.0]
+            },
+            Element::Label(ref text) => text_size(text),
+            Element::Image(_, size) => size,
+            Element::Button(_, ref text) => {
+                let text = text_size(text);
+                [text[0] + skin.padding * 2.0, text[1] + skin.padding * 2.0]
+            },
+            Element::Checkbox(_, ref text, _) => {
+                let check = skin.text_height + skin.padding;
+                [check + skin.spacing + text_size(text)[0], check]
+            },
+            Element::Slider(_, _) => [SLIDER_WIDTH, skin.text_height + skin.padding * 2.0],
+            Element::TextField(_, _) => [TEXT_FIELD_WIDTH, skin.text_height + skin.padding * 2.0],
+        }
+    }
+
+    /// Places and draws an element, and everything in it if it's a panel. Widgets in a panel are
+    /// stretched across the panel, so buttons in a menu all have the same width.
+    fn arrange(&mut self, element: &Element, low: [f32; 2], size: [f32; 2]) {
+        let high = [low[0] + size[0], low[1] + size[1]];
+        self.draw(element, low, high);
+
+        let panel = match *element {
+            Element::Panel(ref panel) => panel,
+            _ => return,
+        };
+
+        // Lay out from the top left, inside the padding
+        let padding = self.skin.padding;
+        let inner = [size[0] - padding * 2.0, size[1] - padding * 2.0];
+        let mut cursor = [low[0] + padding, low[1] + size[1] - padding];
+        for child in &panel.children {
+            let measured = self.measure(child);
+            if panel.direction == Direction::Horizontal {
+                self.arrange(child, [cursor[0], cursor[1] - inner[1]], [measured[0], inner[1]]);
+                cursor[0] += measured[0] + self.skin.spacing;
+            } else {
+                self.arrange(child, [cursor[0], cursor[1] - measured[1]], [inner[0], measured[1]]);
+                cursor[1] -= measured[1] + self.skin.spacing;
+            }
+        }
+    }
+
+    fn draw(&mut self, element: &Element, low: [f32; 2], high: [f32; 2]) {
+        let mut rectangles = Vec::new();
+        let skin = self.skin.clone();
+
+        let interactive = match *element {
+            Element::Panel(_) => {
+                rectangles.extend(skin.panel.rectangles(low, high, skin.panel_color));
+                None
+            },
+            Element::Label(ref text) => {
+                self.centered_text(&mut rectangles, low, high, text);
+                None
+            },
+            Element::Image(texture, size) => {
+                let center = [(low[0] + high[0]) * 0.5, (low[1] + high[1]) * 0.5];
+                rectangles.push(Rectangle::new(center, size, texture));
+                None
+            },
+            Element::Button(id, ref text) => {
+                rectangles.extend(skin.button.rectangles(low, high, self.state_color(id)));
+                self.centered_text(&mut rectangles, low, high, text);
+                Some(id)
+            },
+            Element::Checkbox(id, ref text, checked) => {
+                let check = high[1] - low[1];
+                let check_high = [low[0] + check, high[1]];
+                rectangles.extend(skin.field.rectangles(low, check_high, self.state_color(id)));
+                if checked {
+                    let inset = skin.padding;
+                    let mark = [low[0] + inset, low[1] + inset];
+                    let mark_high = [check_high[0] - inset, check_high[1] - inset];
+                    rectangles.extend(self.plain(mark, mark_high));
+                }
+
+                let text_low = [low[0] + check + skin.spacing, low[1] + (check - skin.text_height) * 0.5];
+                self.draw_text(&mut rectangles, text_low, text);
+                Some(id)
+            },
+            Element::Slider(id, factor) => {
+                rectangles.extend(skin.field.rectangles(low, high, skin.normal_color));
+
+                // The handle is centered on the value, which goes from one end of the padding to
+                // the other
+                let center = low[0] + skin.padding + (high[0] - low[0] - skin.padding * 2.0) * factor;
+                let half = skin.padding;
+                let color = self.state_color(id);
+                rectangles.extend(skin.button.rectangles([center - half, low[1]], [center + half, high[1]], color));
+                Some(id)
+            },
+            Element::TextField(id, ref text) => {
+                rectangles.extend(skin.field.rectangles(low, high, self.state_color(id)));
+                let text_low = [low[0] + skin.padding, low[1] + skin.padding];
+                self.draw_text(&mut rectangles, text_low, text);
+
+                // Show a caret after the text while typing
+                if self.focused == Some(id) {
+                    let caret = text_low[0] + font::text_width(text, skin.text_height) + 2.0;
+                    let caret_high = [caret + 2.0, text_low[1] + skin.text_height];
+                    rectangles.extend(self.plain([caret, text_low[1]], caret_high));
+                }
+                Some(id)
+            },
+        };
+
+        if let Some(id) = interactive {
+            self.next_bounds.push((id, low, high));
+        }
+        self.next_rectangles.extend(rectangles);
+    }
+
+    /// Draws the last finished frame into the camera, which should be set up as described on `Ui`.
+    pub fn render(&self, camera: &mut GameCameraInfo) {
+        let batch = camera.batch();
+        for rect in &self.rectangles {
+            batch.rectangle(rect.clone());
+        }
+    }
+
+    fn draw_text(&self, rectangles: &mut Vec<Rectangle>, low: [f32; 2], text: &str) {
+        let skin = &self.skin;
+        font::text_rectangles(rectangles, low, skin.text_height, text, skin.white, skin.text_color);
+    }
+
+    fn centered_text(&self, rectangles: &mut Vec<Rectangle>, low: [f32; 2], high: [f32; 2], text: &str) {
+        let width = font::text_width(text, self.skin.text_height);
+        let text_low = [
+            (low[0] + high[0] - width) * 0.5,
+            (low[1] + high[1] - self.skin.text_height) * 0.5,
+        ];
+        self.draw_text(rectangles, text_low, text);
+    }
+
+    /// Returns a plain rectangle in the text color, for details like check marks.
+    fn plain(&self, low: [f32; 2], high: [f32; 2]) -> Vec<Rectangle> {
+        NineSlice::plain(self.skin.white).rectangles(low, high, self.skin.text_color)
+    }
+
+    fn state_color(&self, widget: WidgetId) -> [f32; 4] {
+        if self.pressed == Some(widget) && self.hovered == Some(widget) {
+            self.skin.pressed_color
+        } else if self.hovered == Some(widget) {
+            self.skin.hovered_color
+        } else if self.focused == Some(widget) {
+            self.skin.focused_color
+        } else {
+            self.skin.normal_color
+        }
+    }
+}
+
+fn mouse_position(window_size: [u32; 2], pixels: [i32; 2]) -> [f32; 2] {
+    // Pixels start at the top left, the UI is centered with the y axis going up
+    [
+        pixels[0] as f32 / window_size[0] as f32 * VIEW_SIZE[0] - VIEW_SIZE[0] * 0.5,
+        VIEW_SIZE[1] * 0.5 - pixels[1] as f32 / window_size[1] as f32 * VIEW_SIZE[1],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use frontend::TextureId;
+    use input::InputState;
+    use {Key, KeyState, MouseButton};
+    use super::{Ui, Skin, Direction, WidgetId, WidgetEvent};
+
+    /// The state of the menu used by the tests.
+    struct Menu {
+        sound: bool,
+        name: String,
+        started: bool,
+        submitted: bool,
+    }
+
+    fn frame(ui: &mut Ui, menu: &mut Menu) {
+        ui.begin();
+        ui.begin_panel("menu", [0.0, 0.0], Direction::Vertical);
+        menu.started = ui.button("Start");
+        ui.checkbox("Sound", &mut menu.sound);
+        menu.submitted = ui.text_field("name", &mut menu.name);
+        ui.end_panel();
+        ui.end();
+    }
+
+    fn click(ui: &mut Ui, position: [f32; 2]) {
+        // The UI is centered in a 1280x720 window
+        let mut state = InputState::new();
+        state.mouse_moved([(position[0] + 640.0) as i32, (360.0 - position[1]) as i32]);
+        state.button_input(MouseButton::Left, KeyState::Pressed);
+        state.button_input(MouseButton::Left, KeyState::Released);
+        ui.handle_input(&state);
+    }
+
+    fn center(bounds: Option<([f32; 2], [f32; 2])>) -> [f32; 2] {
+        let (low, high) = bounds.unwrap();
+        [(low[0] + high[0]) * 0.5, (low[1] + high[1]) * 0.5]
+    }
+
+    #[test]
+    fn clicks_and_types_into_widgets() {
+        let mut ui = Ui::new(Skin::plain(TextureId::from_raw(0)));
+        let mut menu = Menu { sound: true, name: String::new(), started: false, submitted: false };
+        let panel = WidgetId::root("menu");
+        let (start, sound, name) = (panel.child("Start"), panel.child("Sound"), panel.child("name"));
+        frame(&mut ui, &mut menu);
+
+        // Widgets are stacked from the top, stretched to the same width
+        let (start_low, start_high) = ui.bounds(start).unwrap();
+        let (sound_low, sound_high) = ui.bounds(sound).unwrap();
+        assert!(start_low[1] > sound_high[1]);
+        assert_eq!(start_high[0] - start_low[0], sound_high[0] - sound_low[0]);
+
+        let position = center(ui.bounds(start));
+        click(&mut ui, position);
+        frame(&mut ui, &mut menu);
+        assert!(menu.started);
+        assert_eq!(ui.events().take(), vec!(
+            WidgetEvent::FocusChanged(Some(start)), WidgetEvent::Clicked(start),
+        ));
+
+        let position = center(ui.bounds(sound));
+        click(&mut ui, position);
+        frame(&mut ui, &mut menu);
+        assert!(!menu.started);
+        assert!(!menu.sound);
+        assert_eq!(ui.events().take()[1], WidgetEvent::Toggled(sound, false));
+
+        // Typing only goes into a focused text field
+        ui.text_input('a');
+        frame(&mut ui, &mut menu);
+        assert!(menu.name.is_empty());
+        let position = center(ui.bounds(name));
+        click(&mut ui, position);
+        ui.text_input('h');
+        ui.text_input('i');
+        frame(&mut ui, &mut menu);
+        assert_eq!(menu.name, "hi");
+        assert_eq!(ui.events().take()[1], WidgetEvent::TextChanged(name, "hi".into()));
+
+        let mut state = InputState::new();
+        state.key_input(Key::Return, KeyState::Pressed);
+        ui.handle_input(&state);
+        frame(&mut ui, &mut menu);
+        assert!(menu.submitted);
+        assert_eq!(ui.events().take(), vec!(WidgetEvent::Submitted(name, "hi".into())));
+    }
+
+    #[test]
+    fn ignores_misuse_and_drops_focus_of_missing_widgets() {
+        let mut ui = Ui::new(Skin::plain(TextureId::from_raw(0)));
+        let mut value = 5.0;
+
+        // Widgets outside of panels and unbalanced panel ends do nothing
+        ui.begin();
+        assert!(!ui.button("Lost"));
+        ui.end_panel();
+        ui.begin_panel("menu", [0.0, 0.0], Direction::Horizontal);
+        ui.slider("volume", &mut value, 10.0, 0.0);
+        ui.end();
+        assert_eq!(value, 10.0);
+
+        // Focus the slider, then stop showing it
+        let mut state = InputState::new();
+        state.key_input(Key::Tab, KeyState::Pressed);
+        ui.handle_input(&state);
+        ui.begin();
+        ui.end();
+        assert_eq!(ui.events().take(), vec!(
+            WidgetEvent::FocusChanged(Some(WidgetId::root("menu").child("volume"))),
+            WidgetEvent::FocusChanged(None),
+        ));
+        assert_eq!(ui.focused(), None);
+    }
+}
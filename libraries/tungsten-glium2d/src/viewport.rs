@@ -0,0 +1,212 @@
+use frontend::VIEW_SIZE;
+
+/// The part of the screen a camera draws to.
+#[derive(Copy, Clone, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub enum Viewport {
+    /// In fractions of the screen from the bottom left, so it keeps covering the same part of the
+    /// screen when the window is resized.
+    Normalized { position: [f32; 2], size: [f32; 2] },
+    /// In pixels from the bottom left.
+    Pixels { position: [u32; 2], size: [u32; 2] },
+}
+
+impl Viewport {
+    /// The whole screen.
+    pub fn full() -> Self {
+        Viewport::Normalized {
+            position: [0.0, 0.0],
+            size: [1.0, 1.0],
+        }
+    }
+
+    /// Finds the pixels this viewport covers on a target of the given size, limited to the target.
+    pub fn to_pixels(&self, target: [u32; 2]) -> PixelRect {
+        let (low, high) = match *self {
+            Viewport::Normalized { position, size } => (
+                [
+                    (position[0] * target[0] as f32).round() as i64,
+                    (position[1] * target[1] as f32).round() as i64,
+                ],
+                [
+                    ((position[0] + size[0]) * target[0] as f32).round() as i64,
+                    ((position[1] + size[1]) * target[1] as f32).round() as i64,
+                ]
+            ),
+            Viewport::Pixels { position, size } => (
+                [position[0] as i64, position[1] as i64],
+                [position[0] as i64 + size[0] as i64, position[1] as i64 + size[1] as i64]
+            ),
+        };
+
+        let clamp = |value: i64, max: u32| value.max(0).min(max as i64) as u32;
+        let left = clamp(low[0], target[0]);
+        let bottom = clamp(low[1], target[1]);
+        PixelRect {
+            left: left,
+            bottom: bottom,
+            width: clamp(high[0], target[0]).saturating_sub(left),
+            height: clamp(high[1], target[1]).saturating_sub(bottom),
+        }
+    }
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Viewport::full()
+    }
+}
+
+/// A rectangle of pixels on a render target, from the bottom left.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PixelRect {
+    pub left: u32,
+    pub bottom: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl PixelRect {
+    /// Returns the area covered by both rectangles, which is empty if they don't overlap.
+    pub fn intersect(&self, other: &PixelRect) -> PixelRect {
+        let left = self.left.max(other.left);
+        let bottom = self.bottom.max(other.bottom);
+        let right = (self.left + self.width).min(other.left + other.width);
+        let top = (self.bottom + self.height).min(other.bottom + other.height);
+
+        PixelRect {
+            left: left,
+            bottom: bottom,
+            width: right.saturating_sub(left),
+            height: top.saturating_sub(bottom),
+        }
+    }
+}
+
+/// Keeps track of the clip rectangles pushed in a camera, nested clips only draw where all of
+/// them overlap.
+pub struct ClipStack {
+    stack: Vec<PixelRect>,
+}
+
+impl ClipStack {
+    /// Creates a stack that clips to the base rectangle when nothing is pushed.
+    pub fn new(base: PixelRect) -> Self {
+        ClipStack {
+            stack: vec!(base),
+        }
+    }
+
+    pub fn push(&mut self, rect: PixelRect) {
+        let clipped = self.current().intersect(&rect);
+        self.stack.push(clipped);
+    }
+
+    pub fn pop(&mut self) {
+        // The base can't be popped, cameras make sure pops match pushes
+        if self.stack.len() > 1 {
+            self.stack.pop();
+        }
+    }
+
+    pub fn current(&self) -> PixelRect {
+        *self.stack.last().unwrap()
+    }
+}
+
+/// Where a camera ends up on the render target, and how much of the world it shows there.
+#[derive(Copy, Clone, Debug)]
+pub struct CameraView {
+    pub viewport: PixelRect,
+    /// The part of the viewport currently being drawn to.
+    pub clip: PixelRect,
+    /// The size in world units shown at a zoom of 1.0.
+    pub size: [f32; 2],
+}
+
+impl CameraView {
+    /// Cameras keep the same amount of world units per pixel as a camera covering the whole
+    /// target, so a camera in half the screen shows half as much.
+    pub fn new(viewport: &Viewport, target: [u32; 2]) -> Self {
+        let pixels = viewport.to_pixels(target);
+
+        CameraView {
+            viewport: pixels,
+            clip: pixels,
+            size: [
+                VIEW_SIZE[0] * pixels.width as f32 / target[0] as f32,
+                VIEW_SIZE[1] * pixels.height as f32 / target[1] as f32,
+            ],
+        }
+    }
+
+    /// The size in world units shown at the zoom.
+    pub fn area(&self, zoom: f32) -> [f32; 2] {
+        [self.size[0] / zoom, self.size[1] / zoom]
+    }
+
+    /// Finds the pixels a rectangle in world units covers, seen from a camera at the position and
+    /// zoom. The result is limited to the viewport.
+    pub fn to_pixels(&self, camera: [f32; 2], zoom: f32, position: [f32; 2], size: [f32; 2]) -> PixelRect {
+        let area = self.area(zoom);
+        let viewport = self.viewport;
+        let to_pixel = |world: f32, axis: usize, start: u32, length: u32| {
+            let fraction = (world - camera[axis]) / area[axis] + 0.5;
+            let pixel = (start as f32 + fraction * length as f32).round();
+            pixel.max(start as f32).min((start + length) as f32) as u32
+        };
+
+        let left = to_pixel(position[0] - size[0] * 0.5, 0, viewport.left, viewport.width);
+        let right = to_pixel(position[0] + size[0] * 0.5, 0, viewport.left, viewport.width);
+        let bottom = to_pixel(position[1] - size[1] * 0.5, 1, viewport.bottom, viewport.height);
+        let top = to_pixel(position[1] + size[1] * 0.5, 1, viewport.bottom, viewport.height);
+
+        PixelRect {
+            left: left,
+            bottom: bottom,
+            width: right.saturating_sub(left),
+            height: top.saturating_sub(bottom),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use frontend::VIEW_SIZE;
+    use super::{Viewport, PixelRect, CameraView, ClipStack};
+
+    #[test]
+    fn split_screen_halves_cover_the_target() {
+        let left = Viewport::Normalized { position: [0.0, 0.0], size: [0.5, 1.0] };
+        let right = Viewport::Normalized { position: [0.5, 0.0], size: [0.5, 1.0] };
+
+        assert_eq!(left.to_pixels([1281, 720]), PixelRect { left: 0, bottom: 0, width: 641, height: 720 });
+        assert_eq!(right.to_pixels([1281, 720]), PixelRect { left: 641, bottom: 0, width: 640, height: 720 });
+
+        let view = CameraView::new(&left, [1280, 720]);
+        assert_eq!(view.size, [VIEW_SIZE[0] * 0.5, VIEW_SIZE[1]]);
+    }
+
+    #[test]
+    fn pixel_viewports_are_limited_to_the_target() {
+        let viewport = Viewport::Pixels { position: [1200, 700], size: [200, 200] };
+
+        assert_eq!(viewport.to_pixels([1280, 720]), PixelRect { left: 1200, bottom: 700, width: 80, height: 20 });
+    }
+
+    #[test]
+    fn nested_clips_intersect() {
+        let view = CameraView::new(&Viewport::full(), [1280, 720]);
+        let mut clips = ClipStack::new(view.viewport);
+
+        clips.push(view.to_pixels([0.0, 0.0], 1.0, [-100.0, 0.0], [400.0, 200.0]));
+        assert_eq!(clips.current(), PixelRect { left: 340, bottom: 260, width: 400, height: 200 });
+
+        clips.push(view.to_pixels([0.0, 0.0], 1.0, [100.0, 100.0], [400.0, 200.0]));
+        assert_eq!(clips.current(), PixelRect { left: 540, bottom: 360, width: 200, height: 100 });
+
+        clips.pop();
+        clips.pop();
+        clips.pop();
+        assert_eq!(clips.current(), view.viewport);
+    }
+}
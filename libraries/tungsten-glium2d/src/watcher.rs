@@ -0,0 +1,69 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+struct WatchedFile<K> {
+    key: K,
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+/// Watches files for changes by polling their modification times.
+pub struct FileWatcher<K> {
+    files: Vec<WatchedFile<K>>,
+    interval: Duration,
+    last_poll: Instant,
+}
+
+impl<K: Clone + PartialEq> FileWatcher<K> {
+    /// Creates a new watcher that checks files at most once every interval.
+    pub fn new(interval: Duration) -> Self {
+        FileWatcher {
+            files: Vec::new(),
+            interval: interval,
+            last_poll: Instant::now(),
+        }
+    }
+
+    pub fn watch<P: Into<PathBuf>>(&mut self, key: K, path: P) {
+        let path = path.into();
+        let modified = modified_time(&path);
+
+        self.files.push(WatchedFile {
+            key: key,
+            path: path,
+            modified: modified,
+        });
+    }
+
+    /// Stops watching all files with the key.
+    pub fn unwatch(&mut self, key: &K) {
+        self.files.retain(|f| f.key != *key);
+    }
+
+    /// Returns the keys and paths of all files that changed since the last poll. Does nothing if
+    /// the interval hasn't passed yet.
+    pub fn poll(&mut self) -> Vec<(K, PathBuf)> {
+        let mut changed = Vec::new();
+
+        if self.last_poll.elapsed() < self.interval {
+            return changed;
+        }
+        self.last_poll = Instant::now();
+
+        for file in &mut self.files {
+            // If we can't read the time, the file may be in the middle of being written
+            let modified = modified_time(&file.path);
+            if modified.is_some() && modified != file.modified {
+                file.modified = modified;
+                changed.push((file.key.clone(), file.path.clone()));
+            }
+        }
+
+        changed
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
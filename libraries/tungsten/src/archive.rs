@@ -0,0 +1,494 @@
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write, Seek, SeekFrom, BufReader};
+use std::path::{Path, PathBuf, Component};
+use std::sync::{Arc, Mutex};
+use flate2::Compression as FlateCompression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+
+const MAGIC: &'static [u8; 8] = b"TNGPACK1";
+const HEADER_SIZE: u64 = 16;
+/// Deflate can't make data smaller than this many times its size, so entries claiming more are
+/// damaged.
+const MAX_DEFLATE_RATIO: u64 = 1032;
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    Io(io::Error),
+    /// The file isn't an archive, or is damaged.
+    Format(String),
+    /// The data of an entry doesn't match its checksum.
+    Checksum(String),
+}
+
+impl From<io::Error> for ArchiveError {
+    fn from(error: io::Error) -> Self {
+        ArchiveError::Io(error)
+    }
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ArchiveError::Io(ref e) => write!(f, "{}", e),
+            ArchiveError::Format(ref e) => write!(f, "Invalid archive: {}", e),
+            ArchiveError::Checksum(ref path) => write!(f, "Checksum mismatch in {}", path),
+        }
+    }
+}
+
+impl Error for ArchiveError {
+    fn description(&self) -> &str {
+        match *self {
+            ArchiveError::Io(ref e) => e.description(),
+            ArchiveError::Format(_) => "invalid archive",
+            ArchiveError::Checksum(_) => "checksum mismatch",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Compression {
+    Stored,
+    Deflate,
+}
+
+impl Compression {
+    fn from_raw(raw: u8) -> Result<Self, ArchiveError> {
+        match raw {
+            0 => Ok(Compression::Stored),
+            1 => Ok(Compression::Deflate),
+            _ => Err(ArchiveError::Format(format!("Unknown compression {}", raw))),
+        }
+    }
+
+    fn raw(&self) -> u8 {
+        match *self {
+            Compression::Stored => 0,
+            Compression::Deflate => 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    pub compression: Compression,
+    offset: u64,
+    stored_size: u64,
+    pub size: u64,
+    pub crc32: u32,
+}
+
+/// A single file holding many assets, made with the packer. The layout is a header with the
+/// magic and the offset of the index, the data of every entry, and then the index:
+///
+/// ```text
+/// header: "TNGPACK1", index offset: u64
+/// index:  entry count: u32, then for each entry
+///         path length: u16, path: UTF-8 with / separators, compression: u8,
+///         offset: u64, stored size: u64, size: u64, crc32: u32
+/// ```
+///
+/// All numbers are little endian. Clones share the same open file.
+#[derive(Clone)]
+pub struct Archive {
+    file: Arc<Mutex<BufReader<File>>>,
+    entries: Arc<HashMap<String, ArchiveEntry>>,
+}
+
+impl Archive {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ArchiveError> {
+        let file = try!(File::open(path));
+        let file_size = try!(file.metadata()).len();
+        let mut file = BufReader::new(file);
+
+        let mut header = [0; HEADER_SIZE as usize];
+        try!(file.read_exact(&mut header));
+        if &header[0..8] != MAGIC {
+            return Err(ArchiveError::Format("Wrong magic, not an archive".into()));
+        }
+        let index_offset = read_u64(&header[8..16]);
+        if index_offset < HEADER_SIZE || index_offset > file_size {
+            return Err(ArchiveError::Format("Index is outside of the archive".into()));
+        }
+        try!(file.seek(SeekFrom::Start(index_offset)));
+
+        let mut count = [0; 4];
+        try!(file.read_exact(&mut count));
+        let mut entries = HashMap::new();
+        for _ in 0..read_u32(&count) {
+            let mut length = [0; 2];
+            try!(file.read_exact(&mut length));
+            let mut path = vec!(0; read_u16(&length) as usize);
+            try!(file.read_exact(&mut path));
+            let path = try!(String::from_utf8(path)
+                .map_err(|_| ArchiveError::Format("Path is not valid UTF-8".into())));
+
+            let mut entry = [0; 29];
+            try!(file.read_exact(&mut entry));
+            let entry = ArchiveEntry {
+                compression: try!(Compression::from_raw(entry[0])),
+                offset: read_u64(&entry[1..9]),
+                stored_size: read_u64(&entry[9..17]),
+                size: read_u64(&entry[17..25]),
+                crc32: read_u32(&entry[25..29]),
+            };
+
+            // The sizes are used to allocate memory when reading, so they can't be trusted
+            let end = entry.offset.checked_add(entry.stored_size);
+            if entry.offset < HEADER_SIZE || end.map_or(true, |end| end > file_size) {
+                return Err(ArchiveError::Format(format!("{} is outside of the archive", path)));
+            }
+            let max_size = match entry.compression {
+                Compression::Stored => entry.stored_size,
+                Compression::Deflate => entry.stored_size.saturating_mul(MAX_DEFLATE_RATIO),
+            };
+            if entry.size > max_size {
+                return Err(ArchiveError::Format(format!("{} has an impossible size", path)));
+            }
+
+            entries.insert(path, entry);
+        }
+
+        Ok(Archive {
+            file: Arc::new(Mutex::new(file)),
+            entries: Arc::new(entries),
+        })
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.entries.contains_key(&archive_path(path))
+    }
+
+    pub fn entry(&self, path: &Path) -> Option<&ArchiveEntry> {
+        self.entries.get(&archive_path(path))
+    }
+
+    pub fn paths(&self) -> Vec<&str> {
+        self.entries.keys().map(|p| &p[..]).collect()
+    }
+
+    /// Reads and decompresses an entry, or returns `None` if it's not in the archive.
+    pub fn read(&self, path: &Path) -> Result<Option<Vec<u8>>, ArchiveError> {
+        let name = archive_path(path);
+        let entry = if let Some(entry) = self.entries.get(&name) {
+            entry
+        } else {
+            return Ok(None);
+        };
+
+        let mut stored = vec!(0; entry.stored_size as usize);
+        {
+            let mut file = self.file.lock().unwrap();
+            try!(file.seek(SeekFrom::Start(entry.offset)));
+            try!(file.read_exact(&mut stored));
+        }
+
+        let data = match entry.compression {
+            Compression::Stored => stored,
+            Compression::Deflate => {
+                // Stop right after the size, damaged data could decompress to much more
+                let mut data = Vec::with_capacity(entry.size as usize);
+                try!(DeflateDecoder::new(&stored[..]).take(entry.size + 1).read_to_end(&mut data));
+                data
+            },
+        };
+
+        if data.len() as u64 != entry.size || crc32(&data) != entry.crc32 {
+            return Err(ArchiveError::Checksum(name));
+        }
+        Ok(Some(data))
+    }
+}
+
+/// Writes an archive, the index is written when finished.
+pub struct ArchiveWriter<W: Write + Seek> {
+    writer: W,
+    offset: u64,
+    entries: Vec<(String, ArchiveEntry)>,
+}
+
+impl<W: Write + Seek> ArchiveWriter<W> {
+    pub fn new(mut writer: W) -> Result<Self, ArchiveError> {
+        // The index offset is filled in when finished
+        try!(writer.write_all(MAGIC));
+        try!(writer.write_all(&u64_bytes(0)));
+
+        Ok(ArchiveWriter {
+            writer: writer,
+            offset: HEADER_SIZE,
+            entries: Vec::new(),
+        })
+    }
+
+    /// Adds an entry. Deflated entries that don't end up smaller are stored as they are instead.
+    pub fn add(&mut self, path: &Path, data: &[u8], compression: Compression) -> Result<(), ArchiveError> {
+        let name = archive_path(path);
+        if name.len() > u16::max_value() as usize {
+            return Err(ArchiveError::Format(format!("Path is too long: {}", name)));
+        }
+        if self.entries.iter().any(|&(ref p, _)| *p == name) {
+            return Err(ArchiveError::Format(format!("Duplicate path: {}", name)));
+        }
+
+        let compressed = match compression {
+            Compression::Stored => None,
+            Compression::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), FlateCompression::Default);
+                try!(encoder.write_all(data));
+                let compressed = try!(encoder.finish());
+                if compressed.len() < data.len() { Some(compressed) } else { None }
+            },
+        };
+        let (compression, stored) = match compressed {
+            Some(ref compressed) => (Compression::Deflate, &compressed[..]),
+            None => (Compression::Stored, data),
+        };
+
+        try!(self.writer.write_all(stored));
+        self.entries.push((name, ArchiveEntry {
+            compression: compression,
+            offset: self.offset,
+            stored_size: stored.len() as u64,
+            size: data.len() as u64,
+            crc32: crc32(data),
+        }));
+        self.offset += stored.len() as u64;
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W, ArchiveError> {
+        let mut index = Vec::new();
+        index.extend_from_slice(&u32_bytes(self.entries.len() as u32));
+        for &(ref path, ref entry) in &self.entries {
+            index.extend_from_slice(&u16_bytes(path.len() as u16));
+            index.extend_from_slice(path.as_bytes());
+            index.push(entry.compression.raw());
+            index.extend_from_slice(&u64_bytes(entry.offset));
+            index.extend_from_slice(&u64_bytes(entry.stored_size));
+            index.extend_from_slice(&u64_bytes(entry.size));
+            index.extend_from_slice(&u32_bytes(entry.crc32));
+        }
+        try!(self.writer.write_all(&index));
+
+        try!(self.writer.seek(SeekFrom::Start(8)));
+        try!(self.writer.write_all(&u64_bytes(self.offset)));
+        try!(self.writer.seek(SeekFrom::End(0)));
+        try!(self.writer.flush());
+        Ok(self.writer)
+    }
+}
+
+/// Paths in archives always use / separators, so archives work the same on every platform.
+fn archive_path(path: &Path) -> String {
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().into_owned()),
+            Component::ParentDir => { parts.pop(); },
+            _ => (),
+        }
+    }
+    parts.join("/")
+}
+
+/// Reads assets from loose files in a directory and from archives. Loose files take priority, so
+/// assets in archives can be replaced by putting a file at the same path in the directory. Later
+/// archives take priority over earlier ones, so patches can be shipped as an extra archive.
+#[derive(Clone)]
+pub struct AssetSource {
+    root: PathBuf,
+    archives: Vec<Archive>,
+}
+
+impl AssetSource {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        AssetSource {
+            root: root.into(),
+            archives: Vec::new(),
+        }
+    }
+
+    /// Finds a directory with the name next to the executable, or in a directory above it. This
+    /// finds assets next to a shipped game as well as in its crate while developing it, no matter
+    /// which directory it's run from.
+    pub fn find_root(name: &str) -> Option<PathBuf> {
+        let executable = match env::current_exe() {
+            Ok(executable) => executable,
+            Err(_) => return None,
+        };
+
+        let mut directory = executable.parent();
+        while let Some(current) = directory {
+            let root = current.join(name);
+            if root.is_dir() {
+                return Some(root);
+            }
+            directory = current.parent();
+        }
+        None
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn add_archive(&mut self, archive: Archive) {
+        self.archives.push(archive);
+    }
+
+    /// Returns the path of the loose file for an asset, if there is one.
+    pub fn loose_path(&self, path: &Path) -> Option<PathBuf> {
+        let loose = self.root.join(path);
+        if loose.is_file() {
+            Some(loose)
+        } else {
+            None
+        }
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.loose_path(path).is_some() || self.archives.iter().any(|a| a.contains(path))
+    }
+
+    /// Reads an asset, relative to the root.
+    pub fn read(&self, path: &Path) -> Result<Vec<u8>, String> {
+        if let Some(loose) = self.loose_path(path) {
+            let mut data = Vec::new();
+            try!(File::open(&loose)
+                .and_then(|mut file| file.read_to_end(&mut data))
+                .map_err(|e| format!("Could not read {}: {}", loose.display(), e)));
+            return Ok(data);
+        }
+
+        for archive in self.archives.iter().rev() {
+            if let Some(data) = try!(archive.read(path).map_err(|e| format!("{}: {}", path.display(), e))) {
+                return Ok(data);
+            }
+        }
+
+        Err(format!("Could not find {}", path.display()))
+    }
+}
+
+/// The CRC-32 used by zip and PNG.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (i, value) in table.iter_mut().enumerate() {
+        let mut crc = i as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { 0xEDB88320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+        *value = crc;
+    }
+
+    !data.iter().fold(!0, |crc, &byte| table[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+fn read_u16(data: &[u8]) -> u16 {
+    data[0] as u16 | (data[1] as u16) << 8
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    data[0] as u32 | (data[1] as u32) << 8 | (data[2] as u32) << 16 | (data[3] as u32) << 24
+}
+
+fn read_u64(data: &[u8]) -> u64 {
+    read_u32(&data[0..4]) as u64 | (read_u32(&data[4..8]) as u64) << 32
+}
+
+fn u16_bytes(value: u16) -> [u8; 2] {
+    [value as u8, (value >> 8) as u8]
+}
+
+fn u32_bytes(value: u32) -> [u8; 4] {
+    [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]
+}
+
+fn u64_bytes(value: u64) -> [u8; 8] {
+    let low = u32_bytes(value as u32);
+    let high = u32_bytes((value >> 32) as u32);
+    [low[0], low[1], low[2], low[3], high[0], high[1], high[2], high[3]]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{Read, Write};
+    use std::path::{Path, PathBuf};
+    use std::process;
+    use super::{Archive, ArchiveWriter, ArchiveError, AssetSource, Compression, crc32};
+
+    /// Creates an empty directory only used by one test of this run.
+    fn test_dir(name: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!("tungsten-archive-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[test]
+    fn loose_files_override_archive_entries() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+
+        let root = test_dir("override");
+        let repeated = vec!(7; 1000);
+        {
+            let file = File::create(root.join("assets.pack")).unwrap();
+            let mut writer = ArchiveWriter::new(file).unwrap();
+            writer.add(Path::new("levels/one.txt"), b"Packed", Compression::Deflate).unwrap();
+            writer.add(Path::new("repeated.bin"), &repeated, Compression::Deflate).unwrap();
+            writer.finish().unwrap();
+        }
+
+        // Compression is only used when it helps
+        let archive = Archive::open(root.join("assets.pack")).unwrap();
+        assert_eq!(archive.entry(Path::new("levels/one.txt")).unwrap().compression, Compression::Stored);
+        assert_eq!(archive.entry(Path::new("repeated.bin")).unwrap().compression, Compression::Deflate);
+        assert_eq!(archive.read(Path::new("repeated.bin")).unwrap().unwrap(), repeated);
+
+        let mut source = AssetSource::new(root.join("loose"));
+        source.add_archive(archive);
+        assert_eq!(source.read(Path::new("levels/one.txt")).unwrap(), b"Packed");
+
+        fs::create_dir_all(root.join("loose/levels")).unwrap();
+        File::create(root.join("loose/levels/one.txt")).unwrap().write_all(b"Loose").unwrap();
+        assert_eq!(source.read(Path::new("levels/one.txt")).unwrap(), b"Loose");
+        assert!(source.read(Path::new("missing.txt")).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn rejects_entries_outside_of_the_archive() {
+        let root = test_dir("damaged");
+        let path = root.join("damaged.pack");
+        {
+            let file = File::create(&path).unwrap();
+            let mut writer = ArchiveWriter::new(file).unwrap();
+            writer.add(Path::new("data.bin"), &[1, 2, 3, 4], Compression::Stored).unwrap();
+            writer.finish().unwrap();
+        }
+        assert!(Archive::open(&path).is_ok());
+
+        // Claim the entry is far bigger than the file, its stored size is followed by its size and
+        // checksum at the end of the index
+        let mut data = Vec::new();
+        File::open(&path).unwrap().read_to_end(&mut data).unwrap();
+        let stored_size = data.len() - 20;
+        data[stored_size + 4] = 0xff;
+        File::create(&path).unwrap().write_all(&data).unwrap();
+
+        match Archive::open(&path) {
+            Err(ArchiveError::Format(_)) => (),
+            _ => panic!("Damaged archive was opened"),
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
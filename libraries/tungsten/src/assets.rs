@@ -0,0 +1,673 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak, Mutex};
+use std::sync::mpsc::{self, Sender, Receiver};
+use std::thread;
+use {EventDispatcher, EventSource, AssetSource};
+
+/// How many results are kept for events before the oldest are only counted in the progress, so
+/// a manager that was never added as an event source doesn't keep them forever.
+const MAX_PENDING_RESULTS: usize = 1024;
+
+/// Turns the data of an asset file into an asset. Loaders are run on the loading thread, so this
+/// is where slow work like decoding should happen.
+pub trait AssetLoader: Send + 'static {
+    type Asset: Send + Sync + 'static;
+
+    fn load(&mut self, path: &Path, data: Vec<u8>) -> Result<Self::Asset, String>;
+}
+
+impl<A, F> AssetLoader for F where
+    A: Send + Sync + 'static, F: FnMut(&Path, Vec<u8>) -> Result<A, String> + Send + 'static {
+    type Asset = A;
+
+    fn load(&mut self, path: &Path, data: Vec<u8>) -> Result<A, String> {
+        self(path, data)
+    }
+}
+
+/// Loads data files as they are.
+pub struct DataLoader;
+
+impl AssetLoader for DataLoader {
+    type Asset = Vec<u8>;
+
+    fn load(&mut self, _path: &Path, data: Vec<u8>) -> Result<Vec<u8>, String> {
+        Ok(data)
+    }
+}
+
+/// Loads text files, failing if they aren't valid UTF-8.
+pub struct TextLoader;
+
+impl AssetLoader for TextLoader {
+    type Asset = String;
+
+    fn load(&mut self, path: &Path, data: Vec<u8>) -> Result<String, String> {
+        String::from_utf8(data).map_err(|_| format!("{} is not valid UTF-8", path.display()))
+    }
+}
+
+enum AssetState<T> {
+    Loading,
+    Loaded(Arc<T>),
+    Failed(String),
+}
+
+struct Slot<T> {
+    kind: String,
+    path: PathBuf,
+    state: Mutex<AssetState<T>>,
+}
+
+/// A reference to an asset that may still be loading. The asset is kept in memory for as long as
+/// any handle to it exists, loading it again while that's the case gives back the same asset.
+pub struct Handle<T> {
+    slot: Arc<Slot<T>>,
+}
+
+impl<T> Handle<T> {
+    fn new(kind: &str, path: &Path, state: AssetState<T>) -> Self {
+        Handle {
+            slot: Arc::new(Slot {
+                kind: kind.to_string(),
+                path: path.to_path_buf(),
+                state: Mutex::new(state),
+            }),
+        }
+    }
+
+    pub fn kind(&self) -> &str {
+        &self.slot.kind
+    }
+
+    /// Returns the path of the asset in the asset source.
+    pub fn path(&self) -> &Path {
+        &self.slot.path
+    }
+
+    /// Returns the asset if it's done loading.
+    pub fn get(&self) -> Option<Arc<T>> {
+        match *self.slot.state.lock().unwrap() {
+            AssetState::Loaded(ref asset) => Some(asset.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn is_loading(&self) -> bool {
+        match *self.slot.state.lock().unwrap() {
+            AssetState::Loading => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.get().is_some()
+    }
+
+    /// Returns why the asset couldn't be loaded, if it couldn't.
+    pub fn error(&self) -> Option<String> {
+        match *self.slot.state.lock().unwrap() {
+            AssetState::Failed(ref error) => Some(error.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Handle {
+            slot: self.slot.clone(),
+        }
+    }
+}
+
+/// How far along loading is, counted from the last time everything was done loading.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AssetProgress {
+    pub loaded: usize,
+    pub failed: usize,
+    pub total: usize,
+}
+
+impl AssetProgress {
+    pub fn is_done(&self) -> bool {
+        self.loaded + self.failed == self.total
+    }
+
+    /// Returns how much is done from 0.0 to 1.0, counting failed assets as done.
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            (self.loaded + self.failed) as f32 / self.total as f32
+        }
+    }
+}
+
+/// Raised when an asset is done loading, or failed to load.
+pub struct AssetLoadedEvent {
+    pub kind: String,
+    pub path: PathBuf,
+    pub error: Option<String>,
+}
+
+/// Raised after `AssetLoadedEvent`s, with the progress including those assets.
+pub struct AssetProgressEvent {
+    pub progress: AssetProgress,
+}
+
+struct LoadResult {
+    kind: String,
+    path: PathBuf,
+    error: Option<String>,
+}
+
+trait LoadJob: Send {
+    fn run(self: Box<Self>, source: &AssetSource) -> LoadResult;
+}
+
+type SharedLoader<T> = Arc<Mutex<Box<AssetLoader<Asset=T>>>>;
+
+struct TypedLoadJob<T> {
+    slot: Arc<Slot<T>>,
+    loader: SharedLoader<T>,
+}
+
+impl<T: Send + Sync + 'static> LoadJob for TypedLoadJob<T> {
+    fn run(self: Box<Self>, source: &AssetSource) -> LoadResult {
+        let path = &self.slot.path;
+        let result = source.read(path)
+            .and_then(|data| self.loader.lock().unwrap().load(path, data));
+
+        let error = match result {
+            Ok(asset) => {
+                *self.slot.state.lock().unwrap() = AssetState::Loaded(Arc::new(asset));
+                None
+            },
+            Err(error) => {
+                *self.slot.state.lock().unwrap() = AssetState::Failed(error.clone());
+                Some(error)
+            },
+        };
+
+        LoadResult {
+            kind: self.slot.kind.clone(),
+            path: path.clone(),
+            error: error,
+        }
+    }
+}
+
+/// Loads an asset without the caller knowing its type, so manifests can load any kind.
+type UntypedLoad = fn(&AssetManager, &str, &Path) -> Box<Any>;
+
+fn load_untyped<T: Send + Sync + 'static>(manager: &AssetManager, kind: &str, path: &Path) -> Box<Any> {
+    Box::new(manager.load_path::<T>(kind, path))
+}
+
+trait KindLoader: Send {
+    fn as_any(&self) -> &Any;
+    fn untyped(&self) -> UntypedLoad;
+}
+
+struct TypedKindLoader<T> {
+    loader: SharedLoader<T>,
+}
+
+impl<T: Send + Sync + 'static> KindLoader for TypedKindLoader<T> {
+    fn as_any(&self) -> &Any {
+        self
+    }
+
+    fn untyped(&self) -> UntypedLoad {
+        load_untyped::<T>
+    }
+}
+
+trait CachedSlot: Send {
+    fn as_any(&self) -> &Any;
+    fn is_alive(&self) -> bool;
+}
+
+impl<T: Send + Sync + 'static> CachedSlot for Weak<Slot<T>> {
+    fn as_any(&self) -> &Any {
+        self
+    }
+
+    fn is_alive(&self) -> bool {
+        self.upgrade().is_some()
+    }
+}
+
+struct SharedAssets {
+    source: AssetSource,
+    loaders: HashMap<String, Box<KindLoader>>,
+    cache: HashMap<(String, PathBuf), Box<CachedSlot>>,
+    job_send: Sender<Box<LoadJob>>,
+    result_recv: Receiver<LoadResult>,
+    /// Results that haven't been raised as events yet, including ones that didn't need the
+    /// loading thread, such as loads of unknown kinds.
+    immediate: Vec<LoadResult>,
+    progress: AssetProgress,
+}
+
+impl SharedAssets {
+    fn start_load(&mut self) {
+        if self.progress.is_done() {
+            self.progress = AssetProgress::default();
+        }
+        self.progress.total += 1;
+    }
+
+    fn finish(&mut self, result: LoadResult) {
+        self.immediate.push(result);
+        if self.immediate.len() > MAX_PENDING_RESULTS {
+            let excess = self.immediate.len() - MAX_PENDING_RESULTS;
+            for result in self.immediate.drain(..excess) {
+                if result.error.is_some() {
+                    self.progress.failed += 1;
+                } else {
+                    self.progress.loaded += 1;
+                }
+            }
+        }
+    }
+
+    /// Forgets about assets that aren't in use anymore.
+    fn prune_cache(&mut self) {
+        self.cache.retain(|_, slot| slot.is_alive());
+    }
+
+    fn fail<T>(&mut self, kind: &str, path: &Path, error: String) -> Handle<T> {
+        self.start_load();
+        self.finish(LoadResult {
+            kind: kind.to_string(),
+            path: path.to_path_buf(),
+            error: Some(error.clone()),
+        });
+        Handle::new(kind, path, AssetState::Failed(error))
+    }
+}
+
+/// Loads assets on a background thread, by the kind of asset and a path in the asset source.
+/// Loaders for each kind are added with `add_loader`, for example a "texture" loader from the
+/// frontend. Failures don't panic, they're kept in the handle and raised as events.
+///
+/// Clones share the same assets, so keep one in the model and add one to the framework with
+/// `Framework::add_event_source` to get `AssetLoadedEvent`s and `AssetProgressEvent`s.
+#[derive(Clone)]
+pub struct AssetManager {
+    shared: Arc<Mutex<SharedAssets>>,
+}
+
+impl AssetManager {
+    /// Creates a manager that loads loose files from the root directory.
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        AssetManager::with_source(AssetSource::new(root))
+    }
+
+    /// Creates a manager that loads from a source, which can include archives.
+    pub fn with_source(source: AssetSource) -> Self {
+        let thread_source = source.clone();
+        let (job_send, job_recv) = mpsc::channel::<Box<LoadJob>>();
+        let (result_send, result_recv) = mpsc::channel();
+
+        // The thread stops by itself when the last clone of the manager is dropped
+        thread::spawn(move || {
+            for job in job_recv.iter() {
+                if result_send.send(job.run(&thread_source)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        AssetManager {
+            shared: Arc::new(Mutex::new(SharedAssets {
+                source: source,
+                loaders: HashMap::new(),
+                cache: HashMap::new(),
+                job_send: job_send,
+                result_recv: result_recv,
+                immediate: Vec::new(),
+                progress: AssetProgress::default(),
+            })),
+        }
+    }
+
+    /// Sets the loader used for a kind of asset, replacing any previous loader for it.
+    pub fn add_loader<L: AssetLoader>(&self, kind: &str, loader: L) {
+        let loader: Box<AssetLoader<Asset=L::Asset>> = Box::new(loader);
+        let kind_loader = TypedKindLoader {
+            loader: Arc::new(Mutex::new(loader)),
+        };
+        self.shared.lock().unwrap().loaders.insert(kind.to_string(), Box::new(kind_loader));
+    }
+
+    /// Starts loading an asset, or returns the existing asset if it's still in use. If there's no
+    /// loader for the kind, or its loader loads a different type, the handle has failed.
+    pub fn load<T: Send + Sync + 'static>(&self, kind: &str, path: &str) -> Handle<T> {
+        self.load_path(kind, Path::new(path))
+    }
+
+    fn load_path<T: Send + Sync + 'static>(&self, kind: &str, path: &Path) -> Handle<T> {
+        let mut shared = self.shared.lock().unwrap();
+
+        let key = (kind.to_string(), path.to_path_buf());
+        let cached = shared.cache.get(&key)
+            .and_then(|c| c.as_any().downcast_ref::<Weak<Slot<T>>>())
+            .and_then(|w| w.upgrade());
+        if let Some(slot) = cached {
+            return Handle {
+                slot: slot,
+            };
+        }
+
+        let loader = match shared.loaders.get(kind) {
+            Some(loader) => loader.as_any().downcast_ref::<TypedKindLoader<T>>().map(|l| l.loader.clone()),
+            None => return shared.fail(kind, path, format!("No loader for {} assets", kind)),
+        };
+        let loader = match loader {
+            Some(loader) => loader,
+            None => return shared.fail(kind, path, format!("The {} loader loads a different type", kind)),
+        };
+
+        let handle = Handle::new(kind, path, AssetState::Loading);
+        shared.prune_cache();
+        shared.cache.insert(key, Box::new(Arc::downgrade(&handle.slot)));
+        shared.start_load();
+
+        let job = TypedLoadJob {
+            slot: handle.slot.clone(),
+            loader: loader,
+        };
+        if shared.job_send.send(Box::new(job)).is_err() {
+            // Only happens if the loading thread panicked in a loader
+            let error = "The loading thread has stopped".to_string();
+            *handle.slot.state.lock().unwrap() = AssetState::Failed(error.clone());
+            shared.finish(LoadResult {
+                kind: kind.to_string(),
+                path: path.to_path_buf(),
+                error: Some(error),
+            });
+        }
+
+        handle
+    }
+
+    /// Loads a manifest from the asset source, so it can be packed with the assets it lists.
+    pub fn load_manifest(&self, path: &str) -> Result<AssetManifest, String> {
+        let data = try!(self.shared.lock().unwrap().source.read(Path::new(path)));
+        let text = try!(String::from_utf8(data).map_err(|_| format!("{} is not valid UTF-8", path)));
+        AssetManifest::parse(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// Starts loading all assets of a level in a manifest. The assets stay loaded for as long as
+    /// the returned `LevelAssets` is kept around, `load` gives back the same assets in the
+    /// meantime.
+    pub fn load_level(&self, manifest: &AssetManifest, level: &str) -> Result<LevelAssets, String> {
+        let entries = try!(manifest.level(level).ok_or_else(|| format!("No level named {}", level)));
+
+        // A new level is a good moment to forget about assets from the previous one
+        self.shared.lock().unwrap().prune_cache();
+
+        let mut handles = Vec::new();
+        for entry in entries {
+            // Unknown kinds still go through load, so they're reported like other failures
+            let load = {
+                let shared = self.shared.lock().unwrap();
+                shared.loaders.get(&entry.kind).map(|l| l.untyped()).unwrap_or(load_untyped::<()>)
+            };
+            handles.push(load(self, &entry.kind, Path::new(&entry.path)));
+        }
+
+        Ok(LevelAssets {
+            handles: handles,
+        })
+    }
+
+    pub fn progress(&self) -> AssetProgress {
+        let mut shared = self.shared.lock().unwrap();
+        while let Ok(result) = shared.result_recv.try_recv() {
+            shared.finish(result);
+        }
+
+        // Results that haven't been raised as events yet are still counted here
+        let mut progress = shared.progress;
+        for result in &shared.immediate {
+            if result.error.is_some() {
+                progress.failed += 1;
+            } else {
+                progress.loaded += 1;
+            }
+        }
+        progress
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.progress().is_done()
+    }
+
+    fn take_results(&self) -> (Vec<LoadResult>, AssetProgress) {
+        let mut shared = self.shared.lock().unwrap();
+        while let Ok(result) = shared.result_recv.try_recv() {
+            shared.finish(result);
+        }
+        let results = ::std::mem::replace(&mut shared.immediate, Vec::new());
+        shared.prune_cache();
+
+        for result in &results {
+            if result.error.is_some() {
+                shared.progress.failed += 1;
+            } else {
+                shared.progress.loaded += 1;
+            }
+        }
+        (results, shared.progress)
+    }
+}
+
+impl<M: 'static> EventSource<M> for AssetManager {
+    fn process_events(&mut self, dispatcher: &mut EventDispatcher<M>, model: &mut M) {
+        // The lock is released before dispatching, so handlers can load more assets
+        let (results, progress) = self.take_results();
+        if results.is_empty() {
+            return;
+        }
+
+        for result in results {
+            dispatcher.dispatch(model, AssetLoadedEvent {
+                kind: result.kind,
+                path: result.path,
+                error: result.error,
+            });
+        }
+        dispatcher.dispatch(model, AssetProgressEvent {
+            progress: progress,
+        });
+    }
+}
+
+/// Keeps the assets of a level loaded until dropped.
+pub struct LevelAssets {
+    handles: Vec<Box<Any>>,
+}
+
+impl LevelAssets {
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handles.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub kind: String,
+    pub path: String,
+}
+
+/// Lists the assets of each level. Manifests are text files with a `[level]` line starting each
+/// level, followed by a line per asset with its kind and path:
+///
+/// ```text
+/// # Comments start with a hash
+/// [menu]
+/// texture menu/background.png
+/// sound menu/music.ogg
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AssetManifest {
+    levels: HashMap<String, Vec<ManifestEntry>>,
+}
+
+impl AssetManifest {
+    pub fn new() -> Self {
+        AssetManifest::default()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut manifest = AssetManifest::new();
+        let mut level = None;
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                if !line.ends_with(']') || line.len() < 3 {
+                    return Err(format!("Line {}: Invalid level name {}", number + 1, line));
+                }
+                let name = line[1..line.len() - 1].trim().to_string();
+                manifest.levels.entry(name.clone()).or_insert_with(Vec::new);
+                level = Some(name);
+                continue;
+            }
+
+            let level = try!(level.as_ref()
+                .ok_or_else(|| format!("Line {}: Asset before the first level", number + 1)));
+            let mut parts = line.splitn(2, char::is_whitespace);
+            let kind = parts.next().unwrap();
+            let path = try!(parts.next().map(|p| p.trim())
+                .ok_or_else(|| format!("Line {}: Asset {} has no path", number + 1, kind)));
+
+            manifest.add(level, kind, path);
+        }
+
+        Ok(manifest)
+    }
+
+    pub fn add(&mut self, level: &str, kind: &str, path: &str) {
+        self.levels.entry(level.to_string()).or_insert_with(Vec::new).push(ManifestEntry {
+            kind: kind.to_string(),
+            path: path.to_string(),
+        });
+    }
+
+    pub fn level(&self, level: &str) -> Option<&[ManifestEntry]> {
+        self.levels.get(level).map(|e| &e[..])
+    }
+
+    pub fn levels(&self) -> Vec<&str> {
+        self.levels.keys().map(|l| &l[..]).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::process;
+    use std::thread;
+    use std::time::Duration;
+    use super::{AssetManager, AssetManifest, ManifestEntry, TextLoader, MAX_PENDING_RESULTS};
+
+    /// Creates an empty directory only used by one test of this run.
+    fn test_dir(name: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!("tungsten-assets-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    fn wait_until_done(assets: &AssetManager) {
+        for _ in 0..5000 {
+            if assets.is_done() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        panic!("Assets didn't finish loading: {:?}", assets.progress());
+    }
+
+    #[test]
+    fn parses_manifest_levels() {
+        let manifest = AssetManifest::parse("
+            # The first level
+            [one]
+            texture bird.png
+            data levels/one map.json
+
+            [two]
+        ").unwrap();
+
+        assert_eq!(manifest.level("one").unwrap(), &[
+            ManifestEntry { kind: "texture".into(), path: "bird.png".into() },
+            ManifestEntry { kind: "data".into(), path: "levels/one map.json".into() },
+        ]);
+        assert!(manifest.level("two").unwrap().is_empty());
+        assert!(manifest.level("three").is_none());
+        assert!(AssetManifest::parse("texture bird.png").is_err());
+    }
+
+    #[test]
+    fn loads_shared_handles_in_the_background() {
+        let root = test_dir("background");
+        File::create(root.join("hello.txt")).unwrap().write_all(b"Hello").unwrap();
+
+        let assets = AssetManager::new(&root);
+        assets.add_loader("text", TextLoader);
+        let hello = assets.load::<String>("text", "hello.txt");
+        let missing = assets.load::<String>("text", "missing.txt");
+        let unknown = assets.load::<String>("sound", "hello.txt");
+
+        wait_until_done(&assets);
+
+        // Loading it again while it's in use gives back the same asset
+        assert_eq!(*hello.get().unwrap(), "Hello");
+        assert_eq!(*assets.load::<String>("text", "hello.txt").get().unwrap(), "Hello");
+        assert!(missing.error().is_some());
+        assert!(unknown.error().is_some());
+
+        let progress = assets.progress();
+        assert_eq!((progress.loaded, progress.failed, progress.total), (1, 2, 3));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn forgets_unused_assets_and_old_results() {
+        let assets = AssetManager::new(test_dir("forget"));
+        assets.add_loader("text", TextLoader);
+        drop(assets.load::<String>("text", "one.txt"));
+        wait_until_done(&assets);
+        let _two = assets.load::<String>("text", "two.txt");
+        assert_eq!(assets.shared.lock().unwrap().cache.len(), 1);
+
+        // Without an event source the results are only counted once there are too many
+        for _ in 0..MAX_PENDING_RESULTS + 10 {
+            assets.load::<String>("sound", "three.wav");
+        }
+        wait_until_done(&assets);
+        let progress = assets.progress();
+        assert_eq!(assets.shared.lock().unwrap().immediate.len(), MAX_PENDING_RESULTS);
+        assert_eq!((progress.failed, progress.total), (MAX_PENDING_RESULTS + 12, MAX_PENDING_RESULTS + 12));
+    }
+}
@@ -6,6 +6,12 @@ pub trait Frontend<M> {
     fn render(&mut self, model: &M);
 }
 
+/// Something other than the frontend that raises events, such as the `AssetManager`. Event
+/// sources are processed right after the frontend.
+pub trait EventSource<M> {
+    fn process_events(&mut self, dispatcher: &mut EventDispatcher<M>, model: &mut M);
+}
+
 pub struct UpdateEvent {
     pub delta: f32
 }
@@ -14,6 +20,7 @@ pub struct Framework<M, F> {
     model: M,
     frontend: F,
     dispatcher: EventDispatcher<M>,
+    sources: Vec<Box<EventSource<M>>>,
 }
 
 impl<M: 'static, F: Frontend<M>> Framework<M, F> {
@@ -22,9 +29,14 @@ impl<M: 'static, F: Frontend<M>> Framework<M, F> {
             model: model,
             frontend: frontend,
             dispatcher: dispatcher,
+            sources: Vec::new(),
         }
     }
 
+    pub fn add_event_source<S: EventSource<M> + 'static>(&mut self, source: S) {
+        self.sources.push(Box::new(source));
+    }
+
     pub fn run<RC: Fn(&M) -> bool>(mut self, run_condition: RC) {
         let mut last_update = PreciseTime::now();
 
@@ -44,6 +56,9 @@ impl<M: 'static, F: Frontend<M>> Framework<M, F> {
 
                 // Perform the actual update tick
                 self.frontend.process_events(&mut self.dispatcher, &mut self.model);
+                for source in &mut self.sources {
+                    source.process_events(&mut self.dispatcher, &mut self.model);
+                }
                 self.dispatcher.dispatch(&mut self.model, UpdateEvent { delta: delta });
                 self.frontend.render(&self.model);
             } else {
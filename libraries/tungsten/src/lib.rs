@@ -1,8 +1,13 @@
 extern crate dynamic;
+extern crate flate2;
 extern crate time;
 
+mod archive;
+mod assets;
 mod event_dispatcher;
 mod framework;
 
+pub use archive::{Archive, ArchiveWriter, ArchiveEntry, ArchiveError, AssetSource, Compression};
+pub use assets::{AssetManager, AssetLoader, DataLoader, TextLoader, Handle, AssetProgress, AssetLoadedEvent, AssetProgressEvent, AssetManifest, ManifestEntry, LevelAssets};
 pub use event_dispatcher::{EventDispatcher, EventHandler};
-pub use framework::{Framework, Frontend, UpdateEvent};
+pub use framework::{Framework, Frontend, EventSource, UpdateEvent};